@@ -24,15 +24,18 @@ use crossterm::{
 };
 
 use kittui_racer::ui::{
+    config::{self, ClientConfig},
     draw::draw_ui_from_layout,
     input_handler,
+    replay::Replay,
     types::{App, UiMessage},
     websocket_handler,
 };
 
 use std::{
+    env,
     error::Error,
-    io,
+    fs, io,
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -43,6 +46,46 @@ use tui::{
 };
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // The arguments are parsed before the terminal is taken over, so that errors can be printed
+    let config = match ClientConfig::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(message) if message == config::USAGE => {
+            println!("{message}");
+            return Ok(());
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(2);
+        }
+    };
+
+    let replay = match config.replay.as_deref() {
+        Some(replay_path) => match Replay::read(replay_path) {
+            Ok(replay) => Some(replay),
+            Err(message) => {
+                eprintln!("{message}");
+                std::process::exit(2);
+            }
+        },
+        None => None,
+    };
+
+    let log_file = match config.log_file.as_ref() {
+        Some(log_file_path) => Some(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_file_path)
+                .map_err(|error| {
+                    format!(
+                        "Could not open log file {}: {error}",
+                        log_file_path.display()
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -57,25 +100,50 @@ fn main() -> Result<(), Box<dyn Error>> {
     let (sender, receiver) = tokio::sync::mpsc::channel::<UiMessage>(32);
     let app = Arc::new(Mutex::new(App::new(sender)));
 
+    if let Some(log_file) = log_file {
+        app.lock().unwrap().set_log_file(log_file);
+    }
+    app.lock().unwrap().set_account(config.account_settings());
+    app.lock()
+        .unwrap()
+        .set_replay_directory(config.replay_directory.clone());
+    app.lock()
+        .unwrap()
+        .set_ghost_directory(config.ghost_directory.clone());
+
     let app_clone = app.clone();
 
-    // Handle the websocket events in a separate thread
-    std::thread::spawn(move || {
-        // A single threaded runtime is sufficient because there will not be much activity
-        // with regards to websocket on the client side.
-        let single_threaded_runtime = tokio::runtime::Builder::new_current_thread()
-            .enable_io()
-            .build()
-            .unwrap();
-
-        // The created runtime is run on the current thread.
-        // The current thread runtime does not spawn any background threads, so tokio::spawn()
-        // on the single threaded runtime does not work. It comes into effect only when block_on()
-        // is called.
-        //
-        // https://tokio.rs/tokio/topics/bridging
-        single_threaded_runtime.block_on(websocket_handler::event_handler(app_clone, receiver))
-    });
+    // In practice and replay modes the server is never contacted, the receiver is only kept
+    // alive so that the messages sent by the app do not fail
+    let _unused_receiver = if let Some(replay) = replay {
+        app.lock().unwrap().start_replay(replay);
+        Some(receiver)
+    } else if config.practice {
+        app.lock().unwrap().start_practice();
+        Some(receiver)
+    } else {
+        let url = config.connection_url();
+        // Handle the websocket events in a separate thread
+        std::thread::spawn(move || {
+            // A single threaded runtime is sufficient because there will not be much activity
+            // with regards to websocket on the client side.
+            let single_threaded_runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .enable_time()
+                .build()
+                .unwrap();
+
+            // The created runtime is run on the current thread.
+            // The current thread runtime does not spawn any background threads, so tokio::spawn()
+            // on the single threaded runtime does not work. It comes into effect only when block_on()
+            // is called.
+            //
+            // https://tokio.rs/tokio/topics/bridging
+            single_threaded_runtime
+                .block_on(websocket_handler::event_handler(app_clone, receiver, url))
+        });
+        None
+    };
 
     let res = run_app(&mut terminal, app);
 
@@ -134,7 +202,9 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: Arc<Mutex<App>>) -> io::
         if crossterm::event::poll(timeout).unwrap() {
             // If `poll()` returns Ok(false), this `read()` call is non blocking
             if let crossterm::event::Event::Key(key_event) = crossterm::event::read()? {
-                if let KeyCode::Esc = key_event.code {
+                // Esc leaves the text field rather than the app while some text is being entered
+                let is_entering_text = app.lock().unwrap().is_entering_text();
+                if key_event.code == KeyCode::Esc && !is_entering_text {
                     return Ok(());
                 } else {
                     input_handler::handle_input(app.clone(), key_event.code);
@@ -144,12 +214,24 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: Arc<Mutex<App>>) -> io::
 
         // Update the timeout only if it is fully exhausted
         if last_tick.elapsed() >= tick_rate {
+            app.lock().unwrap().on_tick();
             last_tick = std::time::Instant::now();
         }
     }
 }
 
 fn ui<B: Backend>(frame: &mut Frame<B>, app: Arc<Mutex<App>>) {
-    let layouts = kittui_racer::ui::layout_divider::divide_frame(frame.size());
+    // One progress bar for every racer, but only while a game is being played
+    let progress_bar_count = {
+        let app = app.lock().unwrap();
+        match app.state.game.as_ref() {
+            Some(game_data) if app.current_tab == kittui_racer::ui::types::Tab::Game => {
+                game_data.racers.len()
+            }
+            _ => 0,
+        }
+    };
+
+    let layouts = kittui_racer::ui::layout_divider::divide_frame(frame.size(), progress_bar_count);
     draw_ui_from_layout(app, layouts, frame);
 }
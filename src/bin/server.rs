@@ -3,35 +3,85 @@ use std::sync::{
     Arc,
 };
 
-use futures_util::StreamExt;
+use futures_util::{SinkExt, StreamExt};
 use tokio::sync::mpsc::{self};
 
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use kittui_racer::{
+    errors::{Error, ProtocolError},
     models,
-    server_utils::{self, fast_storage},
+    server_utils::{self, fast_storage, prompt_corpus, storage},
 };
 
 /// Our global unique user id counter.
 static NEXT_USER_ID: AtomicUsize = AtomicUsize::new(1);
 
-use std::env;
+/// Seconds to wait for the `Hello` message, after which the user is treated as a guest
+const HELLO_TIMEOUT: u64 = 3;
+
+use std::{collections::HashMap, env, time::Duration};
 
 use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{tungstenite::protocol::Message, WebSocketStream};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{Request, Response},
+        protocol::{frame::coding::CloseCode, CloseFrame, Message},
+    },
+    WebSocketStream,
+};
 
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
 
-    // Keep track of all connected users, key is usize,
-    // value is a websocket sender.
-    let database = Arc::new(fast_storage::BlazinglyFastDb::default());
     let addr = env::args()
         .nth(1)
         .unwrap_or_else(|| "0.0.0.0:8080".to_string());
 
+    // Quotes from the optional prompts directory are used along with the bundled quotes
+    let mut prompts = prompt_corpus::PromptCorpus::bundled();
+    if let Some(prompts_directory) = env::args().nth(2) {
+        match prompt_corpus::load_directory(std::path::Path::new(&prompts_directory)) {
+            Ok(directory_prompts) => prompts.extend(directory_prompts),
+            Err(error) => eprintln!("Could not load prompts from {prompts_directory}: {error}"),
+        }
+    }
+    println!("Loaded {} prompts", prompts.len());
+
+    // Keep track of all connected users, key is usize,
+    // value is a websocket sender.
+    let config = server_utils::config::ServerConfig::from_env();
+
+    // The server does not start with corrupt data, so that nothing more is written to it
+    let storage: Box<dyn storage::Storage> = match &config.data_directory {
+        Some(data_directory) => match storage::FileStorage::open(data_directory.clone()) {
+            Ok(file_storage) => Box::new(file_storage),
+            Err(error) => {
+                eprintln!(
+                    "Could not read data from {}: {error}",
+                    data_directory.display()
+                );
+                std::process::exit(1);
+            }
+        },
+        None => {
+            println!("KITTUI_DATA_DIR is empty, nothing will be saved");
+            Box::<storage::MemoryStorage>::default()
+        }
+    };
+
+    let database = Arc::new(fast_storage::BlazinglyFastDb::new(prompts, storage, config));
+    match database.account_count().await {
+        Ok(account_count) => println!("Loaded {account_count} accounts"),
+        Err(error) => eprintln!("Could not count the accounts, {error}"),
+    }
+
+    tokio::spawn(server_utils::message_handlers::run_ranked_matchmaking(
+        database.clone(),
+    ));
+    server_utils::bots::spawn_bots(database.clone()).await;
+
     // Create the event loop and TCP listener we'll accept connections on.
     let try_socket = TcpListener::bind(&addr).await;
     let listener = try_socket.expect("Failed to bind");
@@ -41,12 +91,36 @@ async fn main() {
     while let Ok((stream, addr)) = listener.accept().await {
         let db = database.clone();
         tokio::spawn(async move {
-            let ws_stream = tokio_tungstenite::accept_async(stream)
-                .await
-                .expect("Error during the websocket handshake occurred");
+            // The client can ask for a name with the `name` query parameter of the url,
+            // and resume its previous session with the `resume` query parameter
+            let mut query_parameters = HashMap::new();
+            // The error type of the callback is defined by tungstenite
+            #[allow(clippy::result_large_err)]
+            let read_query_parameters = |request: &Request, response: Response| {
+                if let Some(query) = request.uri().query() {
+                    query_parameters
+                        .extend(url::form_urlencoded::parse(query.as_bytes()).into_owned());
+                }
+                Ok(response)
+            };
+
+            let ws_stream =
+                match tokio_tungstenite::accept_hdr_async(stream, read_query_parameters).await {
+                    Ok(ws_stream) => ws_stream,
+                    Err(error) => {
+                        eprintln!("Websocket handshake with {addr} failed, {error}");
+                        return;
+                    }
+                };
             println!("WebSocket connection established: {}", addr);
 
-            handle_new_websocket_connection(ws_stream, db).await;
+            handle_new_websocket_connection(
+                ws_stream,
+                db,
+                query_parameters.remove("name"),
+                query_parameters.remove("resume"),
+            )
+            .await;
         });
     }
 }
@@ -56,14 +130,11 @@ async fn main() {
 async fn handle_new_websocket_connection(
     ws: WebSocketStream<TcpStream>,
     db: Arc<fast_storage::BlazinglyFastDb>,
+    requested_name: Option<String>,
+    resume_token: Option<String>,
 ) {
-    // Use a counter to assign a new unique ID for this user.
-    let my_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed).to_string();
-
-    eprintln!("new chat user: {}", my_id);
-
     // Split the socket into a sender and receive of messages.
-    let (user_ws_tx, mut user_ws_rx) = ws.split();
+    let (mut user_ws_tx, mut user_ws_rx) = ws.split();
 
     // Use an unbounded channel to handle buffering and flushing of messages
     // to the websocket...
@@ -71,33 +142,200 @@ async fn handle_new_websocket_connection(
     // why is a receiver converted to stream?
     let receiver_stream = UnboundedReceiverStream::new(webs_receiver_channel);
 
+    // The first message introduces the user, clients which do not send it are treated as guests
+    // It also announces the version of the protocol, the clients without it predate the handshake
+    let mut pending_message = None;
+    let mut client_version = 1;
+    let mut client_features = vec![];
+    let hello =
+        match tokio::time::timeout(Duration::from_secs(HELLO_TIMEOUT), user_ws_rx.next()).await {
+            Ok(Some(Ok(Message::Text(text_message)))) => {
+                match models::parse_message::<models::WSClientMessage>(&text_message) {
+                    Ok(models::WSClientMessage::Hello {
+                        nickname,
+                        token,
+                        protocol_version,
+                        features,
+                    }) => {
+                        client_version = protocol_version;
+                        client_features = features;
+                        Some((nickname, token))
+                    }
+                    _ => {
+                        pending_message = Some(text_message);
+                        None
+                    }
+                }
+            }
+            Ok(Some(Ok(_))) | Err(_) => None,
+            // The user left before introducing themselves
+            Ok(Some(Err(_)) | None) => return,
+        };
+    // An older client cannot parse the `Error` message, so it is only sent a close frame
+    if client_version < models::MINIMUM_PROTOCOL_VERSION {
+        let error = Error::from(ProtocolError::UnsupportedVersion {
+            version: client_version,
+        });
+        eprintln!("Closing the connection, {error}");
+        let close_frame = CloseFrame {
+            code: CloseCode::Policy,
+            reason: error.to_string().into(),
+        };
+        user_ws_tx
+            .send(Message::Close(Some(close_frame)))
+            .await
+            .ok();
+        return;
+    }
+
+    let features = models::negotiate_features(client_version, &client_features);
+
     // Spawn a future to send message to the user, since the sending of messages are async
     // this strategy is used
 
     tokio::task::spawn(server_utils::message_handlers::bridge_user_websocket(
         receiver_stream,
         user_ws_tx,
+        features.clone(),
     ));
 
-    // Save the sender in our list of connected users.
-    let new_user = models::User {
-        id: my_id.to_string(),
-        status: models::UserStatus::Available,
-        display_name: server_utils::generate_name(),
+    let resumed_user = match resume_token {
+        Some(resume_token) => db.resume_session(&resume_token).await,
+        None => None,
     };
 
-    let successful_connection_message = models::WSServerMessage::SuccessfulConnection {
-        user: new_user.clone(),
+    let is_resumed = resumed_user.is_some();
+
+    // A registered player logs in with the token, or registers a nickname to get one
+    let mut account_token = None;
+    let mut account_error = None;
+    let known_user = match resumed_user {
+        Some(user) => {
+            eprintln!("Resuming the session of user_id: {}", user.id);
+            Some(user)
+        }
+        None => {
+            let account = match hello {
+                Some((_, Some(token))) => Some(
+                    db.login_account(&token)
+                        .await
+                        .map(|account| (account, token)),
+                ),
+                Some((Some(nickname), None)) => Some(db.register_account(&nickname).await),
+                _ => None,
+            };
+
+            match account {
+                Some(Ok((account, token))) => {
+                    eprintln!("{} logged in as user_id: {}", account.nickname, account.id);
+                    account_token = Some(token);
+                    let rating = match db.rating_of(&account.id).await {
+                        Ok(rating) => rating.map(|rating| rating.display_value()),
+                        Err(error) => {
+                            eprintln!("Could not read the rating of {}, {error}", account.id);
+                            None
+                        }
+                    };
+                    Some(models::User {
+                        id: account.id,
+                        status: models::UserStatus::Available,
+                        display_name: account.nickname,
+                        rating,
+                        bot: false,
+                    })
+                }
+                Some(Err(error)) => {
+                    account_error = Some(error);
+                    None
+                }
+                None => None,
+            }
+        }
     };
 
+    // A known user keeps the id, name and the game they were racing in
+    let (new_user, active_game_id) = match known_user {
+        Some(mut user) => {
+            let active_game_id = db.attach_user_to_game(&user.id, &webs_sender_channel).await;
+            user.status = if active_game_id.is_some() {
+                models::UserStatus::Busy
+            } else {
+                models::UserStatus::Available
+            };
+            (user, active_game_id)
+        }
+        None => {
+            // Guests are anonymous, they are forgotten once the session expires
+            // Use a counter to assign a new unique ID for this user.
+            let my_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed).to_string();
+            eprintln!("new chat user: {}", my_id);
+
+            // A random name is assigned if the requested name is not valid or is already used
+            let display_name = match requested_name
+                .as_deref()
+                .and_then(server_utils::sanitize_name)
+            {
+                Some(name) if !db.is_display_name_taken(&name).await => name,
+                _ => server_utils::generate_name(),
+            };
+
+            let new_user = models::User {
+                id: my_id,
+                status: models::UserStatus::Available,
+                display_name,
+                rating: None,
+                bot: false,
+            };
+            (new_user, None)
+        }
+    };
+    let my_id = new_user.id.clone();
+
+    // Save the sender in our list of connected users.
     let user_connection_details =
-        server_utils::fast_storage::UserConnection::new(new_user, webs_sender_channel);
+        server_utils::fast_storage::UserConnection::new(new_user, webs_sender_channel.clone());
+
+    let successful_connection_message = models::WSServerMessage::SuccessfulConnection {
+        user: user_connection_details.user().clone(),
+        resume_token: user_connection_details.resume_token().to_string(),
+        active_game_id: active_game_id.clone(),
+        account_token,
+        protocol_version: models::PROTOCOL_VERSION,
+        features,
+    };
 
     db.insert_new_user_connection(user_connection_details).await;
-    db.send_message_to_user(&my_id, successful_connection_message)
-        .await;
+    if let Err(error) = db
+        .send_message_to_user(&my_id, successful_connection_message)
+        .await
+    {
+        eprintln!("Could not greet user_id: {my_id}, {error}");
+    }
+
+    // A player who logs in from a new client is racing in a game which the client does not know of
+    if let (Some(game_id), false) = (active_game_id, is_resumed) {
+        db.send_game_state(&my_id, &game_id).await;
+    }
     db.boradcast_status().await;
 
+    // The user continues as a guest
+    if let Some(error) = account_error {
+        eprintln!("user_id: {my_id} could not log in, {error}");
+        db.send_message_to_user(&my_id, error.to_server_message())
+            .await
+            .ok();
+    }
+
+    // The first message was not a `Hello`, it is handled like any other message
+    if let Some(text_message) = pending_message {
+        server_utils::message_handlers::handle_client_messages(
+            &text_message,
+            Arc::clone(&db),
+            &my_id,
+        )
+        .await;
+    }
+
     // Handle the messages sent by the user
     while let Some(result) = user_ws_rx.next().await {
         match result {
@@ -122,6 +360,39 @@ async fn handle_new_websocket_connection(
 
     // user_ws_rx stream will keep processing as long as the user stays
     // connected. Once they disconnect, then...
-    db.delete_user_connection(&my_id.to_string()).await;
+    // If the session was resumed through another connection, the user must not be removed
+    if !db.is_current_connection(&my_id, &webs_sender_channel).await {
+        return;
+    }
+
+    db.withdraw_challenges_of_user(&my_id).await;
+    db.leave_race_lobby(&my_id).await;
+    db.leave_ranked_queue(&my_id).await;
+    db.stop_spectating(&my_id).await;
+
+    // The place in the game is kept for the grace period, after which the race is forfeited
+    if let Some((game_id, disconnected_at)) = db.disconnect_user_from_game(&my_id).await {
+        let db_clone = db.clone();
+        let user_id = my_id.clone();
+        tokio::spawn(server_utils::set_timeout(
+            db.config().disconnect_grace_period,
+            move || async move {
+                db_clone
+                    .forfeit_disconnected_user(&game_id, &user_id, disconnected_at)
+                    .await;
+            },
+        ));
+    }
+
+    // The user can come back with the resume token till the timeout
+    if let Some(resume_token) = db.suspend_user_connection(&my_id).await {
+        let db_clone = db.clone();
+        tokio::spawn(server_utils::set_timeout(
+            db.config().resume_timeout,
+            move || async move {
+                db_clone.remove_suspended_session(&resume_token).await;
+            },
+        ));
+    }
     db.boradcast_status().await;
 }
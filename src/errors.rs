@@ -1,3 +1,333 @@
-pub enum NetworkError {
-    ConnectFailed,
+use std::fmt;
+
+use crate::models;
+
+/// Everything that can go wrong while handling a connection, on the server or the client
+/// The errors are grouped by where they arise
+#[derive(Debug)]
+pub enum Error {
+    Protocol(ProtocolError),
+    Storage(StorageError),
+    Game(GameError),
+    Account(AccountError),
+    Transport(TransportError),
+    Chat(ChatError),
+}
+
+/// The message could not be understood or written
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The message is not valid json, or is not a known message
+    MalformedMessage(String),
+    /// The message could not be converted to json
+    Serialization(String),
+    /// The message is of a type added in a later version of the protocol
+    UnsupportedMessage,
+    /// The peer speaks a version of the protocol older than `MINIMUM_PROTOCOL_VERSION`
+    UnsupportedVersion { version: u32 },
+}
+
+/// Something that was asked for is not in the storage
+#[derive(Debug)]
+pub enum StorageError {
+    UserNotFound {
+        user_id: String,
+    },
+    GameNotFound {
+        game_id: String,
+    },
+    /// The data could not be read from or written to the disk
+    Persistence(String),
+}
+
+/// The request is not allowed in the current state of the game
+#[derive(Debug)]
+pub enum GameError {
+    /// Progress can be sent only after the game has started
+    NotInProgress { game_id: String },
+    /// The user is not racing in the game
+    NotAParticipant { game_id: String, user_id: String },
+    /// Racers cannot spectate, they are busy with their own game
+    RacerCannotSpectate { game_id: String },
+    /// A game cannot start while some of its players are racing in another one
+    PlayersBusy { display_names: Vec<String> },
+}
+
+/// The player could not register or log in
+#[derive(Debug)]
+pub enum AccountError {
+    /// The nickname is empty or too long after being cleaned up
+    InvalidNickname,
+    /// The nickname contains a word which is not allowed
+    NicknameNotAllowed {
+        nickname: String,
+    },
+    NicknameTaken {
+        nickname: String,
+    },
+    /// No account has been issued the token
+    UnknownToken,
+    /// The player is already connected through another client
+    AccountInUse {
+        nickname: String,
+    },
+}
+
+/// The chat message was not sent to anyone
+#[derive(Debug)]
+pub enum ChatError {
+    /// Nothing is left after removing the spaces and control characters
+    Empty,
+    TooLong {
+        length: usize,
+    },
+    /// The user has sent too many messages recently
+    RateLimited {
+        retry_after: u64,
+    },
+}
+
+/// The message could not be delivered
+#[derive(Debug)]
+pub enum TransportError {
+    ConnectFailed(String),
+    /// The receiving end of the connection has been dropped
+    ChannelClosed {
+        user_id: String,
+    },
+    SendFailed(String),
+}
+
+impl Error {
+    /// The error code sent to the client along with the message
+    pub fn code(&self) -> models::ErrorCode {
+        match self {
+            Error::Protocol(ProtocolError::UnsupportedMessage) => {
+                models::ErrorCode::UnsupportedMessage
+            }
+            Error::Protocol(ProtocolError::UnsupportedVersion { .. }) => {
+                models::ErrorCode::UnsupportedVersion
+            }
+            Error::Protocol(_) => models::ErrorCode::MalformedMessage,
+            Error::Storage(StorageError::UserNotFound { .. }) => models::ErrorCode::UserNotFound,
+            Error::Storage(StorageError::GameNotFound { .. }) => models::ErrorCode::GameNotFound,
+            Error::Storage(StorageError::Persistence(_)) => models::ErrorCode::Internal,
+            Error::Game(GameError::RacerCannotSpectate { .. } | GameError::PlayersBusy { .. }) => {
+                models::ErrorCode::UserBusy
+            }
+            Error::Game(_) => models::ErrorCode::InvalidProgress,
+            Error::Account(AccountError::UnknownToken | AccountError::AccountInUse { .. }) => {
+                models::ErrorCode::InvalidAccount
+            }
+            Error::Account(_) => models::ErrorCode::InvalidNickname,
+            Error::Transport(_) => models::ErrorCode::Internal,
+            Error::Chat(ChatError::RateLimited { .. }) => models::ErrorCode::RateLimited,
+            Error::Chat(_) => models::ErrorCode::InvalidChat,
+        }
+    }
+
+    /// The `Error` message which can be sent to the client
+    pub fn to_server_message(&self) -> models::WSServerMessage {
+        // Internal errors can have paths and other details of the server, those are only logged
+        let message = match self.code() {
+            models::ErrorCode::Internal => "Something went wrong on the server".to_string(),
+            _ => self.to_string(),
+        };
+
+        models::WSServerMessage::Error {
+            code: self.code(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Protocol(error) => error.fmt(f),
+            Error::Storage(error) => error.fmt(f),
+            Error::Game(error) => error.fmt(f),
+            Error::Account(error) => error.fmt(f),
+            Error::Transport(error) => error.fmt(f),
+            Error::Chat(error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::MalformedMessage(reason) => {
+                write!(f, "The message could not be understood, {reason}")
+            }
+            ProtocolError::Serialization(reason) => {
+                write!(f, "The message could not be written, {reason}")
+            }
+            ProtocolError::UnsupportedMessage => {
+                write!(f, "The message is not supported, it may be from a newer version")
+            }
+            ProtocolError::UnsupportedVersion { version } => write!(
+                f,
+                "Version {version} of the protocol is no longer supported, version {} or later is needed",
+                models::MINIMUM_PROTOCOL_VERSION
+            ),
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::UserNotFound { user_id } => {
+                write!(f, "User {user_id} cannot be found or is disconnected")
+            }
+            StorageError::GameNotFound { game_id } => {
+                write!(f, "Game {game_id} does not exist or is already over")
+            }
+            StorageError::Persistence(reason) => write!(f, "Could not save the data, {reason}"),
+        }
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::NotInProgress { game_id } => {
+                write!(f, "Game {game_id} has not started yet")
+            }
+            GameError::NotAParticipant { game_id, user_id } => {
+                write!(f, "User {user_id} is not racing in game {game_id}")
+            }
+            GameError::RacerCannotSpectate { game_id } => {
+                write!(f, "Game {game_id} cannot be spectated while racing")
+            }
+            GameError::PlayersBusy { display_names } => write!(
+                f,
+                "Cannot start the game, {} busy in another game",
+                display_names.join(", ")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountError::InvalidNickname => write!(
+                f,
+                "A nickname must have between 1 and {} characters",
+                crate::server_utils::MAXIMUM_NAME_LENGTH
+            ),
+            AccountError::NicknameNotAllowed { nickname } => {
+                write!(f, "The nickname {nickname} is not allowed")
+            }
+            AccountError::NicknameTaken { nickname } => {
+                write!(f, "The nickname {nickname} is already taken")
+            }
+            AccountError::UnknownToken => write!(f, "The account could not be found"),
+            AccountError::AccountInUse { nickname } => {
+                write!(f, "{nickname} is already connected from another client")
+            }
+        }
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::ConnectFailed(reason) => write!(f, "Could not connect, {reason}"),
+            TransportError::ChannelClosed { user_id } => {
+                write!(f, "The connection of user {user_id} is closed")
+            }
+            TransportError::SendFailed(reason) => {
+                write!(f, "Could not send the message, {reason}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatError::Empty => write!(f, "The chat message is empty"),
+            ChatError::TooLong { length } => write!(
+                f,
+                "The chat message has {length} characters, at most {} are allowed",
+                models::MAXIMUM_CHAT_LENGTH
+            ),
+            ChatError::RateLimited { retry_after } => write!(
+                f,
+                "You are sending messages too fast, try again in {retry_after} seconds"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ProtocolError> for Error {
+    fn from(error: ProtocolError) -> Self {
+        Error::Protocol(error)
+    }
+}
+
+impl From<StorageError> for Error {
+    fn from(error: StorageError) -> Self {
+        Error::Storage(error)
+    }
+}
+
+impl From<GameError> for Error {
+    fn from(error: GameError) -> Self {
+        Error::Game(error)
+    }
+}
+
+impl From<AccountError> for Error {
+    fn from(error: AccountError) -> Self {
+        Error::Account(error)
+    }
+}
+
+impl From<TransportError> for Error {
+    fn from(error: TransportError) -> Self {
+        Error::Transport(error)
+    }
+}
+
+impl From<ChatError> for Error {
+    fn from(error: ChatError) -> Self {
+        Error::Chat(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_details_are_not_sent_to_the_client() {
+        let error = Error::from(StorageError::Persistence(
+            "/data/accounts.jsonl: Permission denied".to_string(),
+        ));
+
+        let models::WSServerMessage::Error { code, message } = error.to_server_message() else {
+            panic!("Not an error message");
+        };
+        assert_eq!(code, models::ErrorCode::Internal);
+        assert!(!message.contains("/data"));
+    }
+
+    #[test]
+    fn other_errors_are_explained_to_the_client() {
+        let error = Error::from(StorageError::UserNotFound {
+            user_id: "7".to_string(),
+        });
+
+        let models::WSServerMessage::Error { code, message } = error.to_server_message() else {
+            panic!("Not an error message");
+        };
+        assert_eq!(code, models::ErrorCode::UserNotFound);
+        assert_eq!(message, error.to_string());
+    }
 }
@@ -1,3 +1,20 @@
+/// Maximum number of users who can race in a single game
+pub const MAXIMUM_RACERS: usize = 8;
+
+/// Longest chat message which can be sent, in characters
+pub const MAXIMUM_CHAT_LENGTH: usize = 200;
+
+/// Version of the protocol, raised whenever messages are added or changed
+/// Version 1 is spoken by the clients and servers which predate the handshake
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest version of the protocol which is still understood, the peers of version 1 cannot parse
+/// the `GameUpdate` and `Error` messages as changed by version 2
+pub const MINIMUM_PROTOCOL_VERSION: u32 = 2;
+
+/// Features supported by this version, a peer announces the ones it supports in the handshake
+pub const SUPPORTED_FEATURES: [Feature; 3] = [Feature::Chat, Feature::Spectating, Feature::Ranked];
+
 /// These are the messages that can be sent by server to client.
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 #[serde(tag = "message_type", content = "message")]
@@ -6,28 +23,165 @@ pub enum WSServerMessage {
     // Progress of the game ( user_id, progress )
     UserStatus {
         connected_users: Vec<User>,
+        // Games being played right now, which can be spectated
+        #[serde(default)]
+        live_games: Vec<LiveGame>,
     },
     SuccessfulConnection {
         user: User,
+        // Token to be presented when reconnecting, to resume the same session
+        resume_token: String,
+        // Game in which the user is racing, when a session is resumed in the middle of a game
+        active_game_id: Option<String>,
+        // Token with which the player logs in to their account, sent when the player
+        // registers or logs in. Guests do not have one
+        #[serde(default)]
+        account_token: Option<String>,
+        // Version of the protocol spoken by the server, and the features supported by both sides
+        #[serde(default = "legacy_protocol_version")]
+        protocol_version: u32,
+        #[serde(default)]
+        features: Vec<Feature>,
     },
     RequestForChallenge {
         // Inform the user that a challenge has been raised against him
         from_user: User,
+        // Unix timestamp after which the challenge expires
+        expires_at: u64,
+    },
+    ChallengeCreated {
+        // Inform the challenger that the challenge is waiting for `to_user` to respond
+        to_user: User,
+        expires_at: u64,
+    },
+    ChallengeRejected {
+        // Inform the challenger that `by_user` rejected the challenge
+        by_user: User,
+    },
+    ChallengeCancelled {
+        // Inform the challenged user that `by_user` took back the challenge
+        by_user: User,
+    },
+    ChallengeExpired {
+        // Inform both the users that nobody responded to the challenge in time
+        from_user_id: String,
+        to_user_id: String,
     },
     Error {
+        // What went wrong, so that the client can react to it
+        code: ErrorCode,
         message: String,
     },
     GameInit {
         game_id: String,
         prompt_text: String,
+        // Attribution of the quote, if known
+        prompt_author: Option<String>,
+        prompt_source: Option<String>,
         // Unix timestamp
         starts_at: u64,
+        // All the users racing in the game
+        participants: Vec<User>,
+        // The game was matched by the ranked queue, the ratings change with the result
+        #[serde(default)]
+        ranked: bool,
+        // Characters typed correctly by a user who rejoins the running game, where they resume
+        #[serde(default)]
+        cursor: usize,
     },
     GameStart,
+    RaceLobbyUpdate {
+        // Current state of the race lobby the user has joined
+        lobby: RaceLobby,
+    },
+    RaceInvitation {
+        // Inform the user that `from_user` wants them to join a race
+        race_id: String,
+        from_user: User,
+    },
+    RaceLobbyClosed {
+        // The user is no longer part of the race lobby
+        race_id: String,
+    },
     GameUpdate {
-        my_progress: u16,
-        opponent_progress: u16,
+        // Progress of every user in the game, including the user receiving the message
+        racers: Vec<RacerProgress>,
     },
+    ProgressRejected {
+        game_id: String,
+        // Keystrokes from this position of the prompt have to be sent again
+        position: usize,
+    },
+    OpponentDisconnected {
+        // A user in the game lost the connection, the race is forfeited if they do not return
+        user_id: String,
+        display_name: String,
+        // Unix timestamp at which the user forfeits the race
+        forfeits_at: u64,
+    },
+    OpponentReconnected {
+        // The disconnected user is back in the game
+        user_id: String,
+        display_name: String,
+    },
+    GameOver {
+        // Ranking of all the users in the game
+        placements: Vec<Placement>,
+        // Typing speed and accuracy of the user receiving the message
+        wpm: f32,
+        accuracy: f32,
+    },
+    History {
+        // Races finished by the user, the most recent first
+        races: Vec<RaceSummary>,
+    },
+    Stats {
+        stats: PlayerStats,
+    },
+    RankedQueueJoined {
+        // Rating of the user, opponents with a close rating are looked for
+        rating: u32,
+    },
+    // The user is no longer waiting for a ranked opponent
+    RankedQueueLeft,
+    RatingUpdate {
+        // Rating of the user after the ranked game
+        rating: u32,
+        change: i32,
+    },
+    Leaderboard {
+        category: LeaderboardCategory,
+        period: LeaderboardPeriod,
+        // The best players first, only registered players are ranked
+        entries: Vec<LeaderboardEntry>,
+    },
+    SpectatorInit {
+        // The user is spectating the game, `GameStart` and `GameUpdate` follow like for a racer
+        game_id: String,
+        prompt_text: String,
+        prompt_author: Option<String>,
+        prompt_source: Option<String>,
+        starts_at: u64,
+        racers: Vec<RacerProgress>,
+    },
+    SpectatedGameOver {
+        // The spectated game is over, the user is no longer spectating it
+        game_id: String,
+        placements: Vec<Placement>,
+    },
+    Chat {
+        // Sent to everyone for the lobby, or to the racers and spectators of the game it was sent in
+        from_user_id: String,
+        display_name: String,
+        text: String,
+        // Game in which the message was sent, None for the lobby
+        game_id: Option<String>,
+        // Unix timestamp in milliseconds
+        sent_at: u64,
+    },
+    // A message added in a later version of the protocol, it is only ever parsed
+    #[serde(other)]
+    Unknown,
 }
 
 /// These are the messages that are sent by client to server
@@ -43,13 +197,189 @@ pub enum WSClientMessage {
         // Accept the challenge from opponent_user_id
         opponent_user_id: String,
     },
+    RejectChallenge {
+        // Reject the challenge from opponent_user_id
+        opponent_user_id: String,
+    },
+    CancelChallenge {
+        // Take back the challenge raised against to_user_id
+        to_user_id: String,
+    },
+    Hello {
+        // Sent right after connecting, to register `nickname` or to log in with `token`
+        // A guest with a random name is created if neither is present
+        nickname: Option<String>,
+        token: Option<String>,
+        // Version of the protocol spoken by the client, and the features it supports
+        #[serde(default = "legacy_protocol_version")]
+        protocol_version: u32,
+        #[serde(default)]
+        features: Vec<Feature>,
+    },
+    CreateRace,
+    InviteToRace {
+        // Invite user_id to the race lobby owned by the current user
+        race_id: String,
+        user_id: String,
+    },
+    JoinRace {
+        race_id: String,
+    },
+    LeaveRace {
+        race_id: String,
+    },
+    StartRace {
+        // Start the game with all the participants of the race lobby
+        race_id: String,
+    },
+    // Create a private room, a race lobby which is joined with its code instead of an invitation
+    // The room outlives its races, and is left with `LeaveRace`
+    CreateRoom,
+    JoinRoom {
+        code: String,
+    },
     UpdateProgress {
         game_id: String,
-        progress: u16,
+        // Characters typed since the last update, the server calculates the progress from these
+        keystrokes: Vec<Keystroke>,
+        // Live statistics calculated by the client, relayed to the other racers
+        #[serde(default)]
+        stats: TypingStats,
+    },
+    // Ask for the recent races of the user, answered with `History`
+    GetHistory,
+    // Ask for the statistics of the user, answered with `Stats`
+    GetStats,
+    // Wait for an opponent with a close rating, a ranked game starts once one is found
+    JoinRankedQueue,
+    LeaveRankedQueue,
+    GetLeaderboard {
+        category: LeaderboardCategory,
+        period: LeaderboardPeriod,
+    },
+    // Watch the game without racing in it, answered with `SpectatorInit`
+    // A user spectates only one game at a time
+    SpectateGame {
+        game_id: String,
     },
+    StopSpectating,
+    // Racers chat with the others in their game, everyone else with the lobby
+    Chat {
+        text: String,
+    },
+    // A message added in a later version of the protocol, it is only ever parsed
+    #[serde(other)]
+    Unknown,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+impl WSServerMessage {
+    /// The feature which the client has to support to be sent the message
+    pub fn required_feature(&self) -> Option<Feature> {
+        match self {
+            WSServerMessage::Chat { .. } => Some(Feature::Chat),
+            WSServerMessage::SpectatorInit { .. } | WSServerMessage::SpectatedGameOver { .. } => {
+                Some(Feature::Spectating)
+            }
+            WSServerMessage::RankedQueueJoined { .. }
+            | WSServerMessage::RankedQueueLeft
+            | WSServerMessage::RatingUpdate { .. } => Some(Feature::Ranked),
+            _ => None,
+        }
+    }
+}
+
+/// Optional parts of the protocol, their messages are only sent to the peers which support them
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Chat,
+    Spectating,
+    Ranked,
+    // A feature of a later version of the protocol
+    #[serde(other)]
+    Unknown,
+}
+
+fn legacy_protocol_version() -> u32 {
+    1
+}
+
+/// The features supported by both sides, given the version and the features of the peer
+pub fn negotiate_features(peer_version: u32, peer_features: &[Feature]) -> Vec<Feature> {
+    // A peer older than the oldest supported version is not spoken to, so it gets no features
+    if peer_version < MINIMUM_PROTOCOL_VERSION {
+        return Vec::new();
+    }
+
+    SUPPORTED_FEATURES
+        .into_iter()
+        .filter(|feature| peer_features.contains(feature))
+        .collect()
+}
+
+/// Parse a message of the peer, a message of a type added in a later version of the protocol is
+/// parsed as `Unknown` rather than failing
+pub fn parse_message<T: serde::de::DeserializeOwned>(text: &str) -> serde_json::Result<T> {
+    #[derive(serde::Deserialize)]
+    struct MessageType {
+        message_type: String,
+    }
+
+    serde_json::from_str(text).or_else(|error| {
+        // Without its contents, a message of an unknown type is parsed as the `Unknown` variant,
+        // while the known messages which could not be parsed still fail
+        match serde_json::from_str::<MessageType>(text) {
+            Ok(MessageType { message_type }) => {
+                serde_json::from_value(serde_json::json!({ "message_type": message_type }))
+                    .map_err(|_| error)
+            }
+            Err(_) => Err(error),
+        }
+    })
+}
+
+/// Kind of the error sent to the client along with the error message
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // The user is disconnected or never existed
+    UserNotFound,
+    // The user is playing a game
+    UserBusy,
+    // The challenge cannot be raised or answered
+    InvalidChallenge,
+    // The progress sent by the user is not possible, it has been ignored
+    InvalidProgress,
+    // The user has been disqualified from the game for sending impossible progress
+    Disqualified,
+    // The race lobby cannot be created, joined or started
+    InvalidRace,
+    // The message sent by the user could not be understood
+    MalformedMessage,
+    // The game does not exist or is already over
+    GameNotFound,
+    // Something went wrong on the server while handling the message
+    Internal,
+    // The nickname cannot be registered
+    InvalidNickname,
+    // The account cannot be logged in to
+    InvalidAccount,
+    // The ranked queue cannot be joined or left
+    InvalidQueue,
+    // The chat message is empty or too long
+    InvalidChat,
+    // Too many messages were sent in a short time, the message was dropped
+    RateLimited,
+    // The message is of a type which the server does not know
+    UnsupportedMessage,
+    // The version of the protocol spoken by the client is too old
+    UnsupportedVersion,
+    // An error code added in a later version of the protocol
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
 pub enum UserStatus {
     Available,
     Busy,
@@ -60,6 +390,152 @@ pub struct User {
     pub id: String,
     pub status: UserStatus,
     pub display_name: String,
+    // Rating from the ranked games, guests are not rated
+    #[serde(default)]
+    pub rating: Option<u32>,
+    // Bots are played by the server, they accept every challenge
+    #[serde(default)]
+    pub bot: bool,
+}
+
+/// Progress of a user in a game
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RacerProgress {
+    pub user_id: String,
+    pub display_name: String,
+    pub progress: u16,
+    // Number of characters typed correctly from the start, which is where the cursor is
+    #[serde(default)]
+    pub cursor: usize,
+    #[serde(default)]
+    pub stats: TypingStats,
+    // The user has lost the connection and may forfeit the race
+    #[serde(default)]
+    pub disconnected: bool,
+}
+
+/// Live typing statistics of a user while the game is in progress
+/// These are only for display, the results are calculated by the server from the keystrokes
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default)]
+pub struct TypingStats {
+    // Words per minute of all the characters typed, a word is five characters
+    pub gross_wpm: f32,
+    // Gross WPM after subtracting the errors which are not corrected yet
+    pub net_wpm: f32,
+    pub accuracy: f32,
+    pub uncorrected_errors: u32,
+}
+
+/// A group of users waiting for the owner to start the race
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RaceLobby {
+    pub race_id: String,
+    pub owner_id: String,
+    // Users who have joined the race, including the owner
+    pub participants: Vec<User>,
+    // Code to join the lobby with, if it is a private room
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// Result of a user in a finished game
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Placement {
+    // 1 is the winner
+    pub position: usize,
+    pub user_id: String,
+    pub display_name: String,
+    pub progress: u16,
+    // Unix timestamp in milliseconds, None if the user could not complete the prompt
+    pub finished_at: Option<u64>,
+    pub wpm: f32,
+    pub accuracy: f32,
+    // Why the user was flagged or disqualified
+    pub note: Option<String>,
+    pub disqualified: bool,
+}
+
+/// Result of a finished race, as seen by one of the users who raced in it
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RaceSummary {
+    pub game_id: String,
+    pub prompt_text: String,
+    // Unix timestamp in milliseconds at which the race was over
+    pub finished_at: u64,
+    pub position: usize,
+    pub racer_count: usize,
+    pub wpm: f32,
+    pub accuracy: f32,
+    // The user typed the complete prompt
+    pub completed: bool,
+    pub disqualified: bool,
+}
+
+/// Statistics of a user over all the races finished by them
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct PlayerStats {
+    pub races: usize,
+    pub wins: usize,
+    // Fastest race in which the complete prompt was typed
+    pub best_wpm: f32,
+    // Averages over the last `recent_races` races, disqualified races are left out
+    pub recent_races: usize,
+    pub average_wpm: f32,
+    pub average_accuracy: f32,
+    #[serde(default)]
+    pub rating: Option<u32>,
+}
+
+/// What the players are ranked by in a leaderboard
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardCategory {
+    // Fastest race in which the complete prompt was typed
+    BestWpm,
+    // Current rating, of the players who raced ranked in the period
+    Rating,
+    MostRaces,
+}
+
+/// Races finished within the period are considered for a leaderboard
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardPeriod {
+    // The last 24 hours
+    Daily,
+    // The last 7 days
+    Weekly,
+    AllTime,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct LeaderboardEntry {
+    // 1 is the best
+    pub position: usize,
+    pub user_id: String,
+    pub display_name: String,
+    // WPM, rating or number of races, as per the category
+    pub value: f32,
+}
+
+/// A game being played, listed so that it can be spectated
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct LiveGame {
+    pub game_id: String,
+    pub racer_names: Vec<String>,
+    // Unix timestamp at which the race starts, or started
+    pub starts_at: u64,
+    pub ranked: bool,
+}
+
+/// A character typed by the user in a game
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Keystroke {
+    // Position of the character in the prompt
+    pub position: usize,
+    pub character: char,
+    // Milliseconds since the start of the game
+    pub elapsed: u64,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -68,3 +544,70 @@ pub enum GameStatus {
     InProgress,
     Finished,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peers_older_than_the_minimum_get_no_features() {
+        assert!(negotiate_features(1, &SUPPORTED_FEATURES).is_empty());
+        assert!(negotiate_features(1, &[]).is_empty());
+    }
+
+    #[test]
+    fn features_are_the_ones_supported_by_both_sides() {
+        assert_eq!(
+            negotiate_features(PROTOCOL_VERSION, &[Feature::Ranked, Feature::Chat]),
+            vec![Feature::Chat, Feature::Ranked]
+        );
+        assert_eq!(
+            negotiate_features(PROTOCOL_VERSION, &SUPPORTED_FEATURES),
+            SUPPORTED_FEATURES.to_vec()
+        );
+        assert!(negotiate_features(PROTOCOL_VERSION, &[]).is_empty());
+    }
+
+    #[test]
+    fn features_of_later_versions_are_ignored() {
+        let features: Vec<Feature> =
+            serde_json::from_str(r#"["spectating", "tournaments"]"#).unwrap();
+
+        assert_eq!(features, vec![Feature::Spectating, Feature::Unknown]);
+        assert_eq!(
+            negotiate_features(PROTOCOL_VERSION + 1, &features),
+            vec![Feature::Spectating]
+        );
+    }
+
+    #[test]
+    fn hello_without_a_version_is_from_a_legacy_client() {
+        let message = parse_message::<WSClientMessage>(
+            r#"{"message_type": "hello", "message": {"nickname": null, "token": null}}"#,
+        )
+        .unwrap();
+
+        let WSClientMessage::Hello {
+            protocol_version,
+            features,
+            ..
+        } = message
+        else {
+            panic!("Not a hello message");
+        };
+        assert!(protocol_version < MINIMUM_PROTOCOL_VERSION);
+        assert!(negotiate_features(protocol_version, &features).is_empty());
+    }
+
+    #[test]
+    fn messages_of_later_versions_are_parsed_as_unknown() {
+        let message =
+            parse_message::<WSClientMessage>(r#"{"message_type": "teleport", "message": {}}"#)
+                .unwrap();
+        assert!(matches!(message, WSClientMessage::Unknown));
+
+        let malformed =
+            parse_message::<WSClientMessage>(r#"{"message_type": "chat", "message": {}}"#);
+        assert!(malformed.is_err());
+    }
+}
@@ -1,6 +1,14 @@
 use rnglib;
+pub mod accounts;
+pub mod anti_cheat;
+pub mod bots;
+pub mod config;
 pub mod fast_storage;
+pub mod leaderboard;
 pub mod message_handlers;
+pub mod prompt_corpus;
+pub mod rating;
+pub mod storage;
 
 pub fn generate_name() -> String {
     let random_name_generator = rnglib::RNG::from(&rnglib::Language::Fantasy);
@@ -12,6 +20,55 @@ pub fn generate_name() -> String {
     )
 }
 
+/// Longest name which a user can ask for
+pub const MAXIMUM_NAME_LENGTH: usize = 24;
+
+/// Clean up the name requested by a user, `None` if nothing usable is left
+pub fn sanitize_name(name: &str) -> Option<String> {
+    let name = name
+        .split_whitespace()
+        .map(|word| word.chars().filter(|character| !character.is_control()))
+        .map(String::from_iter)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if name.is_empty() || name.chars().count() > MAXIMUM_NAME_LENGTH {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Length of the tokens with which a user resumes a session or logs in to an account
+const TOKEN_LENGTH: usize = 32;
+
+/// Random token which is hard to guess, whoever knows it can take over the session or account
+pub fn generate_token() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Length of the codes with which users join a private room
+const ROOM_CODE_LENGTH: usize = 6;
+
+/// Characters of the room codes, those which are easily mistaken for one another are left out
+const ROOM_CODE_CHARACTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Short random code which is easy to read out and type, to be shared with the players of a room
+pub fn generate_room_code() -> String {
+    use rand::Rng;
+
+    let mut rng = rand::thread_rng();
+    (0..ROOM_CODE_LENGTH)
+        .map(|_| char::from(ROOM_CODE_CHARACTERS[rng.gen_range(0..ROOM_CODE_CHARACTERS.len())]))
+        .collect()
+}
+
 /// execute the function `func` after `time` seconds
 pub async fn set_timeout<Fut>(time: u64, func: impl FnOnce() -> Fut)
 where
@@ -0,0 +1,87 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+/// Words that are not allowed in a nickname, shipped along with the server binary
+const BLOCKED_WORDS: &str = include_str!("../../assets/blocked_words.txt");
+
+/// A player who registered a nickname, so that they keep the same identity across sessions
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Account {
+    pub id: String,
+    pub nickname: String,
+    /// Hash of the secret with which the player logs in, only the client of the player knows
+    /// the secret itself
+    #[serde(default)]
+    token_hash: String,
+    /// The secret as written by the servers which predate the hashing, see `hash_plain_token`
+    #[serde(default, skip_serializing)]
+    token: Option<String>,
+    /// Unix timestamp at which the account was registered
+    pub registered_at: u64,
+}
+
+/// The tokens are long random strings, so a plain hash is enough to keep them from being guessed
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+impl Account {
+    /// A new account to which the player logs in with the token
+    pub fn new(id: String, nickname: String, token: &str) -> Self {
+        Self {
+            id,
+            nickname,
+            token_hash: hash_token(token),
+            token: None,
+            registered_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }
+    }
+
+    pub fn has_token(&self, token: &str) -> bool {
+        self.token_hash == hash_token(token)
+    }
+
+    /// Replace the secret read from an older record with its hash
+    /// Returns true if the record has to be written again
+    pub fn hash_plain_token(&mut self) -> bool {
+        match self.token.take() {
+            Some(token) => {
+                self.token_hash = hash_token(&token);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Nicknames are unique irrespective of the case
+    pub fn has_nickname(&self, nickname: &str) -> bool {
+        self.nickname.to_lowercase() == nickname.to_lowercase()
+    }
+}
+
+/// Returns false if any word of the nickname is a blocked word
+pub fn is_nickname_allowed(nickname: &str) -> bool {
+    let blocked_words = BLOCKED_WORDS
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect::<Vec<_>>();
+
+    let lowercase_nickname = nickname.to_lowercase();
+    let words = lowercase_nickname
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+
+    // Spaces and punctuation between the letters of a word do not hide it
+    let squashed_nickname = words.concat();
+
+    !words
+        .iter()
+        .chain(std::iter::once(&squashed_nickname.as_str()))
+        .any(|word| blocked_words.contains(word))
+}
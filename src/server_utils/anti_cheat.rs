@@ -0,0 +1,278 @@
+use std::{collections::VecDeque, fmt};
+
+use crate::{models::Keystroke, server_utils::fast_storage::calculate_wpm};
+
+/// Fastest typing speed which can be sustained by a human
+const MAXIMUM_WPM: f32 = 250.0;
+
+/// Number of consecutive keystrokes over which the typing speed is measured
+/// A short burst of fast keystrokes is fine, it has to be sustained to be a violation
+const SPEED_WINDOW: usize = 25;
+
+/// Milliseconds by which a keystroke can be ahead of the time elapsed on the server
+/// This accounts for the network latency of the `GameStart` message
+const LATENCY_TOLERANCE: u64 = 2000;
+
+/// Ways in which the progress sent by a client can be impossible
+#[derive(Clone, Debug)]
+pub enum Violation {
+    /// The keystroke is for a position which the cursor has not reached yet
+    InvalidPosition { position: usize, expected: usize },
+    /// The keystroke is older than the previous keystroke
+    TimeRegression { elapsed: u64, previous: u64 },
+    /// The keystroke claims to have been typed after more time than has elapsed since the start
+    FutureKeystroke { elapsed: u64, actual: u64 },
+    /// The typing speed is faster than what a human can sustain
+    ImpossibleSpeed { wpm: f32 },
+}
+
+impl Violation {
+    /// Severe violations cannot happen because of a slow network, they disqualify the player
+    /// A keystroke ahead of the cursor follows a keystroke which was lost or rejected,
+    /// the client is asked to send the keystrokes again from the cursor
+    pub fn is_severe(&self) -> bool {
+        !matches!(
+            self,
+            Violation::TimeRegression { .. } | Violation::InvalidPosition { .. }
+        )
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::InvalidPosition { position, expected } => write!(
+                f,
+                "keystroke at position {position} when the cursor is at {expected}"
+            ),
+            Violation::TimeRegression { elapsed, previous } => write!(
+                f,
+                "keystroke at {elapsed}ms arrived after a keystroke at {previous}ms"
+            ),
+            Violation::FutureKeystroke { elapsed, actual } => write!(
+                f,
+                "keystroke at {elapsed}ms when only {actual}ms have elapsed"
+            ),
+            Violation::ImpossibleSpeed { wpm } => {
+                write!(f, "typing speed of {wpm:.0} WPM is not humanly possible")
+            }
+        }
+    }
+}
+
+/// Everything the server knows about the typing of a user in a game
+///
+/// The typed text is reconstructed from the keystrokes, so that the progress is
+/// calculated by the server instead of trusting the client
+#[derive(Clone, Default)]
+pub struct TypingRecord {
+    /// Whether the character typed at each position of the prompt was correct
+    typed: Vec<bool>,
+    total_keystrokes: u32,
+    correct_keystrokes: u32,
+    /// Elapsed time of the most recent keystrokes, used to measure the typing speed
+    recent_keystrokes: VecDeque<u64>,
+}
+
+impl TypingRecord {
+    /// Validate the keystroke and update the typed text
+    ///
+    /// `prompt` is the text of the game, `actual_elapsed` is the milliseconds elapsed
+    /// on the server since the start of the game
+    pub fn apply(
+        &mut self,
+        prompt: &[char],
+        keystroke: &Keystroke,
+        actual_elapsed: u64,
+    ) -> Result<(), Violation> {
+        // A position before the cursor means that the user pressed backspace
+        let expected = self.typed.len().min(prompt.len().saturating_sub(1));
+        if keystroke.position > expected {
+            return Err(Violation::InvalidPosition {
+                position: keystroke.position,
+                expected,
+            });
+        }
+
+        if let Some(&previous) = self.recent_keystrokes.back() {
+            if keystroke.elapsed < previous {
+                return Err(Violation::TimeRegression {
+                    elapsed: keystroke.elapsed,
+                    previous,
+                });
+            }
+        }
+
+        if keystroke.elapsed > actual_elapsed + LATENCY_TOLERANCE {
+            return Err(Violation::FutureKeystroke {
+                elapsed: keystroke.elapsed,
+                actual: actual_elapsed,
+            });
+        }
+
+        self.recent_keystrokes.push_back(keystroke.elapsed);
+        if self.recent_keystrokes.len() > SPEED_WINDOW {
+            self.recent_keystrokes.pop_front();
+        }
+
+        if self.recent_keystrokes.len() == SPEED_WINDOW {
+            let window_start = self.recent_keystrokes.front().copied().unwrap_or_default();
+            let wpm = calculate_wpm(SPEED_WINDOW - 1, keystroke.elapsed - window_start);
+
+            if wpm > MAXIMUM_WPM {
+                return Err(Violation::ImpossibleSpeed { wpm });
+            }
+        }
+
+        let is_correct = prompt[keystroke.position] == keystroke.character;
+        self.typed.truncate(keystroke.position);
+        self.typed.push(is_correct);
+
+        self.total_keystrokes += 1;
+        if is_correct {
+            self.correct_keystrokes += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Position of the prompt at which the next keystroke is expected
+    pub fn cursor(&self) -> usize {
+        self.typed.len()
+    }
+
+    /// Number of characters from the start of the prompt which are typed correctly
+    pub fn correct_prefix_length(&self) -> usize {
+        self.typed
+            .iter()
+            .take_while(|is_correct| **is_correct)
+            .count()
+    }
+
+    /// Percentage of the keystrokes which were correct
+    pub fn accuracy(&self) -> f32 {
+        if self.total_keystrokes == 0 {
+            100.0
+        } else {
+            self.correct_keystrokes as f32 * 100.0 / self.total_keystrokes as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keystroke(position: usize, character: char, elapsed: u64) -> Keystroke {
+        Keystroke {
+            position,
+            character,
+            elapsed,
+        }
+    }
+
+    #[test]
+    fn keystrokes_in_order_are_typed() {
+        let prompt = "cat".chars().collect::<Vec<_>>();
+        let mut record = TypingRecord::default();
+
+        record.apply(&prompt, &keystroke(0, 'c', 300), 300).unwrap();
+        record.apply(&prompt, &keystroke(1, 'o', 600), 600).unwrap();
+        assert_eq!(record.correct_prefix_length(), 1);
+
+        // Backspace and correct the mistake
+        record.apply(&prompt, &keystroke(1, 'a', 900), 900).unwrap();
+        record
+            .apply(&prompt, &keystroke(2, 't', 1200), 1200)
+            .unwrap();
+        assert_eq!(record.correct_prefix_length(), 3);
+        assert_eq!(record.cursor(), 3);
+        assert_eq!(record.accuracy(), 75.0);
+    }
+
+    #[test]
+    fn keystroke_after_a_gap_is_rejected_without_disqualifying() {
+        let prompt = "cat".chars().collect::<Vec<_>>();
+        let mut record = TypingRecord::default();
+        record.apply(&prompt, &keystroke(0, 'c', 300), 300).unwrap();
+
+        let violation = record
+            .apply(&prompt, &keystroke(2, 't', 900), 900)
+            .unwrap_err();
+        assert!(matches!(
+            violation,
+            Violation::InvalidPosition {
+                position: 2,
+                expected: 1
+            }
+        ));
+        assert!(!violation.is_severe());
+        assert_eq!(record.cursor(), 1);
+
+        // The keystrokes sent again from the cursor are accepted
+        record
+            .apply(&prompt, &keystroke(1, 'a', 600), 1000)
+            .unwrap();
+        record
+            .apply(&prompt, &keystroke(2, 't', 900), 1000)
+            .unwrap();
+        assert_eq!(record.correct_prefix_length(), 3);
+    }
+
+    #[test]
+    fn keystroke_going_back_in_time_is_rejected() {
+        let prompt = "cat".chars().collect::<Vec<_>>();
+        let mut record = TypingRecord::default();
+        record.apply(&prompt, &keystroke(0, 'c', 600), 600).unwrap();
+
+        let violation = record
+            .apply(&prompt, &keystroke(1, 'a', 300), 700)
+            .unwrap_err();
+        assert!(matches!(violation, Violation::TimeRegression { .. }));
+        assert!(!violation.is_severe());
+        assert_eq!(record.cursor(), 1);
+    }
+
+    #[test]
+    fn keystroke_from_the_future_is_severe() {
+        let prompt = "cat".chars().collect::<Vec<_>>();
+        let mut record = TypingRecord::default();
+
+        let violation = record
+            .apply(&prompt, &keystroke(0, 'c', 5000), 1000)
+            .unwrap_err();
+        assert!(matches!(violation, Violation::FutureKeystroke { .. }));
+        assert!(violation.is_severe());
+    }
+
+    #[test]
+    fn sustained_superhuman_speed_is_severe() {
+        let prompt = "a".repeat(SPEED_WINDOW * 2).chars().collect::<Vec<_>>();
+        let mut record = TypingRecord::default();
+
+        // A keystroke every 10ms is 1200 WPM
+        let result = (0..SPEED_WINDOW).try_for_each(|position| {
+            let elapsed = position as u64 * 10;
+            record.apply(&prompt, &keystroke(position, 'a', elapsed), elapsed)
+        });
+
+        let violation = result.unwrap_err();
+        assert!(matches!(violation, Violation::ImpossibleSpeed { .. }));
+        assert!(violation.is_severe());
+    }
+
+    #[test]
+    fn human_speed_is_accepted() {
+        let prompt = "a".repeat(SPEED_WINDOW * 2).chars().collect::<Vec<_>>();
+        let mut record = TypingRecord::default();
+
+        // A keystroke every 100ms is 120 WPM
+        for position in 0..prompt.len() {
+            let elapsed = position as u64 * 100;
+            record
+                .apply(&prompt, &keystroke(position, 'a', elapsed), elapsed)
+                .unwrap();
+        }
+        assert_eq!(record.correct_prefix_length(), prompt.len());
+    }
+}
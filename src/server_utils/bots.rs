@@ -0,0 +1,252 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::sync::mpsc;
+
+use crate::{
+    errors::ProtocolError,
+    models,
+    server_utils::{
+        self,
+        fast_storage::{self, UserConnection},
+        message_handlers,
+    },
+};
+
+/// Seconds a bot takes to answer a challenge or an invitation
+const RESPONSE_DELAY: u64 = 1;
+
+/// Probability of a character being typed wrong
+const ERROR_RATE: f64 = 0.04;
+
+/// A keystroke takes up to this fraction of the average time more or less than the average
+const JITTER: f64 = 0.5;
+
+/// Most characters typed after a mistake before the bot notices it
+const CHARACTERS_BEFORE_NOTICING: usize = 2;
+
+/// Milliseconds for which the bot pauses on noticing a mistake, before going back to correct it
+const REACTION_TIME: u64 = 400;
+
+/// Add the bots of the config as connected users, each of them is run by a task of its own
+/// The bots act through `handle_client_messages`, exactly like the users connected over websockets
+pub async fn spawn_bots(db: Arc<fast_storage::BlazinglyFastDb>) {
+    for (index, bot) in db.config().bots.clone().into_iter().enumerate() {
+        if db.is_display_name_taken(&bot.name).await {
+            eprintln!(
+                "Not starting the bot {}, the name is already taken",
+                bot.name
+            );
+            continue;
+        }
+
+        let bot_id = format!("bot-{}", index + 1);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let user = models::User {
+            id: bot_id.clone(),
+            status: models::UserStatus::Available,
+            display_name: bot.name.clone(),
+            rating: None,
+            bot: true,
+        };
+
+        db.insert_new_user_connection(UserConnection::new(user, sender))
+            .await;
+        println!("Started the bot {} typing at {} WPM", bot.name, bot.wpm);
+
+        tokio::spawn(run_bot(db.clone(), bot_id, bot.wpm, receiver));
+    }
+}
+
+/// Send a message on behalf of the bot, it is handled like a message received over a websocket
+async fn send_as_bot(
+    db: &Arc<fast_storage::BlazinglyFastDb>,
+    bot_id: &str,
+    message: models::WSClientMessage,
+) {
+    match serde_json::to_string(&message) {
+        Ok(text_message) => {
+            message_handlers::handle_client_messages(&text_message, db.clone(), bot_id).await
+        }
+        Err(error) => eprintln!("{}", ProtocolError::Serialization(error.to_string())),
+    }
+}
+
+/// Send the message after `RESPONSE_DELAY`, so that the bot does not answer instantly
+fn respond_later(
+    db: &Arc<fast_storage::BlazinglyFastDb>,
+    bot_id: &str,
+    message: models::WSClientMessage,
+) {
+    let db = db.clone();
+    let bot_id = bot_id.to_owned();
+    tokio::spawn(server_utils::set_timeout(
+        RESPONSE_DELAY,
+        move || async move {
+            send_as_bot(&db, &bot_id, message).await;
+        },
+    ));
+}
+
+/// React to the messages sent to the bot: accept every challenge and invitation, and type the
+/// prompt of every game once it starts
+async fn run_bot(
+    db: Arc<fast_storage::BlazinglyFastDb>,
+    bot_id: String,
+    wpm: u32,
+    mut receiver: mpsc::UnboundedReceiver<models::WSServerMessage>,
+) {
+    // The game the bot has been put in, which is typed once it starts
+    let mut next_game = None;
+    // Set when the game being typed is over, so that the typing stops
+    let mut is_game_over = Arc::new(AtomicBool::new(false));
+
+    while let Some(message) = receiver.recv().await {
+        match message {
+            models::WSServerMessage::RequestForChallenge { from_user, .. } => respond_later(
+                &db,
+                &bot_id,
+                models::WSClientMessage::AcceptChallenge {
+                    opponent_user_id: from_user.id,
+                },
+            ),
+            models::WSServerMessage::RaceInvitation { race_id, .. } => {
+                respond_later(&db, &bot_id, models::WSClientMessage::JoinRace { race_id })
+            }
+            models::WSServerMessage::GameInit {
+                game_id,
+                prompt_text,
+                ..
+            } => next_game = Some((game_id, prompt_text)),
+            models::WSServerMessage::GameStart => {
+                if let Some((game_id, prompt_text)) = next_game.take() {
+                    is_game_over = Arc::new(AtomicBool::new(false));
+                    tokio::spawn(type_prompt(
+                        db.clone(),
+                        bot_id.clone(),
+                        wpm,
+                        game_id,
+                        prompt_text,
+                        is_game_over.clone(),
+                    ));
+                }
+            }
+            models::WSServerMessage::GameOver { .. } => is_game_over.store(true, Ordering::Relaxed),
+            models::WSServerMessage::Error { message, .. } => {
+                eprintln!("Bot {bot_id} received an error, {message}")
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Typing of the bot in a game, from which the live statistics are calculated
+#[derive(Default)]
+struct BotTyping {
+    keystrokes: u32,
+    correct_keystrokes: u32,
+    uncorrected_errors: u32,
+}
+
+impl BotTyping {
+    fn stats(&self, elapsed: u64) -> models::TypingStats {
+        let minutes = elapsed.max(1) as f32 / 60_000.0;
+        let gross_wpm = self.keystrokes as f32 / 5.0 / minutes;
+
+        models::TypingStats {
+            gross_wpm,
+            net_wpm: (gross_wpm - self.uncorrected_errors as f32 / minutes).max(0.0),
+            accuracy: self.correct_keystrokes as f32 * 100.0 / self.keystrokes.max(1) as f32,
+            uncorrected_errors: self.uncorrected_errors,
+        }
+    }
+}
+
+/// A letter other than the expected one, typed by mistake
+fn wrong_character(expected: char, rng: &mut StdRng) -> char {
+    loop {
+        let character = char::from(rng.gen_range(b'a'..=b'z'));
+        if character != expected {
+            return character;
+        }
+    }
+}
+
+/// Type the prompt one keystroke at a time at about `wpm`, the way a person would
+/// Now and then a character is typed wrong, and corrected after typing a few more characters
+async fn type_prompt(
+    db: Arc<fast_storage::BlazinglyFastDb>,
+    bot_id: String,
+    wpm: u32,
+    game_id: String,
+    prompt_text: String,
+    is_game_over: Arc<AtomicBool>,
+) {
+    let prompt = prompt_text.chars().collect::<Vec<_>>();
+    let mut rng = StdRng::from_entropy();
+    let started_at = Instant::now();
+
+    // Milliseconds per character at the average speed, a word is five characters
+    let average_delay = 60_000.0 / (f64::from(wpm) * 5.0);
+
+    let mut typing = BotTyping::default();
+    let mut position = 0;
+    // Position of the mistake which is not corrected yet, and the characters typed after it
+    // before it is noticed
+    let mut mistake: Option<(usize, usize)> = None;
+
+    while position < prompt.len() || mistake.is_some() {
+        if let Some((mistake_position, characters_before_noticing)) = mistake {
+            if position > mistake_position + characters_before_noticing || position == prompt.len()
+            {
+                // The next keystroke is at the position of the mistake, which erases everything after it
+                tokio::time::sleep(Duration::from_millis(REACTION_TIME)).await;
+                position = mistake_position;
+                mistake = None;
+                typing.uncorrected_errors = 0;
+            }
+        }
+
+        // The sum of two random numbers makes delays close to the average more common
+        let jitter = (rng.gen::<f64>() + rng.gen::<f64>() - 1.0) * JITTER;
+        tokio::time::sleep(Duration::from_millis(
+            (average_delay * (1.0 + jitter)) as u64,
+        ))
+        .await;
+
+        if is_game_over.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let expected = prompt[position];
+        let character = if mistake.is_none() && rng.gen_bool(ERROR_RATE) {
+            mistake = Some((position, rng.gen_range(0..=CHARACTERS_BEFORE_NOTICING)));
+            typing.uncorrected_errors = 1;
+            wrong_character(expected, &mut rng)
+        } else {
+            typing.correct_keystrokes += 1;
+            expected
+        };
+        typing.keystrokes += 1;
+
+        let elapsed = started_at.elapsed().as_millis() as u64;
+        let message = models::WSClientMessage::UpdateProgress {
+            game_id: game_id.clone(),
+            keystrokes: vec![models::Keystroke {
+                position,
+                character,
+                elapsed,
+            }],
+            stats: typing.stats(elapsed),
+        };
+        send_as_bot(&db, &bot_id, message).await;
+
+        position += 1;
+    }
+}
@@ -0,0 +1,202 @@
+use std::{env, path::PathBuf};
+
+use crate::server_utils::prompt_corpus::PromptFilter;
+
+/// Typing speeds outside this range are not allowed for bots, the fastest would be taken for cheating
+const BOT_WPM_RANGE: std::ops::RangeInclusive<u32> = 10..=200;
+
+/// A player run by the server, who accepts every challenge
+#[derive(Clone, Debug)]
+pub struct BotSettings {
+    pub name: String,
+    /// Average typing speed in words per minute
+    pub wpm: u32,
+}
+
+impl BotSettings {
+    /// Parse the bots from a list of `name:wpm`, separated by commas
+    fn parse_list(value: &str) -> Result<Vec<BotSettings>, String> {
+        value
+            .split(',')
+            .filter(|bot| !bot.trim().is_empty())
+            .map(|bot| {
+                let (name, wpm) = bot
+                    .rsplit_once(':')
+                    .filter(|(name, _)| !name.trim().is_empty())
+                    .ok_or_else(|| format!("{bot} is not in the form name:wpm"))?;
+                let wpm = wpm
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|wpm| BOT_WPM_RANGE.contains(wpm))
+                    .ok_or_else(|| {
+                        format!(
+                            "{wpm} is not a typing speed between {} and {}",
+                            BOT_WPM_RANGE.start(),
+                            BOT_WPM_RANGE.end()
+                        )
+                    })?;
+
+                Ok(BotSettings {
+                    name: name.trim().to_string(),
+                    wpm,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Settings of the server which can be changed without rebuilding it
+/// Every setting is read from an environment variable, falling back to a default
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Seconds for which a challenge waits for the opponent to respond
+    pub challenge_timeout: u64,
+    /// Seconds for which the session of a disconnected user can be resumed
+    pub resume_timeout: u64,
+    /// Seconds for which a disconnected user keeps their place in a game, before forfeiting
+    pub disconnect_grace_period: u64,
+    /// Directory in which accounts and finished games are saved
+    /// Nothing is saved to the disk when it is `None`
+    pub data_directory: Option<PathBuf>,
+    /// Bots which are always online, so that there is someone to race against
+    pub bots: Vec<BotSettings>,
+    /// Constraints on the prompts picked for the races
+    pub prompt_filter: PromptFilter,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            challenge_timeout: 30,
+            resume_timeout: 60,
+            disconnect_grace_period: 30,
+            data_directory: Some(PathBuf::from("data")),
+            bots: vec![
+                BotSettings {
+                    name: "Whiskers".to_string(),
+                    wpm: 35,
+                },
+                BotSettings {
+                    name: "Mittens".to_string(),
+                    wpm: 60,
+                },
+                BotSettings {
+                    name: "Shadow".to_string(),
+                    wpm: 95,
+                },
+            ],
+            prompt_filter: PromptFilter::default(),
+        }
+    }
+}
+
+fn read_env_var<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed_value) => Some(parsed_value),
+        Err(_) => {
+            eprintln!("Ignoring invalid value {value} for {name}");
+            None
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        let default_config = Self::default();
+
+        let config = Self {
+            challenge_timeout: read_env_var("KITTUI_CHALLENGE_TIMEOUT")
+                .unwrap_or(default_config.challenge_timeout),
+            resume_timeout: read_env_var("KITTUI_RESUME_TIMEOUT")
+                .unwrap_or(default_config.resume_timeout),
+            disconnect_grace_period: read_env_var("KITTUI_DISCONNECT_GRACE_PERIOD")
+                .unwrap_or(default_config.disconnect_grace_period),
+            // An empty value keeps everything in memory
+            data_directory: match env::var("KITTUI_DATA_DIR") {
+                Ok(directory) if directory.is_empty() => None,
+                Ok(directory) => Some(PathBuf::from(directory)),
+                Err(_) => default_config.data_directory,
+            },
+            // An empty value runs the server without bots
+            bots: match env::var("KITTUI_BOTS") {
+                Ok(bots) => BotSettings::parse_list(&bots).unwrap_or_else(|error| {
+                    eprintln!("Ignoring invalid value {bots} for KITTUI_BOTS, {error}");
+                    default_config.bots
+                }),
+                Err(_) => default_config.bots,
+            },
+            prompt_filter: PromptFilter {
+                length: read_env_var("KITTUI_PROMPT_LENGTH"),
+                difficulty: read_env_var("KITTUI_PROMPT_DIFFICULTY"),
+                language: env::var("KITTUI_PROMPT_LANGUAGE")
+                    .ok()
+                    .filter(|language| !language.is_empty()),
+            },
+        };
+
+        config.with_valid_grace_period()
+    }
+
+    /// A disconnected user cannot return after the session is dropped,
+    /// so they cannot keep their place in a game for longer than that
+    fn with_valid_grace_period(mut self) -> Self {
+        if self.disconnect_grace_period > self.resume_timeout {
+            eprintln!(
+                "KITTUI_DISCONNECT_GRACE_PERIOD of {}s is longer than KITTUI_RESUME_TIMEOUT, using {}s",
+                self.disconnect_grace_period, self.resume_timeout
+            );
+            self.disconnect_grace_period = self.resume_timeout;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grace_period_is_limited_to_the_resume_timeout() {
+        let config = ServerConfig {
+            resume_timeout: 20,
+            disconnect_grace_period: 45,
+            ..ServerConfig::default()
+        }
+        .with_valid_grace_period();
+        assert_eq!(config.disconnect_grace_period, 20);
+
+        let config = ServerConfig {
+            resume_timeout: 60,
+            disconnect_grace_period: 30,
+            ..ServerConfig::default()
+        }
+        .with_valid_grace_period();
+        assert_eq!(config.disconnect_grace_period, 30);
+    }
+
+    #[test]
+    fn bots_are_parsed_from_a_list() {
+        let bots = BotSettings::parse_list(" Whiskers:35, Mr:Fluffy:200 ,").unwrap();
+
+        let bots = bots
+            .iter()
+            .map(|bot| (bot.name.as_str(), bot.wpm))
+            .collect::<Vec<_>>();
+        assert_eq!(bots, vec![("Whiskers", 35), ("Mr:Fluffy", 200)]);
+        assert!(BotSettings::parse_list("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bots_with_bad_input_are_refused() {
+        assert!(BotSettings::parse_list("Whiskers").is_err());
+        assert!(BotSettings::parse_list(" :35").is_err());
+        assert!(BotSettings::parse_list("Whiskers:fast").is_err());
+        assert!(BotSettings::parse_list("Whiskers:-5").is_err());
+        assert!(BotSettings::parse_list("Whiskers:9").is_err());
+        assert!(BotSettings::parse_list("Whiskers:201").is_err());
+        // One bad bot refuses the whole list
+        assert!(BotSettings::parse_list("Whiskers:35,Mittens").is_err());
+    }
+}
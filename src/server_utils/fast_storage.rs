@@ -3,10 +3,24 @@ use tokio::sync::{
     RwLock,
 };
 
-use crate::models::{self, GameStatus, User};
+use crate::{
+    errors::{AccountError, ChatError, Error, GameError, StorageError, TransportError},
+    models::{self, GameStatus, User},
+    server_utils::{
+        accounts::{is_nickname_allowed, Account},
+        anti_cheat::{TypingRecord, Violation},
+        config::ServerConfig,
+        generate_room_code, generate_token, leaderboard,
+        prompt_corpus::{Prompt, PromptCorpus, PromptFilter},
+        rating::Rating,
+        sanitize_name,
+        storage::{GameRecord, MemoryStorage, Storage},
+    },
+};
 use std::{
     collections,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{self, SystemTime, UNIX_EPOCH},
 };
 
 /// Currently connected users.
@@ -15,13 +29,102 @@ use std::{
 pub struct UserConnection {
     sender: mpsc::UnboundedSender<models::WSServerMessage>,
     data: models::User,
+    /// Token with which the session can be resumed, a new token is issued on every connection
+    resume_token: String,
+}
+
+/// Seconds between the game being created and the race starting
+pub const GAME_START_DELAY: u64 = 10;
+
+/// Slowest typing speed, in words per minute, that is given enough time to complete a race
+const MINIMUM_WPM: u64 = 20;
+
+/// Keystrokes arriving out of order after which a user is flagged
+/// A few can be caused by a slow network, so a single one is only logged
+const FLAGGED_TIME_REGRESSIONS: u32 = 3;
+
+/// A race never lasts less than this, irrespective of the prompt length
+const MINIMUM_GAME_DURATION: u64 = 30;
+
+/// Number of races sent in the history of a user
+const HISTORY_LENGTH: usize = 20;
+
+/// Number of the latest races over which the averages in the statistics are calculated
+const RECENT_RACES_FOR_STATS: usize = 10;
+
+/// Largest difference of ratings between the players matched by the ranked queue
+const MATCH_WINDOW: f64 = 100.0;
+
+/// The window is widened by `MATCH_WINDOW_GROWTH` every `MATCH_WINDOW_GROWTH_INTERVAL` seconds
+/// of waiting, so that players with unusual ratings are matched eventually
+const MATCH_WINDOW_GROWTH: f64 = 50.0;
+const MATCH_WINDOW_GROWTH_INTERVAL: u64 = 10;
+
+/// Most chat messages a user can send within `CHAT_RATE_WINDOW` seconds
+const CHAT_RATE_LIMIT: usize = 5;
+const CHAT_RATE_WINDOW: u64 = 10;
+
+/// Used to assign a unique id to every game
+static NEXT_GAME_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Used to assign a unique id to every challenge
+static NEXT_CHALLENGE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Used to assign a unique id to every race lobby
+static NEXT_RACE_ID: AtomicUsize = AtomicUsize::new(1);
+
+pub fn current_timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Words per minute, a word is considered to be 5 characters
+pub fn calculate_wpm(characters_typed: usize, duration_millis: u64) -> f32 {
+    let minutes = duration_millis.max(1) as f32 / 60_000.0;
+    (characters_typed as f32 / 5.0) / minutes
+}
+
+/// Percentage of the prompt that has been typed
+fn progress_percentage(characters_typed: usize, prompt_length: usize) -> u16 {
+    (characters_typed * 100 / prompt_length.max(1)) as u16
+}
+
+/// Result of validating the keystrokes sent by a user
+pub struct ProgressUpdate {
+    /// Progress of the user when the game status was last broadcast
+    pub previous_progress: u16,
+    pub progress: u16,
+    pub disqualified: bool,
+    /// The reason for which the keystrokes were rejected
+    pub violation: Option<Violation>,
+    /// Position of the prompt at which the next keystroke is expected
+    pub cursor: usize,
+    /// All the users have either completed the prompt or are disqualified
+    pub is_race_completed: bool,
 }
 
 /// Details of users who are currently in a game
 #[derive(Clone)]
 pub struct UserGameData {
     progress: u16,
+    /// Progress of the user which was sent in the latest `GameUpdate`
+    broadcast_progress: u16,
+    typing: TypingRecord,
+    /// Live statistics reported by the client, relayed to the other users
+    stats: models::TypingStats,
+    /// Reason for which the user was flagged or disqualified, shown in the game result
+    note: Option<String>,
+    /// Number of keystrokes which arrived after a later keystroke
+    time_regressions: u32,
+    disqualified: bool,
+    /// Unix timestamp in milliseconds at which the user completed the prompt
+    finished_at: Option<u64>,
+    /// Unix timestamp at which the user lost the connection, None while connected
+    disconnected_at: Option<u64>,
     user_id: String,
+    display_name: String,
     sender: mpsc::UnboundedSender<models::WSServerMessage>,
 }
 
@@ -29,64 +132,355 @@ impl UserGameData {
     pub fn new(user: &UserConnection) -> Self {
         Self {
             progress: 0,
+            broadcast_progress: 0,
+            typing: TypingRecord::default(),
+            stats: models::TypingStats::default(),
+            note: None,
+            time_regressions: 0,
+            disqualified: false,
+            finished_at: None,
+            disconnected_at: None,
             user_id: user.data.id.to_owned(),
+            display_name: user.data.display_name.to_owned(),
             sender: user.sender.clone(),
         }
     }
 }
 
+/// A user watching a game in which they are not racing
+#[derive(Clone)]
+struct Spectator {
+    user_id: String,
+    sender: mpsc::UnboundedSender<models::WSServerMessage>,
+}
+
 #[derive(Clone)]
 pub struct GameData {
     pub id: String,
     pub users: Vec<UserGameData>,
+    spectators: Vec<Spectator>,
     pub status: GameStatus,
     pub prompt_text: String,
+    pub prompt_author: Option<String>,
+    pub prompt_source: Option<String>,
     pub starts_at: u64,
+    /// Matched by the ranked queue, the ratings of the users change with the result
+    pub ranked: bool,
+    /// The users as they were when the game was created
+    participants: Vec<models::User>,
 }
 
 impl GameData {
-    pub fn new(users: Vec<UserGameData>) -> Self {
-        // Generate a prompt text, maybe call an api or store all the quotes in a json file
-        let prompt_text = "To wear your heart on your sleeve isn't a very good plan; you should wear it inside, where it functions best.".to_string();
-
-        // Start the game after 5 seconds
+    pub fn new(users: &[UserConnection], prompt: Prompt, ranked: bool) -> Self {
+        // Start the game after `GAME_START_DELAY` seconds
         let current_timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
 
-        let starts_at = current_timestamp + 10;
+        let starts_at = current_timestamp + GAME_START_DELAY;
 
-        let game_id = format!("{}{}", users[0].user_id, users[1].user_id);
+        let game_id = NEXT_GAME_ID.fetch_add(1, Ordering::Relaxed).to_string();
 
         Self {
             id: game_id,
-            users,
+            users: users.iter().map(UserGameData::new).collect(),
+            participants: users
+                .iter()
+                .map(|user_connection| user_connection.user().clone())
+                .collect(),
+            spectators: vec![],
             status: GameStatus::Init,
-            prompt_text,
+            prompt_text: prompt.text,
+            prompt_author: prompt.author,
+            prompt_source: prompt.source,
             starts_at,
+            ranked,
+        }
+    }
+
+    /// The `GameInit` message which informs the users about the game
+    pub fn init_message(&self) -> models::WSServerMessage {
+        self.init_message_at(0)
+    }
+
+    /// The `GameInit` message for a user who rejoins the game, `cursor` is where they resume typing
+    fn init_message_at(&self, cursor: usize) -> models::WSServerMessage {
+        models::WSServerMessage::GameInit {
+            game_id: self.id.clone(),
+            prompt_text: self.prompt_text.clone(),
+            prompt_author: self.prompt_author.clone(),
+            prompt_source: self.prompt_source.clone(),
+            starts_at: self.starts_at,
+            participants: self.participants.clone(),
+            ranked: self.ranked,
+            cursor,
+        }
+    }
+
+    /// Seconds after the start of the race, after which the race is forcefully finished
+    /// This is the time taken to type the prompt at `MINIMUM_WPM`
+    pub fn time_limit(&self) -> u64 {
+        let words = self.prompt_text.chars().count() as u64 / 5;
+        (words * 60 / MINIMUM_WPM).max(MINIMUM_GAME_DURATION)
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.users
+            .iter()
+            .all(|user| user.finished_at.is_some() || user.disqualified)
+    }
+
+    fn send_to_user(&self, user_id: &str, message: models::WSServerMessage) {
+        if let Some(user) = self.users.iter().find(|user| user.user_id == user_id) {
+            user.sender.send(message).ok();
+        }
+    }
+
+    /// Send the message to all the users of the game except `user_id`
+    fn send_to_other_users(&self, user_id: &str, message: models::WSServerMessage) {
+        self.users
+            .iter()
+            .filter(|user| user.user_id != user_id)
+            .for_each(|user| {
+                user.sender.send(message.clone()).ok();
+            });
+    }
+
+    fn send_to_spectators(&self, message: models::WSServerMessage) {
+        self.spectators.iter().for_each(|spectator| {
+            spectator.sender.send(message.clone()).ok();
+        });
+    }
+
+    /// Progress of every user in the game, as sent in `GameUpdate`
+    fn racer_progress(&self) -> Vec<models::RacerProgress> {
+        self.users
+            .iter()
+            .map(|user| models::RacerProgress {
+                user_id: user.user_id.to_owned(),
+                display_name: user.display_name.to_owned(),
+                progress: user.progress,
+                cursor: user.typing.correct_prefix_length(),
+                stats: user.stats,
+                disconnected: user.disconnected_at.is_some(),
+            })
+            .collect()
+    }
+
+    /// Rank the users of the game
+    /// Users who completed the prompt are ranked by their finishing time, followed by the
+    /// users who could not complete, ranked by their progress.
+    /// Disqualified users are always ranked last
+    pub fn placements(&self, finished_at: u64) -> Vec<models::Placement> {
+        let started_at = self.starts_at * 1000;
+        let prompt_length = self.prompt_text.chars().count();
+
+        let mut ranked_users = self.users.iter().collect::<Vec<_>>();
+        ranked_users.sort_by(|user1, user2| {
+            user1.disqualified.cmp(&user2.disqualified).then(
+                match (user1.finished_at, user2.finished_at) {
+                    (Some(time1), Some(time2)) => time1.cmp(&time2),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => user2.progress.cmp(&user1.progress),
+                },
+            )
+        });
+
+        ranked_users
+            .into_iter()
+            .enumerate()
+            .map(|(index, user)| {
+                let characters_typed = prompt_length * usize::from(user.progress) / 100;
+                let race_duration = user
+                    .finished_at
+                    .unwrap_or(finished_at)
+                    .saturating_sub(started_at);
+
+                models::Placement {
+                    position: index + 1,
+                    user_id: user.user_id.to_owned(),
+                    display_name: user.display_name.to_owned(),
+                    progress: user.progress,
+                    finished_at: user.finished_at,
+                    wpm: calculate_wpm(characters_typed, race_duration),
+                    accuracy: user.typing.accuracy(),
+                    note: user.note.clone(),
+                    disqualified: user.disqualified,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A challenge that is waiting for the opponent to accept or reject it
+#[derive(Clone)]
+pub struct PendingChallenge {
+    pub id: usize,
+    pub from_user_id: String,
+    pub to_user_id: String,
+    /// Unix timestamp after which the challenge expires
+    pub expires_at: u64,
+}
+
+impl PendingChallenge {
+    pub fn new(from_user_id: &str, to_user_id: &str, timeout: u64) -> Self {
+        let current_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        Self {
+            id: NEXT_CHALLENGE_ID.fetch_add(1, Ordering::Relaxed),
+            from_user_id: from_user_id.to_owned(),
+            to_user_id: to_user_id.to_owned(),
+            expires_at: current_timestamp + timeout,
         }
     }
+
+    fn involves(&self, user_id: &str) -> bool {
+        self.from_user_id == user_id || self.to_user_id == user_id
+    }
+}
+
+/// A user waiting in the ranked queue for an opponent
+pub struct QueuedPlayer {
+    user_id: String,
+    rating: f64,
+    joined_at: time::Instant,
+}
+
+impl QueuedPlayer {
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Largest difference of rating which the player accepts, it grows with the time waited
+    fn match_window(&self) -> f64 {
+        let intervals_waited = self.joined_at.elapsed().as_secs() / MATCH_WINDOW_GROWTH_INTERVAL;
+        MATCH_WINDOW + MATCH_WINDOW_GROWTH * intervals_waited as f64
+    }
+}
+
+/// Users gathered by the owner of a race, the race starts when the owner decides
+#[derive(Clone)]
+pub struct RaceLobbyData {
+    pub id: String,
+    pub owner_id: String,
+    /// Users who have joined the race, including the owner
+    pub participant_ids: Vec<String>,
+    /// Users who have been invited by the owner, but have not joined yet
+    pub invited_ids: Vec<String>,
+    /// Code with which anyone can join, if the lobby is a private room
+    /// A private room is not closed when its race starts, and its participants only see each other
+    pub code: Option<String>,
+}
+
+impl RaceLobbyData {
+    pub fn new(owner_id: &str) -> Self {
+        Self {
+            id: NEXT_RACE_ID.fetch_add(1, Ordering::Relaxed).to_string(),
+            owner_id: owner_id.to_owned(),
+            participant_ids: vec![owner_id.to_owned()],
+            invited_ids: vec![],
+            code: None,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.participant_ids.len() >= models::MAXIMUM_RACERS
+    }
+
+    pub fn has_participant(&self, user_id: &str) -> bool {
+        self.participant_ids
+            .iter()
+            .any(|participant_id| participant_id == user_id)
+    }
 }
 
 impl UserConnection {
     pub fn new(user: models::User, sender: mpsc::UnboundedSender<models::WSServerMessage>) -> Self {
-        Self { sender, data: user }
+        Self {
+            sender,
+            data: user,
+            resume_token: generate_token(),
+        }
+    }
+
+    pub fn user(&self) -> &models::User {
+        &self.data
+    }
+
+    pub fn resume_token(&self) -> &str {
+        &self.resume_token
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.data.status == models::UserStatus::Busy
     }
 }
 
 /// A simple storage service ( not S3 )
 /// This holds the user connections and user data
-#[derive(Default)]
+/// Data which has to outlive the server is handed over to the `Storage`
 pub struct BlazinglyFastDb {
     users: UserConnections,
     games: GameDetails,
+    challenges: PendingChallenges,
+    race_lobbies: RaceLobbies,
+    suspended_sessions: SuspendedSessions,
+    ranked_queue: RankedQueue,
+    chat_times: ChatTimes,
+    storage: Box<dyn Storage>,
+    prompts: PromptCorpus,
+    config: ServerConfig,
+    /// Unix timestamp in milliseconds at which the server started
+    started_at: u64,
+}
+
+impl Default for BlazinglyFastDb {
+    fn default() -> Self {
+        Self::new(
+            PromptCorpus::default(),
+            Box::<MemoryStorage>::default(),
+            ServerConfig::default(),
+        )
+    }
 }
 
 type UserConnections = RwLock<collections::HashMap<String, UserConnection>>;
+/// Users who lost their connection, the key is the resume token
+type SuspendedSessions = RwLock<collections::HashMap<String, models::User>>;
 type GameDetails = RwLock<collections::HashMap<String, GameData>>;
+type PendingChallenges = RwLock<collections::HashMap<usize, PendingChallenge>>;
+type RaceLobbies = RwLock<collections::HashMap<String, RaceLobbyData>>;
+/// Players waiting for a ranked game, in the order in which they joined
+type RankedQueue = RwLock<Vec<QueuedPlayer>>;
+/// Unix timestamps in milliseconds of the recent chat messages of every user, the oldest first
+type ChatTimes = RwLock<collections::HashMap<String, collections::VecDeque<u64>>>;
 
 impl BlazinglyFastDb {
+    pub fn new(prompts: PromptCorpus, storage: Box<dyn Storage>, config: ServerConfig) -> Self {
+        Self {
+            users: UserConnections::default(),
+            games: GameDetails::default(),
+            challenges: PendingChallenges::default(),
+            race_lobbies: RaceLobbies::default(),
+            ranked_queue: RankedQueue::default(),
+            chat_times: ChatTimes::default(),
+            suspended_sessions: SuspendedSessions::default(),
+            storage,
+            prompts,
+            config,
+            started_at: current_timestamp_millis(),
+        }
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
     pub async fn insert_new_user_connection(&self, user_connection: UserConnection) {
         self.users
             .write()
@@ -94,23 +488,385 @@ impl BlazinglyFastDb {
             .insert(user_connection.data.id.clone(), user_connection);
     }
 
-    /// Boradcast the current user status to all connected users
+    /// Names of suspended sessions are also taken, they are restored when the user resumes
+    /// Nicknames of registered players are reserved even while they are offline
+    pub async fn is_display_name_taken(&self, display_name: &str) -> bool {
+        let lowercase_name = display_name.to_lowercase();
+
+        self.users.read().await.values().any(|user_connection| {
+            user_connection.data.display_name.to_lowercase() == lowercase_name
+        }) || self
+            .suspended_sessions
+            .read()
+            .await
+            .values()
+            .any(|user| user.display_name.to_lowercase() == lowercase_name)
+            // The name is not handed out if the storage cannot be checked
+            || self
+                .storage
+                .is_nickname_registered(display_name)
+                .await
+                .unwrap_or(true)
+    }
+
+    pub async fn account_count(&self) -> Result<usize, Error> {
+        self.storage.account_count().await
+    }
+
+    /// Register the nickname, which must not be used by any connected guest either
+    /// Returns the account along with its token, which only the client of the player keeps
+    pub async fn register_account(&self, nickname: &str) -> Result<(Account, String), Error> {
+        let nickname = sanitize_name(nickname).ok_or(AccountError::InvalidNickname)?;
+        if !is_nickname_allowed(&nickname) {
+            return Err(AccountError::NicknameNotAllowed { nickname }.into());
+        }
+        if self.is_display_name_taken(&nickname).await {
+            return Err(AccountError::NicknameTaken { nickname }.into());
+        }
+
+        let token = generate_token();
+        let account = self.storage.create_account(&nickname, &token).await?;
+        Ok((account, token))
+    }
+
+    /// Find the account to which the token was issued
+    /// A session of the account which is waiting to be resumed is given up, as the
+    /// player is back through a new client
+    pub async fn login_account(&self, token: &str) -> Result<Account, Error> {
+        let account = self
+            .storage
+            .find_account_by_token(token)
+            .await?
+            .ok_or(AccountError::UnknownToken)?;
+
+        if self.users.read().await.contains_key(&account.id) {
+            return Err(AccountError::AccountInUse {
+                nickname: account.nickname,
+            }
+            .into());
+        }
+
+        self.suspended_sessions
+            .write()
+            .await
+            .retain(|_, user| user.id != account.id);
+
+        Ok(account)
+    }
+
+    async fn is_registered(&self, user_id: &str) -> Result<bool, Error> {
+        Ok(self.storage.find_account_by_id(user_id).await?.is_some())
+    }
+
+    /// Rating of the user, `None` for guests as they are not rated
+    pub async fn rating_of(&self, user_id: &str) -> Result<Option<Rating>, Error> {
+        if self.is_registered(user_id).await? {
+            Ok(Some(self.storage.rating_of(user_id).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Results of all the races finished by the user, the oldest first
+    /// Guests get new ids when the server restarts, so only the races since the start are theirs
+    async fn races_of_user(&self, user_id: &str) -> Result<Vec<models::RaceSummary>, Error> {
+        let since = if self.is_registered(user_id).await? {
+            0
+        } else {
+            self.started_at
+        };
+
+        Ok(self
+            .storage
+            .games_of_user(user_id)
+            .await?
+            .iter()
+            .filter(|game| game.finished_at >= since)
+            .filter_map(|game| game.summary_for(user_id))
+            .collect())
+    }
+
+    /// The latest `HISTORY_LENGTH` races of the user, the most recent first
+    pub async fn race_history(&self, user_id: &str) -> Result<Vec<models::RaceSummary>, Error> {
+        let races = self.races_of_user(user_id).await?;
+
+        Ok(races.into_iter().rev().take(HISTORY_LENGTH).collect())
+    }
+
+    pub async fn player_stats(&self, user_id: &str) -> Result<models::PlayerStats, Error> {
+        let races = self.races_of_user(user_id).await?;
+
+        let best_wpm = races
+            .iter()
+            .filter(|race| race.completed && !race.disqualified)
+            .map(|race| race.wpm)
+            .fold(0.0, f32::max);
+
+        let recent_races = races
+            .iter()
+            .rev()
+            .filter(|race| !race.disqualified)
+            .take(RECENT_RACES_FOR_STATS)
+            .collect::<Vec<_>>();
+        let average = |value: fn(&models::RaceSummary) -> f32| {
+            if recent_races.is_empty() {
+                0.0
+            } else {
+                recent_races.iter().map(|race| value(race)).sum::<f32>() / recent_races.len() as f32
+            }
+        };
+
+        Ok(models::PlayerStats {
+            races: races.len(),
+            wins: races
+                .iter()
+                .filter(|race| race.position == 1 && !race.disqualified)
+                .count(),
+            best_wpm,
+            recent_races: recent_races.len(),
+            average_wpm: average(|race| race.wpm),
+            average_accuracy: average(|race| race.accuracy),
+            rating: self
+                .rating_of(user_id)
+                .await?
+                .map(|rating| rating.display_value()),
+        })
+    }
+
+    pub async fn leaderboard(
+        &self,
+        category: models::LeaderboardCategory,
+        period: models::LeaderboardPeriod,
+    ) -> Result<Vec<models::LeaderboardEntry>, Error> {
+        let since = leaderboard::period_start(period, current_timestamp_millis());
+        let games = self.storage.games_since(since).await?;
+        let ratings = self.storage.ratings().await?;
+        let accounts = self.storage.accounts().await?;
+
+        Ok(leaderboard::rank_players(
+            category, &games, &ratings, &accounts,
+        ))
+    }
+
+    pub async fn is_in_ranked_queue(&self, user_id: &str) -> bool {
+        self.ranked_queue
+            .read()
+            .await
+            .iter()
+            .any(|queued_player| queued_player.user_id == user_id)
+    }
+
+    pub async fn join_ranked_queue(&self, user_id: &str, rating: Rating) {
+        self.ranked_queue.write().await.push(QueuedPlayer {
+            user_id: user_id.to_owned(),
+            rating: rating.value,
+            joined_at: time::Instant::now(),
+        });
+    }
+
+    /// Returns false if the user was not in the queue
+    pub async fn leave_ranked_queue(&self, user_id: &str) -> bool {
+        let mut ranked_queue = self.ranked_queue.write().await;
+        let queue_length = ranked_queue.len();
+        ranked_queue.retain(|queued_player| queued_player.user_id != user_id);
+
+        ranked_queue.len() != queue_length
+    }
+
+    /// Put a matched player back in the queue, in the place they had when they joined
+    /// The time already waited still counts towards the match window
+    pub async fn return_to_ranked_queue(&self, queued_player: QueuedPlayer) {
+        let mut ranked_queue = self.ranked_queue.write().await;
+        let index = ranked_queue
+            .iter()
+            .position(|waiting_player| waiting_player.joined_at > queued_player.joined_at)
+            .unwrap_or(ranked_queue.len());
+        ranked_queue.insert(index, queued_player);
+    }
+
+    /// Take the pairs of players in the ranked queue whose ratings are close enough
+    /// The players who have waited the longest are matched first, with the closest opponent
+    pub async fn take_ranked_matches(&self) -> Vec<(QueuedPlayer, QueuedPlayer)> {
+        let mut ranked_queue = self.ranked_queue.write().await;
+        let mut matches = vec![];
+
+        let mut index = 0;
+        while index < ranked_queue.len() {
+            let player = &ranked_queue[index];
+            // The players after `index` joined later, so their windows are not wider
+            let opponent_index = ranked_queue
+                .iter()
+                .enumerate()
+                .skip(index + 1)
+                .map(|(opponent_index, opponent)| {
+                    (opponent_index, (opponent.rating - player.rating).abs())
+                })
+                .filter(|(_, rating_difference)| *rating_difference <= player.match_window())
+                .min_by(|(_, first_difference), (_, second_difference)| {
+                    first_difference.total_cmp(second_difference)
+                })
+                .map(|(opponent_index, _)| opponent_index);
+
+            match opponent_index {
+                Some(opponent_index) => {
+                    let opponent = ranked_queue.remove(opponent_index);
+                    let player = ranked_queue.remove(index);
+                    matches.push((player, opponent));
+                }
+                None => index += 1,
+            }
+        }
+
+        matches
+    }
+
+    /// Change the ratings of the two users of a ranked game by its result
+    async fn update_ratings(
+        &self,
+        game: &GameData,
+        placements: &[models::Placement],
+    ) -> Result<(), Error> {
+        let [winner, loser] = placements else {
+            return Ok(());
+        };
+
+        // Nobody wins if both the users were disqualified
+        let winner_score = if winner.disqualified && loser.disqualified {
+            0.5
+        } else {
+            1.0
+        };
+        let winner_rating = self.storage.rating_of(&winner.user_id).await?;
+        let loser_rating = self.storage.rating_of(&loser.user_id).await?;
+
+        let rating_changes = [
+            (
+                winner,
+                winner_rating,
+                winner_rating.after_race(&loser_rating, winner_score),
+            ),
+            (
+                loser,
+                loser_rating,
+                loser_rating.after_race(&winner_rating, 1.0 - winner_score),
+            ),
+        ];
+
+        for (placement, previous_rating, rating) in rating_changes {
+            self.storage.save_rating(&placement.user_id, rating).await?;
+
+            let display_rating = rating.display_value();
+            if let Some(user_connection) = self.users.write().await.get_mut(&placement.user_id) {
+                user_connection.data.rating = Some(display_rating);
+            }
+            self.suspended_sessions
+                .write()
+                .await
+                .values_mut()
+                .filter(|user| user.id == placement.user_id)
+                .for_each(|user| user.rating = Some(display_rating));
+
+            let rating_update_message = models::WSServerMessage::RatingUpdate {
+                rating: display_rating,
+                change: display_rating as i32 - previous_rating.display_value() as i32,
+            };
+            game.send_to_user(&placement.user_id, rating_update_message);
+        }
+
+        Ok(())
+    }
+
+    /// Code of the private room of every user who has joined one
+    async fn room_codes(&self) -> collections::HashMap<String, String> {
+        self.race_lobbies
+            .read()
+            .await
+            .values()
+            .filter_map(|race_lobby| Some((race_lobby.code.as_ref()?, race_lobby)))
+            .flat_map(|(code, race_lobby)| {
+                race_lobby
+                    .participant_ids
+                    .iter()
+                    .map(|participant_id| (participant_id.clone(), code.clone()))
+            })
+            .collect()
+    }
+
+    pub async fn is_in_room(&self, user_id: &str) -> bool {
+        self.room_codes().await.contains_key(user_id)
+    }
+
+    /// Returns true if both the users are in the same private room, or neither is in one
+    /// Users can only see, challenge and invite the users they share a room with
+    pub async fn share_room(&self, user_id: &str, other_user_id: &str) -> bool {
+        let room_codes = self.room_codes().await;
+        room_codes.get(user_id) == room_codes.get(other_user_id)
+    }
+
+    /// Boradcast the current user status and the live games to all connected users
+    /// The users of a private room only see each other and their games, and are hidden from the rest
     pub async fn boradcast_status(&self) {
+        let room_codes = self.room_codes().await;
         let read_lock = self.users.read().await;
         let all_users = read_lock
             .values()
             .map(|user_connection| user_connection.data.to_owned())
             .collect::<Vec<models::User>>();
 
-        let status_message = models::WSServerMessage::UserStatus {
-            connected_users: all_users,
-        };
-
-        eprintln!("boradcasting status {status_message:?}");
+        let live_games = self
+            .games
+            .read()
+            .await
+            .values()
+            .map(|game| {
+                let racer_ids = game
+                    .users
+                    .iter()
+                    .map(|user| user.user_id.clone())
+                    .collect::<Vec<_>>();
+                let live_game = models::LiveGame {
+                    game_id: game.id.clone(),
+                    racer_names: game
+                        .users
+                        .iter()
+                        .map(|user| user.display_name.clone())
+                        .collect(),
+                    starts_at: game.starts_at,
+                    ranked: game.ranked,
+                };
+                (racer_ids, live_game)
+            })
+            .collect::<Vec<_>>();
 
         read_lock.values().for_each(|user_connection| {
-            user_connection.sender.send(status_message.clone()).ok();
+            let room_code = room_codes.get(&user_connection.data.id);
+            let connected_users = all_users
+                .iter()
+                .filter(|user| room_codes.get(&user.id) == room_code)
+                .cloned()
+                .collect();
+            let live_games = live_games
+                .iter()
+                .filter(|(racer_ids, _)| {
+                    racer_ids
+                        .iter()
+                        .all(|racer_id| room_codes.get(racer_id) == room_code)
+                })
+                .map(|(_, live_game)| live_game.clone())
+                .collect();
+
+            let status_message = models::WSServerMessage::UserStatus {
+                connected_users,
+                live_games,
+            };
+            user_connection.sender.send(status_message).ok();
         });
+
+        eprintln!(
+            "boradcasted status of {} users and {} games",
+            all_users.len(),
+            live_games.len()
+        );
     }
 
     pub async fn get_user_by_id(&self, user_id: &str) -> Option<User> {
@@ -129,66 +885,1162 @@ impl BlazinglyFastDb {
         read_lock.get(user_id).cloned()
     }
 
-    pub async fn send_message_to_user(&self, user_id: &str, message: models::WSServerMessage) {
-        // handle gracefully, the caller should be notified that message was not sent to user
-        if let Some(user_connection) = self.get_user_connection_by_id(user_id).await {
-            user_connection.sender.send(message).unwrap();
-        }
+    /// Fails if the user is disconnected, the caller decides whether that matters
+    pub async fn send_message_to_user(
+        &self,
+        user_id: &str,
+        message: models::WSServerMessage,
+    ) -> Result<(), Error> {
+        let user_connection = self
+            .get_user_connection_by_id(user_id)
+            .await
+            .ok_or_else(|| StorageError::UserNotFound {
+                user_id: user_id.to_owned(),
+            })?;
+
+        user_connection.sender.send(message).map_err(|_| {
+            TransportError::ChannelClosed {
+                user_id: user_id.to_owned(),
+            }
+            .into()
+        })
     }
 
     pub async fn delete_user_connection(&self, user_id: &str) {
         self.users.write().await.remove(user_id);
     }
 
+    /// Returns true if `sender` belongs to the connection through which the user is connected
+    /// A resumed session replaces the connection, while the old one might not have been closed yet
+    pub async fn is_current_connection(
+        &self,
+        user_id: &str,
+        sender: &mpsc::UnboundedSender<models::WSServerMessage>,
+    ) -> bool {
+        self.users
+            .read()
+            .await
+            .get(user_id)
+            .is_some_and(|user_connection| user_connection.sender.same_channel(sender))
+    }
+
+    /// Remove the connection of the user, keeping the user so that the session can be resumed
+    /// Returns the token with which the session can be resumed
+    pub async fn suspend_user_connection(&self, user_id: &str) -> Option<String> {
+        let user_connection = self.users.write().await.remove(user_id)?;
+
+        self.suspended_sessions
+            .write()
+            .await
+            .insert(user_connection.resume_token.clone(), user_connection.data);
+        Some(user_connection.resume_token)
+    }
+
+    /// The session cannot be resumed anymore
+    pub async fn remove_suspended_session(&self, resume_token: &str) {
+        if let Some(user) = self.suspended_sessions.write().await.remove(resume_token) {
+            eprintln!("Session of user_id: {} has expired", user.id);
+        }
+    }
+
+    /// Find the user to whom the resume token was issued
+    ///
+    /// The session may still be connected if the server has not yet noticed that the
+    /// connection was lost, the new connection then replaces it
+    pub async fn resume_session(&self, resume_token: &str) -> Option<models::User> {
+        if let Some(user) = self.suspended_sessions.write().await.remove(resume_token) {
+            return Some(user);
+        }
+
+        self.users
+            .read()
+            .await
+            .values()
+            .find(|user_connection| user_connection.resume_token == resume_token)
+            .map(|user_connection| user_connection.data.clone())
+    }
+
+    /// Send the messages of the game in which the user is racing to the new connection
+    /// The other users are informed if the user had lost the connection
+    /// Returns the id of the game, if the user is in one
+    pub async fn attach_user_to_game(
+        &self,
+        user_id: &str,
+        sender: &mpsc::UnboundedSender<models::WSServerMessage>,
+    ) -> Option<String> {
+        let mut locked_games = self.games.write().await;
+        let game = locked_games
+            .values_mut()
+            .find(|game| game.users.iter().any(|user| user.user_id == user_id))?;
+
+        let user_data = game.users.iter_mut().find(|user| user.user_id == user_id)?;
+        user_data.sender = sender.clone();
+
+        if user_data.disconnected_at.take().is_some() {
+            let reconnected_message = models::WSServerMessage::OpponentReconnected {
+                user_id: user_id.to_owned(),
+                display_name: user_data.display_name.clone(),
+            };
+            game.send_to_other_users(user_id, reconnected_message);
+        }
+
+        Some(game.id.clone())
+    }
+
+    /// Send the state of the game to the user, whose new client does not know about the game
+    /// The user resumes typing after the characters which the server has validated
+    pub async fn send_game_state(&self, user_id: &str, game_id: &str) {
+        let locked_games = self.games.read().await;
+        let Some(game) = locked_games.get(game_id) else {
+            return;
+        };
+        let Some(user_data) = game.users.iter().find(|user| user.user_id == user_id) else {
+            return;
+        };
+
+        let cursor = user_data.typing.correct_prefix_length();
+        user_data.sender.send(game.init_message_at(cursor)).ok();
+        let game_update_message = models::WSServerMessage::GameUpdate {
+            racers: game.racer_progress(),
+        };
+        user_data.sender.send(game_update_message).ok();
+    }
+
+    /// Mark the user as disconnected in the game they are racing in, and inform the other users
+    /// Returns the id of the game and the time of disconnection, if the user has to return
+    /// before the grace period to avoid forfeiting the race
+    pub async fn disconnect_user_from_game(&self, user_id: &str) -> Option<(String, u64)> {
+        let mut locked_games = self.games.write().await;
+        let game = locked_games
+            .values_mut()
+            .find(|game| game.users.iter().any(|user| user.user_id == user_id))?;
+
+        let user_data = game.users.iter_mut().find(|user| user.user_id == user_id)?;
+
+        // Nothing is lost by leaving once the prompt is completed or the user is disqualified
+        if user_data.finished_at.is_some() || user_data.disqualified {
+            return None;
+        }
+
+        let disconnected_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        user_data.disconnected_at = Some(disconnected_at);
+
+        let disconnected_message = models::WSServerMessage::OpponentDisconnected {
+            user_id: user_id.to_owned(),
+            display_name: user_data.display_name.clone(),
+            forfeits_at: disconnected_at + self.config.disconnect_grace_period,
+        };
+        game.send_to_other_users(user_id, disconnected_message);
+
+        Some((game.id.clone(), disconnected_at))
+    }
+
+    /// Disqualify the user if they have not reconnected since `disconnected_at`
+    /// The game is finished if nobody else is left racing
+    pub async fn forfeit_disconnected_user(
+        &self,
+        game_id: &str,
+        user_id: &str,
+        disconnected_at: u64,
+    ) {
+        let is_race_completed = {
+            let mut locked_games = self.games.write().await;
+            let Some(game) = locked_games.get_mut(game_id) else {
+                return;
+            };
+            let Some(user_data) = game.users.iter_mut().find(|user| user.user_id == user_id) else {
+                return;
+            };
+
+            // The user came back, and maybe lost the connection again later
+            if user_data.disconnected_at != Some(disconnected_at) {
+                return;
+            }
+
+            eprintln!("user_id: {user_id} forfeits game_id: {game_id} after disconnecting");
+            user_data.disqualified = true;
+            user_data.note = Some("Forfeited: did not reconnect in time".to_string());
+
+            game.is_completed()
+        };
+
+        if is_race_completed {
+            self.finish_game(game_id).await;
+        } else {
+            self.broadcase_game_status(game_id).await;
+        }
+    }
+
     pub async fn insert_game(&self, game: GameData) {
         let mut locked_games = self.games.write().await;
         locked_games.insert(game.id.clone(), game);
     }
 
-    pub async fn update_game_progress(&self, game_id: &str, user_id: &str, progress: u16) {
+    pub async fn insert_challenge(&self, challenge: PendingChallenge) {
+        self.challenges
+            .write()
+            .await
+            .insert(challenge.id, challenge);
+    }
+
+    /// Returns true if the user has raised a challenge or has been challenged
+    /// and the challenge is still waiting for a response
+    pub async fn has_pending_challenge(&self, user_id: &str) -> bool {
+        self.challenges
+            .read()
+            .await
+            .values()
+            .any(|challenge| challenge.involves(user_id))
+    }
+
+    /// Remove the challenge raised by `from_user_id` against `to_user_id`
+    pub async fn remove_challenge(
+        &self,
+        from_user_id: &str,
+        to_user_id: &str,
+    ) -> Option<PendingChallenge> {
+        let mut locked_challenges = self.challenges.write().await;
+        let challenge_id = locked_challenges
+            .values()
+            .find(|challenge| {
+                challenge.from_user_id == from_user_id && challenge.to_user_id == to_user_id
+            })
+            .map(|challenge| challenge.id)?;
+
+        locked_challenges.remove(&challenge_id)
+    }
+
+    pub async fn remove_challenge_by_id(&self, challenge_id: usize) -> Option<PendingChallenge> {
+        self.challenges.write().await.remove(&challenge_id)
+    }
+
+    /// Remove all the challenges raised by or against the user
+    pub async fn remove_challenges_of_user(&self, user_id: &str) -> Vec<PendingChallenge> {
+        let mut locked_challenges = self.challenges.write().await;
+        let challenge_ids = locked_challenges
+            .values()
+            .filter(|challenge| challenge.involves(user_id))
+            .map(|challenge| challenge.id)
+            .collect::<Vec<_>>();
+
+        challenge_ids
+            .iter()
+            .filter_map(|challenge_id| locked_challenges.remove(challenge_id))
+            .collect()
+    }
+
+    /// Inform both the users if the challenge is still waiting for a response
+    pub async fn expire_challenge(&self, challenge_id: usize) {
+        if let Some(challenge) = self.remove_challenge_by_id(challenge_id).await {
+            let challenge_expired_message = models::WSServerMessage::ChallengeExpired {
+                from_user_id: challenge.from_user_id.clone(),
+                to_user_id: challenge.to_user_id.clone(),
+            };
+
+            // A user who left in the meantime does not need to know
+            self.send_message_to_user(&challenge.from_user_id, challenge_expired_message.clone())
+                .await
+                .ok();
+            self.send_message_to_user(&challenge.to_user_id, challenge_expired_message)
+                .await
+                .ok();
+        }
+    }
+
+    /// Remove all the challenges of a user who is leaving
+    /// Challenges raised by the user are cancelled, challenges against the user are rejected
+    pub async fn withdraw_challenges_of_user(&self, user_id: &str) {
+        let Some(user_details) = self.get_user_by_id(user_id).await else {
+            return;
+        };
+
+        for challenge in self.remove_challenges_of_user(user_id).await {
+            if challenge.from_user_id == user_id {
+                let message = models::WSServerMessage::ChallengeCancelled {
+                    by_user: user_details.clone(),
+                };
+                self.send_message_to_user(&challenge.to_user_id, message)
+                    .await
+                    .ok();
+            } else {
+                let message = models::WSServerMessage::ChallengeRejected {
+                    by_user: user_details.clone(),
+                };
+                self.send_message_to_user(&challenge.from_user_id, message)
+                    .await
+                    .ok();
+            }
+        }
+    }
+
+    /// Insert the race lobby, replacing the previous state of the lobby if any
+    pub async fn save_race_lobby(&self, race_lobby: RaceLobbyData) {
+        self.race_lobbies
+            .write()
+            .await
+            .insert(race_lobby.id.clone(), race_lobby);
+    }
+
+    pub async fn get_race_lobby(&self, race_id: &str) -> Option<RaceLobbyData> {
+        self.race_lobbies.read().await.get(race_id).cloned()
+    }
+
+    pub async fn remove_race_lobby(&self, race_id: &str) -> Option<RaceLobbyData> {
+        self.race_lobbies.write().await.remove(race_id)
+    }
+
+    /// Create a private room owned by the user, with a code which no other room has
+    pub async fn create_room(&self, owner_id: &str) -> RaceLobbyData {
+        let mut locked_race_lobbies = self.race_lobbies.write().await;
+
+        let code = loop {
+            let code = generate_room_code();
+            let is_taken = locked_race_lobbies
+                .values()
+                .any(|race_lobby| race_lobby.code.as_ref() == Some(&code));
+            if !is_taken {
+                break code;
+            }
+        };
+
+        let room = RaceLobbyData {
+            code: Some(code),
+            ..RaceLobbyData::new(owner_id)
+        };
+        locked_race_lobbies.insert(room.id.clone(), room.clone());
+        room
+    }
+
+    /// The private room with the code, which is matched ignoring the case
+    pub async fn find_room(&self, code: &str) -> Option<RaceLobbyData> {
+        let code = code.trim().to_uppercase();
+        self.race_lobbies
+            .read()
+            .await
+            .values()
+            .find(|race_lobby| race_lobby.code.as_ref() == Some(&code))
+            .cloned()
+    }
+
+    /// The race lobby which the user has joined, a user can be in only one lobby at a time
+    pub async fn find_race_lobby_of_user(&self, user_id: &str) -> Option<RaceLobbyData> {
+        self.race_lobbies
+            .read()
+            .await
+            .values()
+            .find(|race_lobby| race_lobby.has_participant(user_id))
+            .cloned()
+    }
+
+    /// Send the current state of the race lobby to all of its participants
+    pub async fn broadcast_race_lobby(&self, race_id: &str) {
+        let Some(race_lobby) = self.get_race_lobby(race_id).await else {
+            return;
+        };
+
+        let mut participants = vec![];
+        for participant_id in race_lobby.participant_ids.iter() {
+            if let Some(participant) = self.get_user_by_id(participant_id).await {
+                participants.push(participant);
+            }
+        }
+
+        let lobby_update_message = models::WSServerMessage::RaceLobbyUpdate {
+            lobby: models::RaceLobby {
+                race_id: race_lobby.id.clone(),
+                owner_id: race_lobby.owner_id.clone(),
+                participants,
+                code: race_lobby.code.clone(),
+            },
+        };
+
+        for participant_id in race_lobby.participant_ids.iter() {
+            self.send_message_to_user(participant_id, lobby_update_message.clone())
+                .await
+                .ok();
+        }
+    }
+
+    /// Remove the user from the race lobby they have joined
+    /// If the user is the owner, the lobby is closed for all the participants
+    pub async fn leave_race_lobby(&self, user_id: &str) {
+        let Some(mut race_lobby) = self.find_race_lobby_of_user(user_id).await else {
+            return;
+        };
+        let is_room = race_lobby.code.is_some();
+
+        if race_lobby.owner_id == user_id {
+            self.remove_race_lobby(&race_lobby.id).await;
+
+            let lobby_closed_message = models::WSServerMessage::RaceLobbyClosed {
+                race_id: race_lobby.id.clone(),
+            };
+            for participant_id in race_lobby.participant_ids.iter() {
+                self.send_message_to_user(participant_id, lobby_closed_message.clone())
+                    .await
+                    .ok();
+            }
+        } else {
+            race_lobby
+                .participant_ids
+                .retain(|participant_id| participant_id != user_id);
+            let race_id = race_lobby.id.clone();
+            self.save_race_lobby(race_lobby).await;
+
+            let lobby_closed_message = models::WSServerMessage::RaceLobbyClosed {
+                race_id: race_id.clone(),
+            };
+            self.send_message_to_user(user_id, lobby_closed_message)
+                .await
+                .ok();
+            self.broadcast_race_lobby(&race_id).await;
+        }
+
+        // The user is visible outside the room again, and so are the others if the room is closed
+        if is_room {
+            self.boradcast_status().await;
+        }
+    }
+
+    /// Pick a prompt for a new game, recently used prompts are avoided
+    pub fn pick_prompt(&self, filter: &PromptFilter) -> Prompt {
+        self.prompts.pick(filter)
+    }
+
+    /// Mark the game as started, progress updates are accepted only after this
+    /// The spectators are informed here, the racers by the caller
+    pub async fn start_game(&self, game_id: &str) {
+        if let Some(game) = self.games.write().await.get_mut(game_id) {
+            game.status = GameStatus::InProgress;
+            game.send_to_spectators(models::WSServerMessage::GameStart);
+        }
+    }
+
+    /// Add the user to the spectators of the game, leaving the game they were spectating
+    /// Returns the `SpectatorInit` message with the current state of the game
+    pub async fn spectate_game(
+        &self,
+        game_id: &str,
+        user_id: &str,
+    ) -> Result<models::WSServerMessage, Error> {
+        let user_connection = self
+            .get_user_connection_by_id(user_id)
+            .await
+            .ok_or_else(|| StorageError::UserNotFound {
+                user_id: user_id.to_owned(),
+            })?;
+        let room_codes = self.room_codes().await;
+
         let mut locked_games = self.games.write().await;
-        let current_game = locked_games.get_mut(game_id).unwrap();
+        let is_racing = locked_games
+            .values()
+            .any(|game| game.users.iter().any(|user| user.user_id == user_id));
+        if is_racing {
+            return Err(GameError::RacerCannotSpectate {
+                game_id: game_id.to_owned(),
+            }
+            .into());
+        }
+        // The games of a private room are only visible to the users in it
+        let is_visible = locked_games.get(game_id).is_some_and(|game| {
+            game.users
+                .iter()
+                .all(|racer| room_codes.get(&racer.user_id) == room_codes.get(user_id))
+        });
+        if !is_visible {
+            return Err(StorageError::GameNotFound {
+                game_id: game_id.to_owned(),
+            }
+            .into());
+        }
+
+        locked_games.values_mut().for_each(|game| {
+            game.spectators
+                .retain(|spectator| spectator.user_id != user_id)
+        });
+
+        let game = locked_games
+            .get_mut(game_id)
+            .ok_or_else(|| StorageError::GameNotFound {
+                game_id: game_id.to_owned(),
+            })?;
+        game.spectators.push(Spectator {
+            user_id: user_id.to_owned(),
+            sender: user_connection.sender,
+        });
+
+        Ok(models::WSServerMessage::SpectatorInit {
+            game_id: game.id.clone(),
+            prompt_text: game.prompt_text.clone(),
+            prompt_author: game.prompt_author.clone(),
+            prompt_source: game.prompt_source.clone(),
+            starts_at: game.starts_at,
+            racers: game.racer_progress(),
+        })
+    }
+
+    /// Returns false if the user was not spectating any game
+    pub async fn stop_spectating(&self, user_id: &str) -> bool {
+        let mut is_spectating = false;
+
+        for game in self.games.write().await.values_mut() {
+            let spectator_count = game.spectators.len();
+            game.spectators
+                .retain(|spectator| spectator.user_id != user_id);
+            is_spectating |= game.spectators.len() != spectator_count;
+        }
+
+        is_spectating
+    }
+
+    /// Validate the keystrokes of the user and update the progress in the game
+    /// Fails if the game is not in progress or the user is not a part of it
+    pub async fn update_game_progress(
+        &self,
+        game_id: &str,
+        user_id: &str,
+        keystrokes: &[models::Keystroke],
+        stats: models::TypingStats,
+    ) -> Result<ProgressUpdate, Error> {
+        let mut locked_games = self.games.write().await;
+        let current_game =
+            locked_games
+                .get_mut(game_id)
+                .ok_or_else(|| StorageError::GameNotFound {
+                    game_id: game_id.to_owned(),
+                })?;
+
+        if current_game.status != GameStatus::InProgress {
+            return Err(GameError::NotInProgress {
+                game_id: game_id.to_owned(),
+            }
+            .into());
+        }
+
+        let prompt = current_game.prompt_text.chars().collect::<Vec<_>>();
+        let actual_elapsed =
+            current_timestamp_millis().saturating_sub(current_game.starts_at * 1000);
 
         let user_data = current_game
             .users
             .iter_mut()
             .find(|user| user.user_id == user_id)
-            .unwrap();
+            .ok_or_else(|| GameError::NotAParticipant {
+                game_id: game_id.to_owned(),
+                user_id: user_id.to_owned(),
+            })?;
+
+        let previous_progress = user_data.broadcast_progress;
+
+        // Progress of a disqualified user is not updated anymore
+        // A rejected keystroke does not stop the rest of the keystrokes, unless it disqualifies the user
+        let mut violation = None;
+        if !user_data.disqualified {
+            user_data.stats = stats;
+            for keystroke in keystrokes {
+                if let Err(keystroke_violation) =
+                    user_data.typing.apply(&prompt, keystroke, actual_elapsed)
+                {
+                    if matches!(keystroke_violation, Violation::TimeRegression { .. }) {
+                        user_data.time_regressions += 1;
+                    }
+                    let is_severe = keystroke_violation.is_severe();
+                    if is_severe || violation.is_none() {
+                        violation = Some(keystroke_violation);
+                    }
+                    if is_severe {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(violation) = violation.as_ref() {
+            eprintln!("Violation by user_id: {user_id} in game_id: {game_id}, {violation}");
+            // A keystroke ahead of the cursor is sent again by the client, it is not suspicious
+            if violation.is_severe() {
+                user_data.disqualified = true;
+                user_data.note = Some(format!("Disqualified: {violation}"));
+            }
+        }
+        if user_data.time_regressions >= FLAGGED_TIME_REGRESSIONS && !user_data.disqualified {
+            user_data.note = Some(format!(
+                "Flagged: {} keystrokes arrived out of order",
+                user_data.time_regressions
+            ));
+        }
+
+        user_data.progress =
+            progress_percentage(user_data.typing.correct_prefix_length(), prompt.len());
+        if user_data.progress == 100 && user_data.finished_at.is_none() {
+            user_data.finished_at = Some(current_timestamp_millis());
+        }
+
+        Ok(ProgressUpdate {
+            previous_progress,
+            progress: user_data.progress,
+            disqualified: user_data.disqualified,
+            violation,
+            cursor: user_data.typing.cursor(),
+            is_race_completed: current_game.is_completed(),
+        })
+    }
+
+    /// Mark all the users busy for a new game, if every one of them is connected and available
+    /// The check and the update happen under a single lock, so no user can land in two games
+    ///
+    /// Returns the users as they were before being marked busy
+    pub async fn reserve_players(&self, user_ids: &[String]) -> Result<Vec<UserConnection>, Error> {
+        let mut users = self.users.write().await;
+
+        let mut user_connections = vec![];
+        for user_id in user_ids {
+            let user_connection = users
+                .get(user_id)
+                .ok_or_else(|| StorageError::UserNotFound {
+                    user_id: user_id.to_owned(),
+                })?;
+            user_connections.push(user_connection.clone());
+        }
 
-        user_data.progress = progress;
+        let busy_display_names = user_connections
+            .iter()
+            .filter(|user_connection| user_connection.is_busy())
+            .map(|user_connection| user_connection.data.display_name.clone())
+            .collect::<Vec<_>>();
+        if !busy_display_names.is_empty() {
+            return Err(GameError::PlayersBusy {
+                display_names: busy_display_names,
+            }
+            .into());
+        }
+
+        for user_id in user_ids {
+            if let Some(user_connection) = users.get_mut(user_id) {
+                user_connection.data.status = models::UserStatus::Busy;
+            }
+        }
+
+        Ok(user_connections)
+    }
+
+    pub async fn set_user_status(&self, user_id: &str, status: models::UserStatus) {
+        if let Some(user_connection) = self.users.write().await.get_mut(user_id) {
+            user_connection.data.status = status;
+        }
     }
 
-    pub async fn find_game_progress(&self, game_id: &str, user_id: &str) -> u16 {
+    /// End the game and inform the results to all the users of the game
+    ///
+    /// The game is removed from the storage, so this does nothing if the game
+    /// has already been finished
+    pub async fn finish_game(&self, game_id: &str) {
+        let Some(mut game) = self.games.write().await.remove(game_id) else {
+            return;
+        };
+        game.status = GameStatus::Finished;
+
+        let finished_at = current_timestamp_millis();
+        let placements = game.placements(finished_at);
+
+        for user in game.users.iter() {
+            let (wpm, accuracy) = placements
+                .iter()
+                .find(|placement| placement.user_id == user.user_id)
+                .map(|placement| (placement.wpm, placement.accuracy))
+                .unwrap_or_default();
+
+            let game_over_message = models::WSServerMessage::GameOver {
+                placements: placements.clone(),
+                wpm,
+                accuracy,
+            };
+            user.sender.send(game_over_message).ok();
+
+            self.set_user_status(&user.user_id, models::UserStatus::Available)
+                .await;
+        }
+
+        game.send_to_spectators(models::WSServerMessage::SpectatedGameOver {
+            game_id: game_id.to_string(),
+            placements: placements.clone(),
+        });
+
+        if game.ranked {
+            if let Err(error) = self.update_ratings(&game, &placements).await {
+                eprintln!("Could not update the ratings of the game {game_id}, {error}");
+            }
+        }
+
+        let game_record = GameRecord {
+            game_id: game_id.to_string(),
+            prompt_text: game.prompt_text.clone(),
+            started_at: game.starts_at,
+            finished_at,
+            placements,
+            ranked: game.ranked,
+        };
+        if let Err(error) = self.storage.record_game(game_record).await {
+            eprintln!("Could not record the game {game_id}, {error}");
+        }
+
+        self.boradcast_status().await;
+    }
+
+    /// Send the progress of every user in the game to all the users and spectators of the game
+    pub async fn broadcase_game_status(&self, game_id: &str) {
         let mut locked_games = self.games.write().await;
-        let current_game = locked_games.get_mut(game_id).unwrap();
+        let Some(current_game) = locked_games.get_mut(game_id) else {
+            return;
+        };
 
-        let user_data = current_game
+        current_game
             .users
             .iter_mut()
-            .find(|user| user.user_id == user_id)
-            .unwrap();
+            .for_each(|user| user.broadcast_progress = user.progress);
+
+        let game_update_message = models::WSServerMessage::GameUpdate {
+            racers: current_game.racer_progress(),
+        };
 
-        user_data.progress
+        // The sender of a disconnected user may be closed, they get the state when they return
+        current_game.users.iter().for_each(|user| {
+            user.sender.send(game_update_message.clone()).ok();
+        });
+        current_game.send_to_spectators(game_update_message);
     }
 
-    pub async fn broadcase_game_status(&self, game_id: &str) {
+    /// Remember that the user chatted at `current_time`, a unix timestamp in milliseconds
+    /// Fails without remembering it if the user has reached `CHAT_RATE_LIMIT` within the window
+    async fn record_chat_time(&self, user_id: &str, current_time: u64) -> Result<(), Error> {
+        let window_start = current_time.saturating_sub(CHAT_RATE_WINDOW * 1000);
+        let mut chat_times = self.chat_times.write().await;
+        // Users who have not chatted within the window are forgotten
+        chat_times.retain(|_, times| times.back().is_some_and(|&time| time > window_start));
+
+        let times = chat_times.entry(user_id.to_owned()).or_default();
+        times.retain(|&time| time > window_start);
+        if let Some(&oldest) = times.front().filter(|_| times.len() >= CHAT_RATE_LIMIT) {
+            let retry_after = (oldest / 1000 + CHAT_RATE_WINDOW)
+                .saturating_sub(current_time / 1000)
+                .max(1);
+            return Err(ChatError::RateLimited { retry_after }.into());
+        }
+        times.push_back(current_time);
+        Ok(())
+    }
+
+    /// Send the chat message of the user to the racers and spectators of the game they are racing in,
+    /// or to everyone they can see if they are not racing
+    /// Fails if the message is empty or too long, or the user has sent too many messages recently
+    pub async fn send_chat(&self, user_id: &str, text: &str) -> Result<(), Error> {
+        let text = text
+            .chars()
+            .filter(|character| !character.is_control())
+            .collect::<String>()
+            .trim()
+            .to_string();
+
+        let length = text.chars().count();
+        if length == 0 {
+            return Err(ChatError::Empty.into());
+        }
+        if length > models::MAXIMUM_CHAT_LENGTH {
+            return Err(ChatError::TooLong { length }.into());
+        }
+
+        let current_time = current_timestamp_millis();
+        self.record_chat_time(user_id, current_time).await?;
+
+        let user =
+            self.get_user_by_id(user_id)
+                .await
+                .ok_or_else(|| StorageError::UserNotFound {
+                    user_id: user_id.to_owned(),
+                })?;
+        let chat_message = |game_id: Option<String>| models::WSServerMessage::Chat {
+            from_user_id: user.id.clone(),
+            display_name: user.display_name.clone(),
+            text: text.clone(),
+            game_id,
+            sent_at: current_time,
+        };
+
         let locked_games = self.games.read().await;
-        let current_game = locked_games.get(game_id).unwrap();
+        let racing_game = locked_games
+            .values()
+            .find(|game| game.users.iter().any(|racer| racer.user_id == user_id));
 
-        let user1 = &current_game.users[0];
-        let user2 = &current_game.users[1];
+        match racing_game {
+            Some(game) => {
+                let message = chat_message(Some(game.id.clone()));
+                game.users.iter().for_each(|racer| {
+                    racer.sender.send(message.clone()).ok();
+                });
+                game.send_to_spectators(message);
+            }
+            None => {
+                // The games are not needed for the lobby, and the users are locked before them
+                drop(locked_games);
 
-        let user1_message = models::WSServerMessage::GameUpdate {
-            my_progress: user1.progress,
-            opponent_progress: user2.progress,
+                // Users in a private room chat with the room
+                let room_codes = self.room_codes().await;
+                let message = chat_message(None);
+                self.users
+                    .read()
+                    .await
+                    .values()
+                    .filter(|user_connection| {
+                        room_codes.get(&user_connection.data.id) == room_codes.get(user_id)
+                    })
+                    .for_each(|user_connection| {
+                        user_connection.sender.send(message.clone()).ok();
+                    });
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the progress of every user to the spectators of the game
+    /// Spectators follow the cursors, so they are updated on every keystroke, unlike the racers
+    pub async fn update_spectators(&self, game_id: &str) {
+        let locked_games = self.games.read().await;
+        let Some(current_game) = locked_games.get(game_id) else {
+            return;
         };
 
-        let user2_message = models::WSServerMessage::GameUpdate {
-            my_progress: user2.progress,
-            opponent_progress: user1.progress,
+        if !current_game.spectators.is_empty() {
+            current_game.send_to_spectators(models::WSServerMessage::GameUpdate {
+                racers: current_game.racer_progress(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(value: f64) -> Rating {
+        Rating { value, races: 0 }
+    }
+
+    fn user_connection(
+        user_id: &str,
+    ) -> (
+        UserConnection,
+        mpsc::UnboundedReceiver<models::WSServerMessage>,
+    ) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let user = User {
+            id: user_id.to_string(),
+            status: models::UserStatus::Available,
+            display_name: format!("Player {user_id}"),
+            rating: None,
+            bot: false,
         };
+        (UserConnection::new(user, sender), receiver)
+    }
+
+    /// Insert a started game of the users, returns its id, the prompt and the receivers of the users
+    async fn running_game(
+        db: &BlazinglyFastDb,
+        user_ids: &[&str],
+    ) -> (
+        String,
+        Vec<char>,
+        Vec<mpsc::UnboundedReceiver<models::WSServerMessage>>,
+    ) {
+        let (users, receivers): (Vec<_>, Vec<_>) = user_ids
+            .iter()
+            .map(|user_id| user_connection(user_id))
+            .unzip();
+        let game = GameData::new(&users, db.pick_prompt(&PromptFilter::default()), false);
+        let game_id = game.id.clone();
+        let prompt = game.prompt_text.chars().collect();
+        db.insert_game(game).await;
+        db.start_game(&game_id).await;
+
+        (game_id, prompt, receivers)
+    }
+
+    fn keystroke(position: usize, character: char, elapsed: u64) -> models::Keystroke {
+        models::Keystroke {
+            position,
+            character,
+            elapsed,
+        }
+    }
+
+    async fn note_of(db: &BlazinglyFastDb, game_id: &str, user_id: &str) -> Option<String> {
+        let locked_games = db.games.read().await;
+        let user_data = locked_games[game_id]
+            .users
+            .iter()
+            .find(|user| user.user_id == user_id)
+            .unwrap();
+        user_data.note.clone()
+    }
+
+    fn user_ids(matches: &[(QueuedPlayer, QueuedPlayer)]) -> Vec<(&str, &str)> {
+        matches
+            .iter()
+            .map(|(player, opponent)| (player.user_id(), opponent.user_id()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn closest_ratings_are_matched_in_the_order_of_joining() {
+        let db = BlazinglyFastDb::default();
+        db.join_ranked_queue("1", rating(1500.0)).await;
+        db.join_ranked_queue("2", rating(1590.0)).await;
+        db.join_ranked_queue("3", rating(1520.0)).await;
+        db.join_ranked_queue("4", rating(1600.0)).await;
+
+        let matches = db.take_ranked_matches().await;
+        assert_eq!(user_ids(&matches), vec![("1", "3"), ("2", "4")]);
+        assert!(!db.is_in_ranked_queue("1").await);
+    }
+
+    #[tokio::test]
+    async fn distant_ratings_are_not_matched() {
+        let db = BlazinglyFastDb::default();
+        db.join_ranked_queue("1", rating(1200.0)).await;
+        db.join_ranked_queue("2", rating(1800.0)).await;
+
+        assert!(db.take_ranked_matches().await.is_empty());
+        assert!(db.is_in_ranked_queue("1").await);
+        assert!(db.is_in_ranked_queue("2").await);
+    }
+
+    #[tokio::test]
+    async fn only_the_players_of_a_room_are_in_it() {
+        let db = BlazinglyFastDb::default();
+        db.create_room("1").await;
+        db.save_race_lobby(RaceLobbyData::new("2")).await;
+
+        assert!(db.is_in_room("1").await);
+        assert!(!db.is_in_room("2").await);
+        assert!(!db.is_in_room("3").await);
+    }
+
+    #[tokio::test]
+    async fn returned_player_keeps_their_place_in_the_queue() {
+        let db = BlazinglyFastDb::default();
+        db.join_ranked_queue("1", rating(1500.0)).await;
+        db.join_ranked_queue("2", rating(1510.0)).await;
+        db.join_ranked_queue("3", rating(1000.0)).await;
+
+        let mut matches = db.take_ranked_matches().await;
+        assert_eq!(user_ids(&matches), vec![("1", "2")]);
+
+        // The opponent was gone, so the player waits again ahead of the later players
+        let (player, _) = matches.remove(0);
+        db.return_to_ranked_queue(player).await;
+        db.join_ranked_queue("4", rating(1505.0)).await;
+
+        let matches = db.take_ranked_matches().await;
+        assert_eq!(user_ids(&matches), vec![("1", "4")]);
+        assert!(db.is_in_ranked_queue("3").await);
+    }
+
+    #[tokio::test]
+    async fn rejoining_user_resumes_after_the_validated_characters() {
+        let db = BlazinglyFastDb::default();
+        let (game_id, prompt, mut receivers) = running_game(&db, &["1", "2"]).await;
+
+        // Two correct characters followed by a wrong one
+        let wrong_character = if prompt[2] == '#' { '%' } else { '#' };
+        let keystrokes = [
+            keystroke(0, prompt[0], 0),
+            keystroke(1, prompt[1], 200),
+            keystroke(2, wrong_character, 400),
+        ];
+        db.update_game_progress(&game_id, "1", &keystrokes, models::TypingStats::default())
+            .await
+            .unwrap();
+
+        db.send_game_state("1", &game_id).await;
+        let Ok(models::WSServerMessage::GameInit { cursor, .. }) = receivers[0].try_recv() else {
+            panic!("Not a game init message");
+        };
+        assert_eq!(cursor, 2);
+    }
+
+    #[tokio::test]
+    async fn keystroke_ahead_of_the_cursor_does_not_flag_the_user() {
+        let db = BlazinglyFastDb::default();
+        let (game_id, prompt, _receivers) = running_game(&db, &["1", "2"]).await;
+
+        // The update with the first keystroke was lost
+        let progress_update = db
+            .update_game_progress(
+                &game_id,
+                "1",
+                &[keystroke(1, prompt[1], 200)],
+                models::TypingStats::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            progress_update.violation,
+            Some(Violation::InvalidPosition { .. })
+        ));
+        assert!(!progress_update.disqualified);
+        assert_eq!(note_of(&db, &game_id, "1").await, None);
+    }
+
+    #[tokio::test]
+    async fn repeated_keystrokes_out_of_order_flag_the_user() {
+        let db = BlazinglyFastDb::default();
+        let (game_id, prompt, _receivers) = running_game(&db, &["1", "2"]).await;
+
+        let mut keystrokes = vec![keystroke(0, prompt[0], 1000)];
+        for elapsed in [900, 800] {
+            keystrokes.push(keystroke(1, prompt[1], elapsed));
+        }
+        db.update_game_progress(&game_id, "1", &keystrokes, models::TypingStats::default())
+            .await
+            .unwrap();
+        assert_eq!(note_of(&db, &game_id, "1").await, None);
+
+        let keystrokes = [keystroke(1, prompt[1], 700)];
+        let progress_update = db
+            .update_game_progress(&game_id, "1", &keystrokes, models::TypingStats::default())
+            .await
+            .unwrap();
+        assert!(!progress_update.disqualified);
+        assert!(note_of(&db, &game_id, "1")
+            .await
+            .is_some_and(|note| note.starts_with("Flagged")));
+    }
+
+    #[tokio::test]
+    async fn sixth_chat_message_within_the_window_is_refused() {
+        let db = BlazinglyFastDb::default();
+        let (user, mut receiver) = user_connection("1");
+        db.insert_new_user_connection(user).await;
+
+        for index in 0..CHAT_RATE_LIMIT {
+            db.send_chat("1", &format!("meow {index}")).await.unwrap();
+        }
+        let error = db.send_chat("1", "one meow too many").await.unwrap_err();
+        assert!(matches!(error, Error::Chat(ChatError::RateLimited { .. })));
+
+        let mut chat_messages = 0;
+        while let Ok(message) = receiver.try_recv() {
+            if matches!(message, models::WSServerMessage::Chat { .. }) {
+                chat_messages += 1;
+            }
+        }
+        assert_eq!(chat_messages, CHAT_RATE_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn chat_is_allowed_again_once_the_oldest_message_leaves_the_window() {
+        let db = BlazinglyFastDb::default();
+        let start = 1_000_000_000;
+        for index in 0..CHAT_RATE_LIMIT as u64 {
+            db.record_chat_time("1", start + index * 1000)
+                .await
+                .unwrap();
+        }
+        db.record_chat_time("2", start).await.unwrap();
+
+        // The oldest message leaves the window 10 seconds after it was sent
+        let error = db.record_chat_time("1", start + 5_500).await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Chat(ChatError::RateLimited { retry_after: 5 })
+        ));
+        let error = db.record_chat_time("1", start + 9_999).await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Chat(ChatError::RateLimited { retry_after: 1 })
+        ));
+
+        db.record_chat_time("1", start + 10_000).await.unwrap();
+        let error = db.record_chat_time("1", start + 10_500).await.unwrap_err();
+        assert!(matches!(error, Error::Chat(ChatError::RateLimited { .. })));
+
+        // Users who have not chatted within the window are forgotten
+        assert!(!db.chat_times.read().await.contains_key("2"));
+    }
+
+    #[tokio::test]
+    async fn chat_message_longer_than_the_limit_is_refused() {
+        let db = BlazinglyFastDb::default();
+        let (user, _receiver) = user_connection("1");
+        db.insert_new_user_connection(user).await;
+
+        let text = "m".repeat(models::MAXIMUM_CHAT_LENGTH + 1);
+        let error = db.send_chat("1", &text).await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Chat(ChatError::TooLong { length }) if length == models::MAXIMUM_CHAT_LENGTH + 1
+        ));
+
+        let text = "m".repeat(models::MAXIMUM_CHAT_LENGTH);
+        db.send_chat("1", &text).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn chat_message_without_visible_characters_is_refused() {
+        let db = BlazinglyFastDb::default();
+        let (user, _receiver) = user_connection("1");
+        db.insert_new_user_connection(user).await;
+
+        for text in ["", "   ", "\u{7}\u{1b}\n\t"] {
+            let error = db.send_chat("1", text).await.unwrap_err();
+            assert!(matches!(error, Error::Chat(ChatError::Empty)));
+        }
+    }
+
+    #[test]
+    fn finishers_are_placed_by_time_then_the_others_by_progress_and_disqualified_last() {
+        let users = ["slow", "fast", "partial", "behind", "cheater"]
+            .map(|user_id| user_connection(user_id).0);
+        let mut game = GameData::new(
+            &users,
+            PromptCorpus::default().pick(&PromptFilter::default()),
+            false,
+        );
+        let started_at = game.starts_at * 1000;
+        for user in game.users.iter_mut() {
+            let (progress, finished_after) = match user.user_id.as_str() {
+                "slow" => (100, Some(40_000)),
+                "fast" => (100, Some(30_000)),
+                "partial" => (60, None),
+                "behind" => (30, None),
+                // Disqualified users are last even if they had finished first
+                _ => {
+                    user.disqualified = true;
+                    (100, Some(10_000))
+                }
+            };
+            user.progress = progress;
+            user.finished_at = finished_after.map(|duration| started_at + duration);
+        }
 
-        user1.sender.send(user1_message).unwrap();
-        user2.sender.send(user2_message).unwrap();
+        let placements = game.placements(started_at + 60_000);
+        let order = placements
+            .iter()
+            .map(|placement| (placement.position, placement.user_id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                (1, "fast"),
+                (2, "slow"),
+                (3, "partial"),
+                (4, "behind"),
+                (5, "cheater")
+            ]
+        );
+        assert!(placements[4].disqualified);
     }
 }
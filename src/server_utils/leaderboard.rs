@@ -0,0 +1,258 @@
+use std::collections;
+
+use crate::{
+    models::{LeaderboardCategory, LeaderboardEntry, LeaderboardPeriod},
+    server_utils::{accounts::Account, rating::Rating, storage::GameRecord},
+};
+
+/// Number of players listed in a leaderboard
+const LEADERBOARD_LENGTH: usize = 100;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// Unix timestamp in milliseconds from which the races are considered for the period
+pub fn period_start(period: LeaderboardPeriod, current_timestamp: u64) -> u64 {
+    match period {
+        LeaderboardPeriod::Daily => current_timestamp.saturating_sub(DAY_MILLIS),
+        LeaderboardPeriod::Weekly => current_timestamp.saturating_sub(7 * DAY_MILLIS),
+        LeaderboardPeriod::AllTime => 0,
+    }
+}
+
+/// Rank the registered players by the category, over the games of the period
+/// Guests are left out, their ids are not kept across restarts of the server
+pub fn rank_players(
+    category: LeaderboardCategory,
+    games: &[GameRecord],
+    ratings: &collections::HashMap<String, Rating>,
+    accounts: &[Account],
+) -> Vec<LeaderboardEntry> {
+    let nicknames = accounts
+        .iter()
+        .map(|account| (account.id.as_str(), account.nickname.as_str()))
+        .collect::<collections::HashMap<_, _>>();
+
+    let mut values = collections::HashMap::<&str, f32>::new();
+    let placements = games.iter().flat_map(|game| {
+        game.placements
+            .iter()
+            .map(move |placement| (game, placement))
+    });
+
+    for (game, placement) in placements {
+        let user_id = placement.user_id.as_str();
+        if !nicknames.contains_key(user_id) {
+            continue;
+        }
+
+        match category {
+            LeaderboardCategory::BestWpm => {
+                if placement.finished_at.is_some() && !placement.disqualified {
+                    let best_wpm = values.entry(user_id).or_default();
+                    *best_wpm = best_wpm.max(placement.wpm);
+                }
+            }
+            LeaderboardCategory::Rating => {
+                if let Some(rating) = ratings.get(user_id).filter(|_| game.ranked) {
+                    values.insert(user_id, rating.display_value() as f32);
+                }
+            }
+            LeaderboardCategory::MostRaces => *values.entry(user_id).or_default() += 1.0,
+        }
+    }
+
+    let mut entries = values
+        .into_iter()
+        .map(|(user_id, value)| (user_id, nicknames[user_id], value))
+        .collect::<Vec<_>>();
+    entries.sort_by(
+        |(_, first_name, first_value), (_, second_name, second_value)| {
+            second_value
+                .total_cmp(first_value)
+                .then_with(|| first_name.cmp(second_name))
+        },
+    );
+
+    entries
+        .into_iter()
+        .take(LEADERBOARD_LENGTH)
+        .enumerate()
+        .map(|(index, (user_id, display_name, value))| LeaderboardEntry {
+            position: index + 1,
+            user_id: user_id.to_owned(),
+            display_name: display_name.to_owned(),
+            value,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Placement;
+
+    fn account(id: &str, nickname: &str) -> Account {
+        Account::new(id.to_string(), nickname.to_string(), "token")
+    }
+
+    fn placement(user_id: &str, wpm: f32, finished: bool) -> Placement {
+        Placement {
+            position: 1,
+            user_id: user_id.to_string(),
+            display_name: user_id.to_string(),
+            progress: if finished { 100 } else { 50 },
+            finished_at: finished.then_some(1_000_000),
+            wpm,
+            accuracy: 100.0,
+            note: None,
+            disqualified: false,
+        }
+    }
+
+    fn game(placements: Vec<Placement>, ranked: bool) -> GameRecord {
+        GameRecord {
+            game_id: "1".to_string(),
+            prompt_text: "the cat sat on the mat".to_string(),
+            started_at: 1000,
+            finished_at: 1_000_000,
+            placements,
+            ranked,
+        }
+    }
+
+    fn names(entries: &[LeaderboardEntry]) -> Vec<(usize, &str, f32)> {
+        entries
+            .iter()
+            .map(|entry| (entry.position, entry.display_name.as_str(), entry.value))
+            .collect()
+    }
+
+    #[test]
+    fn ties_are_ordered_by_nickname() {
+        let accounts = vec![
+            account("account-1", "Mittens"),
+            account("account-2", "Shadow"),
+            account("account-3", "Bella"),
+        ];
+        let games = vec![
+            game(
+                vec![
+                    placement("account-1", 80.0, true),
+                    placement("account-2", 80.0, true),
+                ],
+                false,
+            ),
+            game(vec![placement("account-3", 80.0, true)], false),
+        ];
+
+        let entries = rank_players(
+            LeaderboardCategory::BestWpm,
+            &games,
+            &collections::HashMap::new(),
+            &accounts,
+        );
+        assert_eq!(
+            names(&entries),
+            vec![
+                (1, "Bella", 80.0),
+                (2, "Mittens", 80.0),
+                (3, "Shadow", 80.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn guests_and_unfinished_races_are_left_out_of_best_wpm() {
+        let accounts = vec![
+            account("account-1", "Mittens"),
+            account("account-2", "Bella"),
+        ];
+        let games = vec![game(
+            vec![
+                placement("account-1", 60.0, true),
+                placement("account-2", 120.0, false),
+                placement("7", 150.0, true),
+            ],
+            false,
+        )];
+
+        let entries = rank_players(
+            LeaderboardCategory::BestWpm,
+            &games,
+            &collections::HashMap::new(),
+            &accounts,
+        );
+        assert_eq!(names(&entries), vec![(1, "Mittens", 60.0)]);
+    }
+
+    #[test]
+    fn most_races_counts_every_race() {
+        let accounts = vec![
+            account("account-1", "Mittens"),
+            account("account-2", "Bella"),
+        ];
+        let games = vec![
+            game(vec![placement("account-1", 60.0, true)], false),
+            game(
+                vec![
+                    placement("account-1", 60.0, false),
+                    placement("account-2", 60.0, true),
+                ],
+                false,
+            ),
+        ];
+
+        let entries = rank_players(
+            LeaderboardCategory::MostRaces,
+            &games,
+            &collections::HashMap::new(),
+            &accounts,
+        );
+        assert_eq!(
+            names(&entries),
+            vec![(1, "Mittens", 2.0), (2, "Bella", 1.0)]
+        );
+    }
+
+    #[test]
+    fn rating_only_counts_players_who_raced_ranked() {
+        let accounts = vec![
+            account("account-1", "Mittens"),
+            account("account-2", "Bella"),
+        ];
+        let ratings = collections::HashMap::from([
+            (
+                "account-1".to_string(),
+                Rating {
+                    value: 1540.0,
+                    races: 1,
+                },
+            ),
+            (
+                "account-2".to_string(),
+                Rating {
+                    value: 1460.0,
+                    races: 1,
+                },
+            ),
+        ]);
+        let games = vec![
+            game(vec![placement("account-1", 60.0, true)], true),
+            game(vec![placement("account-2", 60.0, true)], false),
+        ];
+
+        let entries = rank_players(LeaderboardCategory::Rating, &games, &ratings, &accounts);
+        assert_eq!(names(&entries), vec![(1, "Mittens", 1540.0)]);
+    }
+
+    #[test]
+    fn periods_start_before_the_current_time() {
+        let now = 30 * DAY_MILLIS;
+        assert_eq!(period_start(LeaderboardPeriod::Daily, now), 29 * DAY_MILLIS);
+        assert_eq!(
+            period_start(LeaderboardPeriod::Weekly, now),
+            23 * DAY_MILLIS
+        );
+        assert_eq!(period_start(LeaderboardPeriod::AllTime, now), 0);
+    }
+}
@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use crate::{
+    errors::{Error, ProtocolError},
     models,
     server_utils::{
         self,
-        fast_storage::{self, GameData, UserGameData},
+        anti_cheat::Violation,
+        fast_storage::{self, GameData},
     },
 };
 use futures_util::{SinkExt, StreamExt};
@@ -21,11 +23,13 @@ use tokio_tungstenite::tungstenite::protocol;
 /// For more information
 /// https://tokio.rs/tokio/tutorial/shared-state
 ///
+/// Messages of the features which have not been negotiated with the client are dropped
 pub async fn bridge_user_websocket(
     // receiver_channel: UnboundedReceiverStream<UnboundedReceiver<protocol::Message>>,
     receiver_stream: impl StreamExt<Item = models::WSServerMessage>,
     // mut websocket_sender: SplitSink<WebSocketStream<tokio::net::TcpStream>, protocol::Message>,
     websocket_sender: impl SinkExt<protocol::Message>,
+    features: Vec<models::Feature>,
 ) {
     // Why do we need to pin?
     tokio::pin!(receiver_stream, websocket_sender);
@@ -38,8 +42,20 @@ pub async fn bridge_user_websocket(
     //     .await;
 
     while let Some(message) = receiver_stream.next().await {
+        if let Some(feature) = message.required_feature() {
+            if !features.contains(&feature) {
+                continue;
+            }
+        }
+
         eprintln!("Bridge {message:?}");
-        let stringified_message = serde_json::to_string(&message).unwrap();
+        let stringified_message = match serde_json::to_string(&message) {
+            Ok(stringified_message) => stringified_message,
+            Err(error) => {
+                eprintln!("{}", ProtocolError::Serialization(error.to_string()));
+                continue;
+            }
+        };
         websocket_sender
             .send(protocol::Message::Text(stringified_message))
             .await
@@ -47,117 +63,786 @@ pub async fn bridge_user_websocket(
     }
 }
 
+fn error_message(code: models::ErrorCode, message: &str) -> models::WSServerMessage {
+    models::WSServerMessage::Error {
+        code,
+        message: message.to_string(),
+    }
+}
+
+/// Seconds between two attempts to match the players waiting in the ranked queue
+const MATCHMAKING_INTERVAL: u64 = 1;
+
+/// Create a game for the users and schedule its start after `GAME_START_DELAY` seconds
+/// The users are marked busy till the game is over, and leave the ranked queue and the
+/// game they were spectating
+///
+/// Fails without creating the game if any of the users is disconnected or busy in another game
+///
+/// Returns the `GameInit` message which has to be sent to all the users
+async fn create_game(
+    db: Arc<fast_storage::BlazinglyFastDb>,
+    user_ids: Vec<String>,
+    ranked: bool,
+) -> Result<models::WSServerMessage, Error> {
+    // The users cannot be challenged till the game is over
+    let users = db.reserve_players(&user_ids).await?;
+
+    let prompt = db.pick_prompt(&db.config().prompt_filter);
+    let game_data = GameData::new(&users, prompt, ranked);
+
+    db.insert_game(game_data.clone()).await;
+
+    for user_id in user_ids.iter() {
+        db.leave_ranked_queue(user_id).await;
+        db.stop_spectating(user_id).await;
+    }
+    db.boradcast_status().await;
+
+    // Schedule a tokio task to inform the users about the starting of game
+    let game_id = game_data.id.clone();
+    let time_limit = game_data.time_limit();
+
+    let timeout_func = move || async move {
+        db.start_game(&game_id).await;
+        for user_id in user_ids.iter() {
+            db.send_message_to_user(user_id, models::WSServerMessage::GameStart)
+                .await
+                .ok();
+        }
+
+        // Finish the game if the users could not complete it within the time limit
+        server_utils::set_timeout(time_limit, || async move {
+            db.finish_game(&game_id).await;
+        })
+        .await;
+    };
+
+    tokio::spawn(server_utils::set_timeout(
+        fast_storage::GAME_START_DELAY,
+        timeout_func,
+    ));
+
+    // Inform the users about the starting of game
+    Ok(game_data.init_message())
+}
+
+/// The players of a private room only race each other, so a user who enters one leaves the ranked queue
+async fn leave_ranked_queue_for_room(db: &fast_storage::BlazinglyFastDb, user_id: &str) {
+    if db.leave_ranked_queue(user_id).await {
+        db.send_message_to_user(user_id, models::WSServerMessage::RankedQueueLeft)
+            .await
+            .ok();
+    }
+}
+
+/// Start a ranked game for every pair of players in the ranked queue with close ratings
+/// This runs for as long as the server is running
+pub async fn run_ranked_matchmaking(db: Arc<fast_storage::BlazinglyFastDb>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(MATCHMAKING_INTERVAL));
+
+    loop {
+        interval.tick().await;
+
+        for (player, opponent) in db.take_ranked_matches().await {
+            let user_ids = vec![player.user_id().to_owned(), opponent.user_id().to_owned()];
+
+            match create_game(db.clone(), user_ids.clone(), true).await {
+                Ok(game_init_message) => {
+                    for user_id in user_ids {
+                        db.send_message_to_user(&user_id, game_init_message.clone())
+                            .await
+                            .ok();
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Could not start the ranked game of {user_ids:?}, {error}");
+                    for queued_player in [player, opponent] {
+                        let is_available = db
+                            .get_user_connection_by_id(queued_player.user_id())
+                            .await
+                            .is_some_and(|user_connection| !user_connection.is_busy());
+
+                        if is_available {
+                            // The player who is still around waits for another opponent
+                            db.return_to_ranked_queue(queued_player).await;
+                        } else {
+                            // A busy user is out of the queue, and is told so
+                            db.send_message_to_user(
+                                queued_player.user_id(),
+                                models::WSServerMessage::RankedQueueLeft,
+                            )
+                            .await
+                            .ok();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub async fn handle_client_messages(
     text_message: &str,
     db: Arc<fast_storage::BlazinglyFastDb>,
     current_user_id: &str,
 ) {
-    let parsed_message = serde_json::from_str::<models::WSClientMessage>(text_message)
-        .expect("Unable to parse websocket message");
-    
+    let parsed_message = match models::parse_message::<models::WSClientMessage>(text_message) {
+        Ok(parsed_message) => parsed_message,
+        Err(error) => {
+            let error = Error::from(ProtocolError::MalformedMessage(error.to_string()));
+            eprintln!("Message from user_id: {current_user_id} is not valid, {error}");
+            db.send_message_to_user(current_user_id, error.to_server_message())
+                .await
+                .ok();
+            return;
+        }
+    };
+
     let (message_reply, user_ids) = match parsed_message {
+        models::WSClientMessage::Unknown => {
+            let error = Error::from(ProtocolError::UnsupportedMessage);
+            eprintln!("Message from user_id: {current_user_id} is not supported, {text_message}");
+            (
+                Some(error.to_server_message()),
+                Some(vec![current_user_id.to_string()]),
+            )
+        }
+        models::WSClientMessage::Hello { .. } => (
+            Some(error_message(
+                models::ErrorCode::InvalidAccount,
+                "Hello can only be sent right after connecting",
+            )),
+            Some(vec![current_user_id.to_string()]),
+        ),
         models::WSClientMessage::Challenge { to_user_id } => {
             // Get the user name and send the challenge to `to_user`
-            match db.get_user_by_id(current_user_id).await {
-                Some(user_details) => {
-                    let message = models::WSServerMessage::RequestForChallenge {
-                        from_user: user_details,
-                    };
-                    (Some(message), Some(vec![to_user_id]))
-                }
-                None => {
+            let current_user = db.get_user_by_id(current_user_id).await;
+            // Users in another private room are hidden, as if they were not connected
+            let opponent_user = if db.share_room(current_user_id, &to_user_id).await {
+                db.get_user_by_id(&to_user_id).await
+            } else {
+                None
+            };
+
+            match (current_user, opponent_user) {
+                (Some(_), _) if to_user_id == current_user_id => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidChallenge,
+                        "You cannot challenge yourself",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(user_details), _) if user_details.status == models::UserStatus::Busy => (
+                    Some(error_message(
+                        models::ErrorCode::UserBusy,
+                        "You cannot challenge anyone while in a game",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), Some(opponent_details))
+                    if opponent_details.status == models::UserStatus::Busy =>
+                {
+                    let message = format!("{} is busy in a game", opponent_details.display_name);
+                    (
+                        Some(error_message(models::ErrorCode::UserBusy, &message)),
+                        Some(vec![current_user_id.to_string()]),
+                    )
+                }
+                (Some(user_details), Some(opponent_details)) => {
+                    if db.has_pending_challenge(current_user_id).await {
+                        (
+                            Some(error_message(
+                                models::ErrorCode::InvalidChallenge,
+                                "Respond to your pending challenge first",
+                            )),
+                            Some(vec![current_user_id.to_string()]),
+                        )
+                    } else if db.has_pending_challenge(&to_user_id).await {
+                        let message = format!(
+                            "{} has a pending challenge, try again later",
+                            opponent_details.display_name
+                        );
+                        (
+                            Some(error_message(models::ErrorCode::InvalidChallenge, &message)),
+                            Some(vec![current_user_id.to_string()]),
+                        )
+                    } else {
+                        let challenge = fast_storage::PendingChallenge::new(
+                            current_user_id,
+                            &to_user_id,
+                            db.config().challenge_timeout,
+                        );
+                        db.insert_challenge(challenge.clone()).await;
+
+                        let challenge_created_message = models::WSServerMessage::ChallengeCreated {
+                            to_user: opponent_details,
+                            expires_at: challenge.expires_at,
+                        };
+                        db.send_message_to_user(current_user_id, challenge_created_message)
+                            .await
+                            .ok();
+
+                        // Expire the challenge if the opponent does not respond in time
+                        let db_clone = db.clone();
+                        let challenge_id = challenge.id;
+                        tokio::spawn(server_utils::set_timeout(
+                            db.config().challenge_timeout,
+                            move || async move {
+                                db_clone.expire_challenge(challenge_id).await;
+                            },
+                        ));
+
+                        let message = models::WSServerMessage::RequestForChallenge {
+                            from_user: user_details,
+                            expires_at: challenge.expires_at,
+                        };
+                        (Some(message), Some(vec![to_user_id]))
+                    }
+                }
+                (Some(_), None) => {
                     eprintln!("User not found {to_user_id}");
-                    let error_message = models::WSServerMessage::Error {
-                        message: "Requested user cannot be found or is disconnected".to_string(),
-                    };
-                    (Some(error_message), Some(vec![to_user_id]))
+                    (
+                        Some(error_message(
+                            models::ErrorCode::UserNotFound,
+                            "Requested user cannot be found or is disconnected",
+                        )),
+                        Some(vec![current_user_id.to_string()]),
+                    )
                 }
+                (None, _) => (None, None),
             }
         }
-        models::WSClientMessage::UpdateProgress { game_id, progress } => {
-            let current_progress = db.find_game_progress(&game_id, current_user_id).await;
-             db.update_game_progress(&game_id, current_user_id, progress)
-                    .await;
-            if progress < 70 && progress - current_progress < 5 {
-                eprintln!("Skipping update progress of game_id: {game_id}, current_user_id: {current_user_id}, progress: {progress}");
-            } else {
-                db.broadcase_game_status(&game_id).await;
+        models::WSClientMessage::RejectChallenge { opponent_user_id } => {
+            match db
+                .remove_challenge(&opponent_user_id, current_user_id)
+                .await
+            {
+                Some(_) => {
+                    let message = db
+                        .get_user_by_id(current_user_id)
+                        .await
+                        .map(|user_details| models::WSServerMessage::ChallengeRejected {
+                            by_user: user_details,
+                        });
+                    (message, Some(vec![opponent_user_id]))
+                }
+                None => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidChallenge,
+                        "The challenge has expired or was cancelled",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
             }
-
-            (None, None)
         }
-        models::WSClientMessage::AcceptChallenge { opponent_user_id } => {
-            // Create a game in the database
-            // user1 is the person who created the challenge
-
-            // The user can not be present if he is disconnected, what to do in that case?
-            let current_user_connection = db.get_user_connection_by_id(current_user_id).await;
-            let opponent_user_connection = db.get_user_connection_by_id(&opponent_user_id).await;
+        models::WSClientMessage::CancelChallenge { to_user_id } => {
+            match db.remove_challenge(current_user_id, &to_user_id).await {
+                Some(_) => {
+                    let message = db
+                        .get_user_by_id(current_user_id)
+                        .await
+                        .map(|user_details| models::WSServerMessage::ChallengeCancelled {
+                            by_user: user_details,
+                        });
+                    (message, Some(vec![to_user_id]))
+                }
+                None => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidChallenge,
+                        "The challenge has already expired or was answered",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+            }
+        }
+        models::WSClientMessage::CreateRace => {
+            let current_user = db.get_user_by_id(current_user_id).await;
+            let existing_race_lobby = db.find_race_lobby_of_user(current_user_id).await;
 
-            match (current_user_connection, opponent_user_connection) {
-                (Some(user1), Some(user2)) => {
-                    let user_game_data1 = UserGameData::new(&user1);
-                    let user_game_data2 = UserGameData::new(&user2);
+            match (current_user, existing_race_lobby) {
+                (Some(user_details), _) if user_details.status == models::UserStatus::Busy => (
+                    Some(error_message(
+                        models::ErrorCode::UserBusy,
+                        "You cannot create a race while in a game",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), Some(_)) => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "Leave your current race lobby first",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), None) => {
+                    let race_lobby = fast_storage::RaceLobbyData::new(current_user_id);
+                    let race_id = race_lobby.id.clone();
+                    db.save_race_lobby(race_lobby).await;
+                    db.broadcast_race_lobby(&race_id).await;
 
-                    let game_data = GameData::new(vec![user_game_data1, user_game_data2]);
+                    (None, None)
+                }
+                (None, _) => (None, None),
+            }
+        }
+        models::WSClientMessage::CreateRoom => {
+            let current_user = db.get_user_by_id(current_user_id).await;
+            let existing_race_lobby = db.find_race_lobby_of_user(current_user_id).await;
 
-                    db.insert_game(game_data.clone()).await;
+            match (current_user, existing_race_lobby) {
+                (Some(user_details), _) if user_details.status == models::UserStatus::Busy => (
+                    Some(error_message(
+                        models::ErrorCode::UserBusy,
+                        "You cannot create a room while in a game",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), Some(_)) => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "Leave your current race lobby first",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), None) => {
+                    let room = db.create_room(current_user_id).await;
+                    leave_ranked_queue_for_room(&db, current_user_id).await;
+                    db.broadcast_race_lobby(&room.id).await;
+                    db.boradcast_status().await;
 
-                    // Inform the users about the starting of game
-                    let game_init_message = models::WSServerMessage::GameInit {
-                        game_id: game_data.id.clone(),
-                        prompt_text: game_data.prompt_text.clone(),
-                        starts_at: game_data.starts_at,
-                    };
+                    (None, None)
+                }
+                (None, _) => (None, None),
+            }
+        }
+        models::WSClientMessage::JoinRoom { code } => {
+            let room = db.find_room(&code).await;
+            let current_user = db.get_user_by_id(current_user_id).await;
+            let existing_race_lobby = db.find_race_lobby_of_user(current_user_id).await;
 
-                    // Schedule a tokio task to inform the users about the starting of game
-                    let game_start_message = models::WSServerMessage::GameStart;
-                    let db_clone = db.clone();
+            match (room, current_user) {
+                (None, _) => {
+                    let message = format!("There is no room with the code {}", code.trim());
+                    (
+                        Some(error_message(models::ErrorCode::InvalidRace, &message)),
+                        Some(vec![current_user_id.to_string()]),
+                    )
+                }
+                (Some(room), _) if room.is_full() => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "The room already has the maximum number of players",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), Some(user_details))
+                    if user_details.status == models::UserStatus::Busy =>
+                {
+                    (
+                        Some(error_message(
+                            models::ErrorCode::UserBusy,
+                            "You cannot join a room while in a game",
+                        )),
+                        Some(vec![current_user_id.to_string()]),
+                    )
+                }
+                (Some(_), Some(_)) if existing_race_lobby.is_some() => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "Leave your current race lobby first",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(mut room), Some(_)) => {
+                    let race_id = room.id.clone();
+                    room.participant_ids.push(current_user_id.to_string());
+                    db.save_race_lobby(room).await;
+                    leave_ranked_queue_for_room(&db, current_user_id).await;
+                    db.broadcast_race_lobby(&race_id).await;
+                    db.boradcast_status().await;
 
-                    let cloned_current_user_id = current_user_id.to_string();
-                    let cloned_opponent_user_id = opponent_user_id.clone();
+                    (None, None)
+                }
+                (Some(_), None) => (None, None),
+            }
+        }
+        models::WSClientMessage::InviteToRace { race_id, user_id } => {
+            let race_lobby = db.get_race_lobby(&race_id).await;
+            let invited_user = if db.share_room(current_user_id, &user_id).await {
+                db.get_user_by_id(&user_id).await
+            } else {
+                None
+            };
 
-                    let timeout_func = || async move {
-                        db_clone
-                            .send_message_to_user(
-                                &cloned_current_user_id,
-                                game_start_message.clone(),
-                            )
-                            .await;
-                        db_clone
-                            .send_message_to_user(
-                                &cloned_opponent_user_id.clone(),
-                                game_start_message,
-                            )
-                            .await;
-                    };
+            match (race_lobby, invited_user) {
+                (Some(race_lobby), _) if race_lobby.owner_id != current_user_id => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "Only the owner of the race can invite players",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(race_lobby), _) if race_lobby.is_full() => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "The race already has the maximum number of players",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(race_lobby), Some(_)) if race_lobby.has_participant(&user_id) => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "The player has already joined the race",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), Some(invited_user))
+                    if invited_user.status == models::UserStatus::Busy =>
+                {
+                    let message = format!("{} is busy in a game", invited_user.display_name);
+                    (
+                        Some(error_message(models::ErrorCode::UserBusy, &message)),
+                        Some(vec![current_user_id.to_string()]),
+                    )
+                }
+                (Some(mut race_lobby), Some(_)) => {
+                    if !race_lobby.invited_ids.contains(&user_id) {
+                        race_lobby.invited_ids.push(user_id.clone());
+                    }
+                    db.save_race_lobby(race_lobby).await;
 
-                    tokio::spawn(async {
-                        server_utils::set_timeout(10, timeout_func).await;
-                    });
+                    let message = db
+                        .get_user_by_id(current_user_id)
+                        .await
+                        .map(|user_details| models::WSServerMessage::RaceInvitation {
+                            race_id,
+                            from_user: user_details,
+                        });
+                    (message, Some(vec![user_id]))
+                }
+                (Some(_), None) => (
+                    Some(error_message(
+                        models::ErrorCode::UserNotFound,
+                        "Requested user cannot be found or is disconnected",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (None, _) => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "The race does not exist anymore",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+            }
+        }
+        models::WSClientMessage::JoinRace { race_id } => {
+            let race_lobby = db.get_race_lobby(&race_id).await;
+            let current_user = db.get_user_by_id(current_user_id).await;
+            let existing_race_lobby = db.find_race_lobby_of_user(current_user_id).await;
 
+            match (race_lobby, current_user) {
+                (Some(race_lobby), _)
+                    if !race_lobby
+                        .invited_ids
+                        .iter()
+                        .any(|invited_id| invited_id == current_user_id) =>
+                {
                     (
-                        Some(game_init_message),
-                        Some(vec![current_user_id.to_string(), opponent_user_id]),
+                        Some(error_message(
+                            models::ErrorCode::InvalidRace,
+                            "You have not been invited to the race",
+                        )),
+                        Some(vec![current_user_id.to_string()]),
                     )
                 }
-                (Some(_), None) => {
-                    // The opponent user is disconnected, send the message to current user
-                    eprintln!("User not found {opponent_user_id}");
-                    let error_message = models::WSServerMessage::Error {
-                        message: "Requested user cannot be found or is disconnected".to_string(),
-                    };
-                    (Some(error_message), Some(vec![current_user_id.to_string()]))
+                (Some(race_lobby), _) if race_lobby.is_full() => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "The race already has the maximum number of players",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), Some(user_details))
+                    if user_details.status == models::UserStatus::Busy =>
+                {
+                    (
+                        Some(error_message(
+                            models::ErrorCode::UserBusy,
+                            "You cannot join a race while in a game",
+                        )),
+                        Some(vec![current_user_id.to_string()]),
+                    )
+                }
+                (Some(_), Some(_)) if existing_race_lobby.is_some() => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "Leave your current race lobby first",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(mut race_lobby), Some(_)) => {
+                    race_lobby
+                        .invited_ids
+                        .retain(|invited_id| invited_id != current_user_id);
+                    race_lobby.participant_ids.push(current_user_id.to_string());
+                    db.save_race_lobby(race_lobby).await;
+                    db.broadcast_race_lobby(&race_id).await;
+
+                    (None, None)
+                }
+                (None, _) => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "The race does not exist anymore",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+                (Some(_), None) => (None, None),
+            }
+        }
+        models::WSClientMessage::LeaveRace { race_id } => {
+            match db.find_race_lobby_of_user(current_user_id).await {
+                Some(race_lobby) if race_lobby.id == race_id => {
+                    db.leave_race_lobby(current_user_id).await;
+                    (None, None)
+                }
+                _ => (
+                    Some(error_message(
+                        models::ErrorCode::InvalidRace,
+                        "You are not a part of the race",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                ),
+            }
+        }
+        models::WSClientMessage::StartRace { race_id } => match db.get_race_lobby(&race_id).await {
+            Some(race_lobby) if race_lobby.owner_id != current_user_id => (
+                Some(error_message(
+                    models::ErrorCode::InvalidRace,
+                    "Only the owner of the race can start it",
+                )),
+                Some(vec![current_user_id.to_string()]),
+            ),
+            Some(race_lobby) if race_lobby.participant_ids.len() < 2 => (
+                Some(error_message(
+                    models::ErrorCode::InvalidRace,
+                    "At least two players are needed for a race",
+                )),
+                Some(vec![current_user_id.to_string()]),
+            ),
+            Some(race_lobby) => {
+                match create_game(db.clone(), race_lobby.participant_ids.clone(), false).await {
+                    Ok(game_init_message) => {
+                        // The players of a private room stay in it to race again
+                        if race_lobby.code.is_none() {
+                            db.remove_race_lobby(&race_id).await;
+                        }
+
+                        (Some(game_init_message), Some(race_lobby.participant_ids))
+                    }
+                    Err(error) => (
+                        Some(error.to_server_message()),
+                        Some(vec![current_user_id.to_string()]),
+                    ),
                 }
-                (None, Some(_)) => {
-                    // The opponent user is disconnected, send the message to current user
-                    eprintln!("User not found {current_user_id}");
-                    let error_message = models::WSServerMessage::Error {
-                        message: "Requested user cannot be found or is disconnected".to_string(),
-                    };
-                    (Some(error_message), Some(vec![opponent_user_id]))
+            }
+            None => (
+                Some(error_message(
+                    models::ErrorCode::InvalidRace,
+                    "The race does not exist anymore",
+                )),
+                Some(vec![current_user_id.to_string()]),
+            ),
+        },
+        models::WSClientMessage::UpdateProgress {
+            game_id,
+            keystrokes,
+            stats,
+        } => match db
+            .update_game_progress(&game_id, current_user_id, &keystrokes, stats)
+            .await
+        {
+            Ok(progress_update) => {
+                let progress = progress_update.progress;
+                let progress_difference = progress.abs_diff(progress_update.previous_progress);
+
+                if progress_update.is_race_completed {
+                    db.finish_game(&game_id).await;
+                } else if progress < 70 && progress_difference < 5 && !progress_update.disqualified
+                {
+                    eprintln!("Skipping update progress of game_id: {game_id}, current_user_id: {current_user_id}, progress: {progress}");
+                    db.update_spectators(&game_id).await;
+                } else {
+                    db.broadcase_game_status(&game_id).await;
+                }
+
+                let error_reply = progress_update.violation.map(|violation| {
+                    if progress_update.disqualified {
+                        let message = format!("You have been disqualified, {violation}");
+                        error_message(models::ErrorCode::Disqualified, &message)
+                    } else if let Violation::InvalidPosition { .. } = violation {
+                        // Some keystrokes did not reach the server, they have to be sent again
+                        models::WSServerMessage::ProgressRejected {
+                            game_id: game_id.clone(),
+                            position: progress_update.cursor,
+                        }
+                    } else {
+                        let message = format!("Progress was rejected, {violation}");
+                        error_message(models::ErrorCode::InvalidProgress, &message)
+                    }
+                });
+
+                (error_reply, Some(vec![current_user_id.to_string()]))
+            }
+            Err(error) => {
+                eprintln!("Ignoring progress of {current_user_id}, {error}");
+                (
+                    Some(error.to_server_message()),
+                    Some(vec![current_user_id.to_string()]),
+                )
+            }
+        },
+        models::WSClientMessage::GetHistory => {
+            let reply = match db.race_history(current_user_id).await {
+                Ok(races) => models::WSServerMessage::History { races },
+                Err(error) => {
+                    eprintln!("Could not read the history of {current_user_id}, {error}");
+                    error.to_server_message()
+                }
+            };
+            (Some(reply), Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::GetStats => {
+            let reply = match db.player_stats(current_user_id).await {
+                Ok(stats) => models::WSServerMessage::Stats { stats },
+                Err(error) => {
+                    eprintln!("Could not read the statistics of {current_user_id}, {error}");
+                    error.to_server_message()
+                }
+            };
+            (Some(reply), Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::GetLeaderboard { category, period } => {
+            let reply = match db.leaderboard(category, period).await {
+                Ok(entries) => models::WSServerMessage::Leaderboard {
+                    category,
+                    period,
+                    entries,
+                },
+                Err(error) => {
+                    eprintln!("Could not build the leaderboard, {error}");
+                    error.to_server_message()
+                }
+            };
+            (Some(reply), Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::SpectateGame { game_id } => {
+            let reply = match db.spectate_game(&game_id, current_user_id).await {
+                Ok(spectator_init_message) => spectator_init_message,
+                Err(error) => error.to_server_message(),
+            };
+            (Some(reply), Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::Chat { text } => {
+            let reply = match db.send_chat(current_user_id, &text).await {
+                Ok(()) => None,
+                Err(error) => {
+                    eprintln!("Chat message of {current_user_id} was not sent, {error}");
+                    Some(error.to_server_message())
+                }
+            };
+            (reply, Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::StopSpectating => {
+            let reply = if db.stop_spectating(current_user_id).await {
+                None
+            } else {
+                Some(error_message(
+                    models::ErrorCode::GameNotFound,
+                    "You are not spectating any game",
+                ))
+            };
+            (reply, Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::JoinRankedQueue => {
+            let reply = match db.get_user_by_id(current_user_id).await {
+                Some(user_details) if user_details.status == models::UserStatus::Busy => {
+                    Some(error_message(
+                        models::ErrorCode::UserBusy,
+                        "You cannot join the ranked queue while in a game",
+                    ))
+                }
+                Some(_) if db.is_in_ranked_queue(current_user_id).await => Some(error_message(
+                    models::ErrorCode::InvalidQueue,
+                    "You are already waiting for a ranked opponent",
+                )),
+                // The players of a private room only race each other
+                Some(_) if db.is_in_room(current_user_id).await => Some(error_message(
+                    models::ErrorCode::InvalidQueue,
+                    "Leave the private room to race ranked",
+                )),
+                Some(_) => match db.rating_of(current_user_id).await {
+                    Ok(Some(rating)) => {
+                        db.join_ranked_queue(current_user_id, rating).await;
+                        Some(models::WSServerMessage::RankedQueueJoined {
+                            rating: rating.display_value(),
+                        })
+                    }
+                    Ok(None) => Some(error_message(
+                        models::ErrorCode::InvalidQueue,
+                        "Only registered players can race ranked, register a nickname first",
+                    )),
+                    Err(error) => {
+                        eprintln!("Could not read the rating of {current_user_id}, {error}");
+                        Some(error.to_server_message())
+                    }
+                },
+                None => None,
+            };
+            (reply, Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::LeaveRankedQueue => {
+            let reply = if db.leave_ranked_queue(current_user_id).await {
+                models::WSServerMessage::RankedQueueLeft
+            } else {
+                error_message(
+                    models::ErrorCode::InvalidQueue,
+                    "You are not waiting for a ranked opponent",
+                )
+            };
+            (Some(reply), Some(vec![current_user_id.to_string()]))
+        }
+        models::WSClientMessage::AcceptChallenge { opponent_user_id } => {
+            let pending_challenge = db
+                .remove_challenge(&opponent_user_id, current_user_id)
+                .await;
+
+            if pending_challenge.is_none() {
+                (
+                    Some(error_message(
+                        models::ErrorCode::InvalidChallenge,
+                        "The challenge has expired or was cancelled",
+                    )),
+                    Some(vec![current_user_id.to_string()]),
+                )
+            } else {
+                // The opponent is the person who created the challenge
+                let user_ids = vec![opponent_user_id.clone(), current_user_id.to_string()];
+                match create_game(db.clone(), user_ids, false).await {
+                    Ok(game_init_message) => (
+                        Some(game_init_message),
+                        Some(vec![current_user_id.to_string(), opponent_user_id]),
+                    ),
+                    Err(error) => {
+                        eprintln!("Could not accept the challenge of {opponent_user_id}, {error}");
+                        (
+                            Some(error.to_server_message()),
+                            Some(vec![current_user_id.to_string()]),
+                        )
+                    }
                 }
-                (None, None) => (None, None),
             }
         }
     };
@@ -168,6 +853,148 @@ pub async fn handle_client_messages(
             .map(|user_id| db.send_message_to_user(user_id, message.clone()))
             .collect::<Vec<_>>();
 
-        futures_util::future::join_all(futures_of_messages).await;
+        let results = futures_util::future::join_all(futures_of_messages).await;
+        for error in results.into_iter().filter_map(Result::err) {
+            eprintln!("Could not deliver the reply to the message of {current_user_id}, {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    async fn connect_user(
+        db: &fast_storage::BlazinglyFastDb,
+        user_id: &str,
+    ) -> mpsc::UnboundedReceiver<models::WSServerMessage> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let user = models::User {
+            id: user_id.to_string(),
+            status: models::UserStatus::Available,
+            display_name: format!("Player {user_id}"),
+            rating: None,
+            bot: false,
+        };
+        db.insert_new_user_connection(fast_storage::UserConnection::new(user, sender))
+            .await;
+        receiver
+    }
+
+    fn received_messages(
+        receiver: &mut mpsc::UnboundedReceiver<models::WSServerMessage>,
+    ) -> Vec<models::WSServerMessage> {
+        let mut messages = vec![];
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn count_game_inits(receiver: &mut mpsc::UnboundedReceiver<models::WSServerMessage>) -> usize {
+        received_messages(receiver)
+            .iter()
+            .filter(|message| matches!(message, models::WSServerMessage::GameInit { .. }))
+            .count()
+    }
+
+    fn client_message(message: models::WSClientMessage) -> String {
+        serde_json::to_string(&message).unwrap()
+    }
+
+    /// Accept the challenge of user 1 as user 2, which has to fail as the challenge is gone
+    async fn check_challenge_cannot_be_accepted(
+        db: Arc<fast_storage::BlazinglyFastDb>,
+        receiver: &mut mpsc::UnboundedReceiver<models::WSServerMessage>,
+    ) {
+        let accept = client_message(models::WSClientMessage::AcceptChallenge {
+            opponent_user_id: "1".to_string(),
+        });
+        handle_client_messages(&accept, db.clone(), "2").await;
+
+        let messages = received_messages(receiver);
+        assert!(messages.iter().any(|message| matches!(
+            message,
+            models::WSServerMessage::Error {
+                code: models::ErrorCode::InvalidChallenge,
+                ..
+            }
+        )));
+        assert!(!messages
+            .iter()
+            .any(|message| matches!(message, models::WSServerMessage::GameInit { .. })));
+        assert!(!db.get_user_connection_by_id("1").await.unwrap().is_busy());
+    }
+
+    #[tokio::test]
+    async fn expired_challenge_cannot_be_accepted() {
+        let db = Arc::new(fast_storage::BlazinglyFastDb::default());
+        let mut challenger_receiver = connect_user(&db, "1").await;
+        let mut receiver = connect_user(&db, "2").await;
+        let challenge = fast_storage::PendingChallenge::new("1", "2", 60);
+        let challenge_id = challenge.id;
+        db.insert_challenge(challenge).await;
+
+        db.expire_challenge(challenge_id).await;
+        assert!(received_messages(&mut challenger_receiver)
+            .iter()
+            .any(|message| matches!(message, models::WSServerMessage::ChallengeExpired { .. })));
+
+        check_challenge_cannot_be_accepted(db, &mut receiver).await;
+    }
+
+    #[tokio::test]
+    async fn rejected_or_cancelled_challenge_cannot_be_accepted() {
+        let db = Arc::new(fast_storage::BlazinglyFastDb::default());
+        let _challenger_receiver = connect_user(&db, "1").await;
+        let mut receiver = connect_user(&db, "2").await;
+
+        db.insert_challenge(fast_storage::PendingChallenge::new("1", "2", 60))
+            .await;
+        let reject = client_message(models::WSClientMessage::RejectChallenge {
+            opponent_user_id: "1".to_string(),
+        });
+        handle_client_messages(&reject, db.clone(), "2").await;
+        check_challenge_cannot_be_accepted(db.clone(), &mut receiver).await;
+
+        db.insert_challenge(fast_storage::PendingChallenge::new("1", "2", 60))
+            .await;
+        let cancel = client_message(models::WSClientMessage::CancelChallenge {
+            to_user_id: "2".to_string(),
+        });
+        handle_client_messages(&cancel, db.clone(), "1").await;
+        check_challenge_cannot_be_accepted(db, &mut receiver).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn user_accepting_two_challenges_at_once_joins_one_game() {
+        let db = Arc::new(fast_storage::BlazinglyFastDb::default());
+        let mut receivers = vec![];
+        for user_id in ["1", "2", "3"] {
+            receivers.push(connect_user(&db, user_id).await);
+        }
+        db.insert_challenge(fast_storage::PendingChallenge::new("1", "3", 60))
+            .await;
+        db.insert_challenge(fast_storage::PendingChallenge::new("2", "3", 60))
+            .await;
+
+        let accepts = ["1", "2"].map(|opponent_user_id| {
+            let message = client_message(models::WSClientMessage::AcceptChallenge {
+                opponent_user_id: opponent_user_id.to_string(),
+            });
+            let db = db.clone();
+            tokio::spawn(async move { handle_client_messages(&message, db, "3").await })
+        });
+        for accept in accepts {
+            accept.await.unwrap();
+        }
+
+        let game_inits = receivers
+            .iter_mut()
+            .map(count_game_inits)
+            .collect::<Vec<_>>();
+        assert_eq!(game_inits[2], 1);
+        assert_eq!(game_inits[0] + game_inits[1], 1);
     }
 }
@@ -0,0 +1,371 @@
+use std::{collections::VecDeque, fs, io, path::Path, str::FromStr, sync::Mutex};
+
+use rand::seq::SliceRandom;
+
+/// The quotes that are shipped along with the server binary
+const BUNDLED_PROMPTS: &str = include_str!("../../assets/prompts.json");
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptLength {
+    Short,
+    Medium,
+    Long,
+}
+
+impl PromptLength {
+    fn from_text(text: &str) -> Self {
+        match text.split_whitespace().count() {
+            0..=10 => PromptLength::Short,
+            11..=25 => PromptLength::Medium,
+            _ => PromptLength::Long,
+        }
+    }
+}
+
+impl FromStr for PromptLength {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "short" => Ok(PromptLength::Short),
+            "medium" => Ok(PromptLength::Medium),
+            "long" => Ok(PromptLength::Long),
+            _ => Err(format!("{value} is not one of short, medium or long")),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    /// Guess the difficulty of typing the text
+    /// Punctuation, digits and capital letters need more effort than lowercase letters,
+    /// and so do long words
+    fn from_text(text: &str) -> Self {
+        let total_characters = text.chars().count().max(1);
+        let special_characters = text
+            .chars()
+            .filter(|character| !(character.is_lowercase() || *character == ' '))
+            .count();
+        let special_ratio = special_characters as f32 / total_characters as f32;
+
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let average_word_length = words.iter().map(|word| word.chars().count()).sum::<usize>()
+            as f32
+            / words.len().max(1) as f32;
+
+        if special_ratio < 0.04 && average_word_length < 5.0 {
+            Difficulty::Easy
+        } else if special_ratio < 0.08 && average_word_length < 6.0 {
+            Difficulty::Medium
+        } else {
+            Difficulty::Hard
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("{value} is not one of easy, medium or hard")),
+        }
+    }
+}
+
+/// An entry as it is written in the prompt files
+#[derive(serde::Deserialize)]
+struct PromptEntry {
+    text: String,
+    author: Option<String>,
+    source: Option<String>,
+    language: Option<String>,
+    difficulty: Option<Difficulty>,
+}
+
+/// A quote that can be used as the text of a race
+#[derive(Clone, Debug)]
+pub struct Prompt {
+    pub text: String,
+    pub author: Option<String>,
+    pub source: Option<String>,
+    pub language: String,
+    pub length: PromptLength,
+    pub difficulty: Difficulty,
+}
+
+impl From<PromptEntry> for Prompt {
+    fn from(entry: PromptEntry) -> Self {
+        let text = entry.text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        Self {
+            length: PromptLength::from_text(&text),
+            difficulty: entry
+                .difficulty
+                .unwrap_or_else(|| Difficulty::from_text(&text)),
+            language: entry.language.unwrap_or_else(|| "en".to_string()),
+            author: entry.author,
+            source: entry.source,
+            text,
+        }
+    }
+}
+
+fn parse_prompts(contents: &str) -> io::Result<Vec<Prompt>> {
+    let entries = serde_json::from_str::<Vec<PromptEntry>>(contents)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !entry.text.trim().is_empty())
+        .map(Prompt::from)
+        .collect())
+}
+
+/// Read all the `.json` prompt files present in `directory`
+pub fn load_directory(directory: &Path) -> io::Result<Vec<Prompt>> {
+    let mut prompts = vec![];
+
+    for dir_entry in fs::read_dir(directory)? {
+        let path = dir_entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let contents = fs::read_to_string(&path)?;
+            prompts.extend(parse_prompts(&contents)?);
+        }
+    }
+
+    Ok(prompts)
+}
+
+/// Constraints on the prompt to be picked, `None` matches everything
+#[derive(Default, Clone, Debug)]
+pub struct PromptFilter {
+    pub length: Option<PromptLength>,
+    pub difficulty: Option<Difficulty>,
+    pub language: Option<String>,
+}
+
+impl PromptFilter {
+    fn matches(&self, prompt: &Prompt) -> bool {
+        let length_matches = match self.length {
+            Some(length) => length == prompt.length,
+            None => true,
+        };
+        let difficulty_matches = match self.difficulty {
+            Some(difficulty) => difficulty == prompt.difficulty,
+            None => true,
+        };
+        let language_matches = match self.language.as_ref() {
+            Some(language) => language == &prompt.language,
+            None => true,
+        };
+
+        length_matches && difficulty_matches && language_matches
+    }
+}
+
+/// All the prompts known to the server
+/// Keeps track of the recently used prompts so that they are not repeated in the next few races
+pub struct PromptCorpus {
+    prompts: Vec<Prompt>,
+    recently_used: Mutex<VecDeque<usize>>,
+}
+
+impl Default for PromptCorpus {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+impl PromptCorpus {
+    pub fn new(prompts: Vec<Prompt>) -> Self {
+        Self {
+            prompts,
+            recently_used: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The corpus with only the prompts shipped in the binary
+    pub fn bundled() -> Self {
+        let prompts = parse_prompts(BUNDLED_PROMPTS).expect("Bundled prompts are not valid");
+        Self::new(prompts)
+    }
+
+    pub fn extend(&mut self, prompts: Vec<Prompt>) {
+        self.prompts.extend(prompts);
+    }
+
+    pub fn len(&self) -> usize {
+        self.prompts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prompts.is_empty()
+    }
+
+    /// Pick a random prompt that satisfies the filter
+    ///
+    /// Prompts used in the recent races are avoided, half of the corpus has to be used
+    /// before a prompt can be repeated. If nothing satisfies the filter, it is ignored.
+    /// The lock is never held across an await, so this can be used by the client as well
+    pub fn pick(&self, filter: &PromptFilter) -> Prompt {
+        let mut recently_used = self.recently_used.lock().unwrap();
+
+        let matching_indices = (0..self.prompts.len())
+            .filter(|&index| filter.matches(&self.prompts[index]))
+            .collect::<Vec<_>>();
+
+        let candidates = if matching_indices.is_empty() {
+            (0..self.prompts.len()).collect::<Vec<_>>()
+        } else {
+            matching_indices
+        };
+
+        let fresh_candidates = candidates
+            .iter()
+            .copied()
+            .filter(|index| !recently_used.contains(index))
+            .collect::<Vec<_>>();
+
+        let chosen_index = fresh_candidates
+            .choose(&mut rand::thread_rng())
+            .or_else(|| candidates.choose(&mut rand::thread_rng()))
+            .copied()
+            .expect("Prompt corpus is empty");
+
+        recently_used.push_back(chosen_index);
+        while recently_used.len() > self.prompts.len() / 2 {
+            recently_used.pop_front();
+        }
+
+        self.prompts[chosen_index].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(text: &str, language: &str) -> Prompt {
+        Prompt::from(PromptEntry {
+            text: text.to_string(),
+            author: None,
+            source: None,
+            language: Some(language.to_string()),
+            difficulty: None,
+        })
+    }
+
+    #[test]
+    fn parse_prompts_fills_in_missing_tags() {
+        let prompts = parse_prompts(
+            r#"[
+                { "text": "  the cat   sat on\nthe mat ", "author": "Tabby" },
+                { "text": "   " },
+                { "text": "Quarks, Leptons & Bosons: 1897-1964!", "language": "de", "difficulty": "easy" }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(prompts.len(), 2);
+
+        assert_eq!(prompts[0].text, "the cat sat on the mat");
+        assert_eq!(prompts[0].author.as_deref(), Some("Tabby"));
+        assert_eq!(prompts[0].language, "en");
+        assert_eq!(prompts[0].length, PromptLength::Short);
+        assert_eq!(prompts[0].difficulty, Difficulty::Easy);
+
+        assert_eq!(prompts[1].language, "de");
+        assert_eq!(prompts[1].difficulty, Difficulty::Easy);
+    }
+
+    #[test]
+    fn parse_prompts_rejects_invalid_json() {
+        let error = parse_prompts(r#"[{ "author": "Tabby" }]"#).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn bundled_prompts_are_valid() {
+        assert!(!PromptCorpus::bundled().is_empty());
+    }
+
+    #[test]
+    fn difficulty_is_guessed_from_the_text() {
+        assert_eq!(
+            Difficulty::from_text("the cat sat on the mat"),
+            Difficulty::Easy
+        );
+        assert_eq!(
+            Difficulty::from_text("Extraordinary, Incomprehensible; Unbelievable!"),
+            Difficulty::Hard
+        );
+    }
+
+    #[test]
+    fn pick_only_returns_matching_prompts() {
+        let corpus = PromptCorpus::new(vec![
+            prompt("one two three", "en"),
+            prompt("eins zwei drei", "de"),
+            prompt("un deux trois", "fr"),
+            prompt("vier fünf sechs", "de"),
+        ]);
+        let filter = PromptFilter {
+            language: Some("de".to_string()),
+            ..PromptFilter::default()
+        };
+
+        for _ in 0..20 {
+            assert_eq!(corpus.pick(&filter).language, "de");
+        }
+    }
+
+    #[test]
+    fn pick_ignores_a_filter_which_matches_nothing() {
+        let corpus = PromptCorpus::new(vec![prompt("one two three", "en")]);
+        let filter = PromptFilter {
+            length: Some(PromptLength::Long),
+            ..PromptFilter::default()
+        };
+
+        assert_eq!(corpus.pick(&filter).text, "one two three");
+    }
+
+    #[test]
+    fn pick_avoids_recently_used_prompts() {
+        let corpus = PromptCorpus::new(
+            (0..6)
+                .map(|index| prompt(&format!("prompt number {index}"), "en"))
+                .collect(),
+        );
+
+        let mut picked = vec![];
+        for _ in 0..12 {
+            let text = corpus.pick(&PromptFilter::default()).text;
+            // Half of the corpus has to be used before a prompt is repeated
+            assert!(!picked.iter().rev().take(3).any(|used| used == &text));
+            picked.push(text);
+        }
+    }
+
+    #[test]
+    fn filter_values_are_parsed() {
+        assert_eq!("long".parse::<PromptLength>(), Ok(PromptLength::Long));
+        assert_eq!("hard".parse::<Difficulty>(), Ok(Difficulty::Hard));
+        assert!("huge".parse::<PromptLength>().is_err());
+    }
+}
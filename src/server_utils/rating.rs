@@ -0,0 +1,92 @@
+/// Rating of every player before their first ranked race
+pub const INITIAL_RATING: f64 = 1500.0;
+
+/// Ratings of new players move faster, till they have finished this many ranked races
+const PROVISIONAL_RACES: u32 = 20;
+
+const PROVISIONAL_K_FACTOR: f64 = 40.0;
+
+const K_FACTOR: f64 = 20.0;
+
+/// Elo rating of a player, changed only by ranked races
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+pub struct Rating {
+    pub value: f64,
+    /// Number of ranked races finished
+    pub races: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            value: INITIAL_RATING,
+            races: 0,
+        }
+    }
+}
+
+impl Rating {
+    /// The rating rounded for display
+    pub fn display_value(&self) -> u32 {
+        self.value.max(0.0).round() as u32
+    }
+
+    /// Probability of winning against the opponent, a draw counting as half a win
+    pub fn expected_score(&self, opponent: &Rating) -> f64 {
+        1.0 / (1.0 + 10_f64.powf((opponent.value - self.value) / 400.0))
+    }
+
+    /// The rating after a race against the opponent
+    /// `score` is 1 for a win, 0.5 for a draw and 0 for a loss
+    pub fn after_race(&self, opponent: &Rating, score: f64) -> Rating {
+        let k_factor = if self.races < PROVISIONAL_RACES {
+            PROVISIONAL_K_FACTOR
+        } else {
+            K_FACTOR
+        };
+
+        Rating {
+            value: self.value + k_factor * (score - self.expected_score(opponent)),
+            races: self.races + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(value: f64, races: u32) -> Rating {
+        Rating { value, races }
+    }
+
+    #[test]
+    fn equal_ratings_move_by_half_the_k_factor() {
+        let player = rating(1500.0, 0);
+        let opponent = rating(1500.0, 0);
+
+        assert_eq!(player.after_race(&opponent, 1.0).value, 1520.0);
+        assert_eq!(player.after_race(&opponent, 0.0).value, 1480.0);
+        assert_eq!(player.after_race(&opponent, 0.5).value, 1500.0);
+        assert_eq!(player.after_race(&opponent, 1.0).races, 1);
+    }
+
+    #[test]
+    fn established_ratings_move_slower() {
+        let player = rating(1500.0, PROVISIONAL_RACES);
+        let opponent = rating(1500.0, PROVISIONAL_RACES);
+
+        assert_eq!(player.after_race(&opponent, 1.0).value, 1510.0);
+    }
+
+    #[test]
+    fn beating_a_stronger_opponent_gains_more() {
+        let player = rating(1400.0, 0);
+        let opponent = rating(1600.0, 0);
+
+        let upset_gain = player.after_race(&opponent, 1.0).value - player.value;
+        let expected_gain = opponent.after_race(&player, 1.0).value - opponent.value;
+        assert!(upset_gain > expected_gain);
+        assert!((upset_gain + expected_gain - PROVISIONAL_K_FACTOR).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections;
+
+use async_trait::async_trait;
+
+use crate::{
+    errors::{AccountError, Error},
+    models,
+    server_utils::{accounts::Account, rating::Rating},
+};
+
+mod file;
+mod memory;
+
+pub use file::FileStorage;
+pub use memory::MemoryStorage;
+
+/// A game which has been finished, kept so that the players can look back at it
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct GameRecord {
+    pub game_id: String,
+    pub prompt_text: String,
+    /// Unix timestamp at which the race started
+    pub started_at: u64,
+    /// Unix timestamp in milliseconds at which the game was finished
+    pub finished_at: u64,
+    pub placements: Vec<models::Placement>,
+    /// The ratings of the players were changed by the game
+    #[serde(default)]
+    pub ranked: bool,
+}
+
+/// The rating of a user as it was saved, the latest record of a user is the current rating
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+struct RatingRecord {
+    user_id: String,
+    rating: Rating,
+}
+
+impl GameRecord {
+    pub fn has_participant(&self, user_id: &str) -> bool {
+        self.placements
+            .iter()
+            .any(|placement| placement.user_id == user_id)
+    }
+
+    /// The result of the race for one of the users who raced in it
+    pub fn summary_for(&self, user_id: &str) -> Option<models::RaceSummary> {
+        let placement = self
+            .placements
+            .iter()
+            .find(|placement| placement.user_id == user_id)?;
+
+        Some(models::RaceSummary {
+            game_id: self.game_id.clone(),
+            prompt_text: self.prompt_text.clone(),
+            finished_at: self.finished_at,
+            position: placement.position,
+            racer_count: self.placements.len(),
+            wpm: placement.wpm,
+            accuracy: placement.accuracy,
+            completed: placement.finished_at.is_some(),
+            disqualified: placement.disqualified,
+        })
+    }
+}
+
+/// Everything that has to outlive the server process
+///
+/// Connections, challenges, lobbies and running games hold channels to the users and live
+/// only in `BlazinglyFastDb`. Accounts and finished games are kept here
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Create an account with a new id, fails if the nickname is already registered
+    /// Only the hash of the token is kept
+    async fn create_account(&self, nickname: &str, token: &str) -> Result<Account, Error>;
+
+    async fn find_account_by_token(&self, token: &str) -> Result<Option<Account>, Error>;
+
+    async fn find_account_by_id(&self, account_id: &str) -> Result<Option<Account>, Error>;
+
+    async fn is_nickname_registered(&self, nickname: &str) -> Result<bool, Error>;
+
+    async fn account_count(&self) -> Result<usize, Error>;
+
+    async fn record_game(&self, game: GameRecord) -> Result<(), Error>;
+
+    async fn accounts(&self) -> Result<Vec<Account>, Error>;
+
+    /// Games in which the user raced, the oldest first
+    async fn games_of_user(&self, user_id: &str) -> Result<Vec<GameRecord>, Error>;
+
+    /// Games finished at or after the unix timestamp in milliseconds, the oldest first
+    async fn games_since(&self, finished_at: u64) -> Result<Vec<GameRecord>, Error>;
+
+    /// The rating of the user, users who have not raced ranked have the initial rating
+    async fn rating_of(&self, user_id: &str) -> Result<Rating, Error>;
+
+    async fn save_rating(&self, user_id: &str, rating: Rating) -> Result<(), Error>;
+
+    /// Ratings of all the users who have raced ranked
+    async fn ratings(&self) -> Result<collections::HashMap<String, Rating>, Error>;
+}
+
+/// All the stored data, held in memory by every implementation
+/// The file storage additionally writes every change to the disk
+#[derive(Default)]
+struct Records {
+    accounts: Vec<Account>,
+    games: Vec<GameRecord>,
+    ratings: collections::HashMap<String, Rating>,
+}
+
+impl Records {
+    fn new_account(&self, nickname: &str, token: &str) -> Result<Account, Error> {
+        if self
+            .accounts
+            .iter()
+            .any(|account| account.has_nickname(nickname))
+        {
+            return Err(AccountError::NicknameTaken {
+                nickname: nickname.to_owned(),
+            }
+            .into());
+        }
+
+        let account_id = format!("account-{}", self.accounts.len() + 1);
+        Ok(Account::new(account_id, nickname.to_owned(), token))
+    }
+
+    fn find_account_by_token(&self, token: &str) -> Option<Account> {
+        self.accounts
+            .iter()
+            .find(|account| account.has_token(token))
+            .cloned()
+    }
+
+    fn find_account_by_id(&self, account_id: &str) -> Option<Account> {
+        self.accounts
+            .iter()
+            .find(|account| account.id == account_id)
+            .cloned()
+    }
+
+    fn is_nickname_registered(&self, nickname: &str) -> bool {
+        self.accounts
+            .iter()
+            .any(|account| account.has_nickname(nickname))
+    }
+
+    fn rating_of(&self, user_id: &str) -> Rating {
+        self.ratings.get(user_id).copied().unwrap_or_default()
+    }
+
+    fn games_of_user(&self, user_id: &str) -> Vec<GameRecord> {
+        self.games
+            .iter()
+            .filter(|game| game.has_participant(user_id))
+            .cloned()
+            .collect()
+    }
+
+    fn games_since(&self, finished_at: u64) -> Vec<GameRecord> {
+        self.games
+            .iter()
+            .filter(|game| game.finished_at >= finished_at)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_record(game_id: &str, user_ids: &[&str], finished_at: u64) -> GameRecord {
+        GameRecord {
+            game_id: game_id.to_string(),
+            prompt_text: "the cat sat on the mat".to_string(),
+            started_at: finished_at / 1000 - 30,
+            finished_at,
+            placements: user_ids
+                .iter()
+                .enumerate()
+                .map(|(index, user_id)| models::Placement {
+                    position: index + 1,
+                    user_id: user_id.to_string(),
+                    display_name: user_id.to_string(),
+                    progress: 100,
+                    finished_at: Some(finished_at),
+                    wpm: 60.0,
+                    accuracy: 100.0,
+                    note: None,
+                    disqualified: false,
+                })
+                .collect(),
+            ranked: false,
+        }
+    }
+
+    /// A directory of its own for every test, so that the tests can run in parallel
+    pub(super) fn test_directory(name: &str) -> std::path::PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("kittui-storage-{}-{name}", std::process::id()));
+        std::fs::remove_dir_all(&directory).ok();
+        directory
+    }
+
+    async fn check_accounts(storage: &dyn Storage) {
+        let account = storage
+            .create_account("Tabby", "tabby-token")
+            .await
+            .unwrap();
+        assert!(storage.create_account("tabby", "other").await.is_err());
+        storage
+            .create_account("Mittens", "mittens-token")
+            .await
+            .unwrap();
+
+        let found_account = storage
+            .find_account_by_token("tabby-token")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found_account.id, account.id);
+        assert!(storage
+            .find_account_by_token("wrong")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage.is_nickname_registered("TABBY").await.unwrap());
+        assert_eq!(storage.account_count().await.unwrap(), 2);
+    }
+
+    async fn check_games_and_ratings(storage: &dyn Storage) {
+        storage
+            .record_game(game_record("1", &["account-1", "account-2"], 1_000_000))
+            .await
+            .unwrap();
+        storage
+            .record_game(game_record("2", &["account-2"], 2_000_000))
+            .await
+            .unwrap();
+
+        let games = storage.games_of_user("account-1").await.unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].game_id, "1");
+        assert_eq!(storage.games_since(1_500_000).await.unwrap().len(), 1);
+
+        assert_eq!(storage.rating_of("account-1").await.unwrap().races, 0);
+        let rating = Rating {
+            value: 1520.0,
+            races: 1,
+        };
+        storage.save_rating("account-1", rating).await.unwrap();
+        assert_eq!(storage.rating_of("account-1").await.unwrap().value, 1520.0);
+        assert_eq!(storage.ratings().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn memory_storage_keeps_records() {
+        let storage = MemoryStorage::default();
+        check_accounts(&storage).await;
+        check_games_and_ratings(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn file_storage_keeps_records() {
+        let directory = test_directory("records");
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        check_accounts(&storage).await;
+        check_games_and_ratings(&storage).await;
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_storage_keeps_records_after_restarting() {
+        let directory = test_directory("restart");
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        let account = storage
+            .create_account("Tabby", "tabby-token")
+            .await
+            .unwrap();
+        storage
+            .record_game(game_record("1", &[&account.id], 1_000_000))
+            .await
+            .unwrap();
+        let rating = Rating {
+            value: 1480.0,
+            races: 1,
+        };
+        storage.save_rating(&account.id, rating).await.unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        let found_account = storage.find_account_by_token("tabby-token").await.unwrap();
+        assert_eq!(found_account.unwrap().id, account.id);
+        assert_eq!(storage.games_of_user(&account.id).await.unwrap().len(), 1);
+        assert_eq!(storage.rating_of(&account.id).await.unwrap().value, 1480.0);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    collections, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::{
+    errors::{Error, StorageError},
+    server_utils::{
+        accounts::Account,
+        rating::Rating,
+        storage::{GameRecord, RatingRecord, Records, Storage},
+    },
+};
+
+const ACCOUNTS_FILE: &str = "accounts.jsonl";
+const GAMES_FILE: &str = "games.jsonl";
+const RATINGS_FILE: &str = "ratings.jsonl";
+
+/// Permissions of the data directory and of the logs in it, on unix
+const DIRECTORY_MODE: u32 = 0o700;
+const LOG_MODE: u32 = 0o600;
+
+/// Read every line of the log as a record
+/// A missing file has no records. A last line without a newline was being written when the
+/// server stopped, it is removed from the file instead of refusing to start, so that the
+/// next record starts on a line of its own
+fn read_log<T: serde::de::DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error),
+    };
+
+    let complete_length = contents
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |position| position + 1);
+    if complete_length < contents.len() {
+        eprintln!(
+            "Removing an incomplete record at the end of {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&contents[complete_length..])
+        );
+        let file = fs::OpenOptions::new().write(true).open(path)?;
+        file.set_len(complete_length as u64)?;
+        file.sync_data()?;
+    }
+
+    let contents = std::str::from_utf8(&contents[..complete_length]).map_err(|error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {error}", path.display()),
+        )
+    })?;
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {error}", path.display()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Only the owner of the server can read the logs, the accounts hold the hashes of the tokens
+#[cfg(unix)]
+fn restrict_permissions(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Options with which the logs are opened, new files are readable only by the owner
+fn log_options() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(LOG_MODE);
+    }
+    options
+}
+
+/// Replace the log with the records, through a new file so that a crash leaves the old log intact
+fn rewrite_log<T: serde::Serialize>(path: &Path, records: &[T]) -> io::Result<()> {
+    let mut contents = String::new();
+    for record in records {
+        let line = serde_json::to_string(record)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+
+    let new_path = path.with_extension("jsonl.new");
+    let mut file = log_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&new_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_data()?;
+    fs::rename(new_path, path)
+}
+
+fn persistence_error(path: &Path, error: &dyn std::fmt::Display) -> Error {
+    StorageError::Persistence(format!("{}: {error}", path.display())).into()
+}
+
+/// Write the record as the last line of the log
+/// The file is written on a thread meant for blocking work, so that other tasks are not held up
+async fn append_to_log<T: serde::Serialize>(path: PathBuf, record: &T) -> Result<(), Error> {
+    let mut line =
+        serde_json::to_string(record).map_err(|error| persistence_error(&path, &error))?;
+    line.push('\n');
+
+    tokio::task::spawn_blocking(move || {
+        log_options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| {
+                file.write_all(line.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|error| persistence_error(&path, &error))
+    })
+    .await
+    .map_err(|error| StorageError::Persistence(error.to_string()))?
+}
+
+/// Keeps every record in an append only log inside a directory, one json record per line
+/// All the records are read into memory when the server starts
+pub struct FileStorage {
+    directory: PathBuf,
+    records: RwLock<Records>,
+}
+
+impl FileStorage {
+    /// Read the logs in the directory, creating the directory if it does not exist
+    /// The directory and the logs are made private to the owner of the server
+    pub fn open(directory: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        restrict_permissions(&directory, DIRECTORY_MODE)?;
+        for file_name in [ACCOUNTS_FILE, GAMES_FILE, RATINGS_FILE] {
+            let path = directory.join(file_name);
+            if path.exists() {
+                restrict_permissions(&path, LOG_MODE)?;
+            }
+        }
+
+        // The tokens written by older servers are replaced with their hashes
+        let accounts_path = directory.join(ACCOUNTS_FILE);
+        let mut accounts = read_log::<Account>(&accounts_path)?;
+        let mut has_plain_tokens = false;
+        for account in accounts.iter_mut() {
+            has_plain_tokens |= account.hash_plain_token();
+        }
+        if has_plain_tokens {
+            eprintln!("Hashing the tokens saved in {}", accounts_path.display());
+            rewrite_log(&accounts_path, &accounts)?;
+        }
+
+        // Every change of a rating is appended, the last one of a user is the current rating
+        let ratings = read_log::<RatingRecord>(&directory.join(RATINGS_FILE))?
+            .into_iter()
+            .map(|record| (record.user_id, record.rating))
+            .collect();
+
+        let records = Records {
+            accounts,
+            games: read_log(&directory.join(GAMES_FILE))?,
+            ratings,
+        };
+
+        Ok(Self {
+            directory,
+            records: RwLock::new(records),
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for FileStorage {
+    async fn create_account(&self, nickname: &str, token: &str) -> Result<Account, Error> {
+        // The lock is held while writing, so that the records are written in order
+        let mut records = self.records.write().await;
+        let account = records.new_account(nickname, token)?;
+
+        append_to_log(self.directory.join(ACCOUNTS_FILE), &account).await?;
+        records.accounts.push(account.clone());
+        Ok(account)
+    }
+
+    async fn find_account_by_token(&self, token: &str) -> Result<Option<Account>, Error> {
+        Ok(self.records.read().await.find_account_by_token(token))
+    }
+
+    async fn find_account_by_id(&self, account_id: &str) -> Result<Option<Account>, Error> {
+        Ok(self.records.read().await.find_account_by_id(account_id))
+    }
+
+    async fn is_nickname_registered(&self, nickname: &str) -> Result<bool, Error> {
+        Ok(self.records.read().await.is_nickname_registered(nickname))
+    }
+
+    async fn account_count(&self) -> Result<usize, Error> {
+        Ok(self.records.read().await.accounts.len())
+    }
+
+    async fn record_game(&self, game: GameRecord) -> Result<(), Error> {
+        let mut records = self.records.write().await;
+
+        append_to_log(self.directory.join(GAMES_FILE), &game).await?;
+        records.games.push(game);
+        Ok(())
+    }
+
+    async fn accounts(&self) -> Result<Vec<Account>, Error> {
+        Ok(self.records.read().await.accounts.clone())
+    }
+
+    async fn games_of_user(&self, user_id: &str) -> Result<Vec<GameRecord>, Error> {
+        Ok(self.records.read().await.games_of_user(user_id))
+    }
+
+    async fn games_since(&self, finished_at: u64) -> Result<Vec<GameRecord>, Error> {
+        Ok(self.records.read().await.games_since(finished_at))
+    }
+
+    async fn rating_of(&self, user_id: &str) -> Result<Rating, Error> {
+        Ok(self.records.read().await.rating_of(user_id))
+    }
+
+    async fn save_rating(&self, user_id: &str, rating: Rating) -> Result<(), Error> {
+        let mut records = self.records.write().await;
+        let record = RatingRecord {
+            user_id: user_id.to_owned(),
+            rating,
+        };
+
+        append_to_log(self.directory.join(RATINGS_FILE), &record).await?;
+        records.ratings.insert(record.user_id, rating);
+        Ok(())
+    }
+
+    async fn ratings(&self) -> Result<collections::HashMap<String, Rating>, Error> {
+        Ok(self.records.read().await.ratings.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_utils::storage::tests::test_directory;
+
+    #[tokio::test]
+    async fn incomplete_record_is_removed_when_opening() {
+        let directory = test_directory("incomplete");
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        storage
+            .create_account("Tabby", "tabby-token")
+            .await
+            .unwrap();
+        drop(storage);
+
+        // The server stopped while writing a record
+        let accounts_path = directory.join(ACCOUNTS_FILE);
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&accounts_path)
+            .unwrap();
+        file.write_all(br#"{"id":"account-2","nick"#).unwrap();
+        drop(file);
+
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        assert_eq!(storage.account_count().await.unwrap(), 1);
+        storage
+            .create_account("Mittens", "mittens-token")
+            .await
+            .unwrap();
+        drop(storage);
+
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        assert_eq!(storage.account_count().await.unwrap(), 2);
+        assert!(storage.is_nickname_registered("Mittens").await.unwrap());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tokens_are_not_written_to_the_disk() {
+        let directory = test_directory("tokens");
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        storage
+            .create_account("Tabby", "tabby-token")
+            .await
+            .unwrap();
+
+        let accounts_path = directory.join(ACCOUNTS_FILE);
+        let contents = fs::read_to_string(&accounts_path).unwrap();
+        assert!(!contents.contains("tabby-token"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&directory), DIRECTORY_MODE);
+            assert_eq!(mode(&accounts_path), LOG_MODE);
+        }
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn plain_tokens_of_older_servers_are_hashed() {
+        let directory = test_directory("plain-tokens");
+        fs::create_dir_all(&directory).unwrap();
+        let accounts_path = directory.join(ACCOUNTS_FILE);
+        fs::write(
+            &accounts_path,
+            concat!(
+                r#"{"id":"account-1","nickname":"Tabby","token":"tabby-token","registered_at":1}"#,
+                "\n"
+            ),
+        )
+        .unwrap();
+
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        let account = storage.find_account_by_token("tabby-token").await.unwrap();
+        assert_eq!(account.unwrap().id, "account-1");
+        assert!(!fs::read_to_string(&accounts_path)
+            .unwrap()
+            .contains("tabby-token"));
+        drop(storage);
+
+        let storage = FileStorage::open(directory.clone()).unwrap();
+        assert!(storage
+            .find_account_by_token("tabby-token")
+            .await
+            .unwrap()
+            .is_some());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn corrupt_record_is_refused() {
+        let directory = test_directory("corrupt");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join(GAMES_FILE), "not json\n").unwrap();
+
+        let error = FileStorage::open(directory.clone()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
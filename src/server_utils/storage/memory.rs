@@ -0,0 +1,79 @@
+use std::collections;
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::{
+    errors::Error,
+    server_utils::{
+        accounts::Account,
+        rating::Rating,
+        storage::{GameRecord, Records, Storage},
+    },
+};
+
+/// Keeps everything in memory, all of it is lost when the server stops
+#[derive(Default)]
+pub struct MemoryStorage {
+    records: RwLock<Records>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn create_account(&self, nickname: &str, token: &str) -> Result<Account, Error> {
+        let mut records = self.records.write().await;
+        let account = records.new_account(nickname, token)?;
+        records.accounts.push(account.clone());
+        Ok(account)
+    }
+
+    async fn find_account_by_token(&self, token: &str) -> Result<Option<Account>, Error> {
+        Ok(self.records.read().await.find_account_by_token(token))
+    }
+
+    async fn find_account_by_id(&self, account_id: &str) -> Result<Option<Account>, Error> {
+        Ok(self.records.read().await.find_account_by_id(account_id))
+    }
+
+    async fn is_nickname_registered(&self, nickname: &str) -> Result<bool, Error> {
+        Ok(self.records.read().await.is_nickname_registered(nickname))
+    }
+
+    async fn account_count(&self) -> Result<usize, Error> {
+        Ok(self.records.read().await.accounts.len())
+    }
+
+    async fn record_game(&self, game: GameRecord) -> Result<(), Error> {
+        self.records.write().await.games.push(game);
+        Ok(())
+    }
+
+    async fn accounts(&self) -> Result<Vec<Account>, Error> {
+        Ok(self.records.read().await.accounts.clone())
+    }
+
+    async fn games_of_user(&self, user_id: &str) -> Result<Vec<GameRecord>, Error> {
+        Ok(self.records.read().await.games_of_user(user_id))
+    }
+
+    async fn games_since(&self, finished_at: u64) -> Result<Vec<GameRecord>, Error> {
+        Ok(self.records.read().await.games_since(finished_at))
+    }
+
+    async fn rating_of(&self, user_id: &str) -> Result<Rating, Error> {
+        Ok(self.records.read().await.rating_of(user_id))
+    }
+
+    async fn save_rating(&self, user_id: &str, rating: Rating) -> Result<(), Error> {
+        self.records
+            .write()
+            .await
+            .ratings
+            .insert(user_id.to_owned(), rating);
+        Ok(())
+    }
+
+    async fn ratings(&self) -> Result<collections::HashMap<String, Rating>, Error> {
+        Ok(self.records.read().await.ratings.clone())
+    }
+}
@@ -1,7 +1,10 @@
+pub mod config;
 pub mod draw;
 pub mod fsm;
+pub mod ghost;
 pub mod input_handler;
 pub mod layout_divider;
+pub mod replay;
 pub mod stateful_list;
 pub mod types;
 pub mod utils;
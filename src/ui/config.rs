@@ -0,0 +1,350 @@
+use crate::ui::types::AccountSettings;
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Server to connect to when nothing else is configured
+const DEFAULT_SERVER_URL: &str = "ws://127.0.0.1:8080";
+
+/// Environment variable which is used when `--server` is not passed
+const SERVER_URL_ENV_VAR: &str = "KITTUI_SERVER_URL";
+
+pub const USAGE: &str = "Usage: client [OPTIONS]
+
+Options:
+  --server <url>      Websocket url of the server, ws:// or wss://
+                      Falls back to $KITTUI_SERVER_URL, then the config file
+  --name <name>       Name to be used instead of the one assigned by the server
+  --register <name>   Register the nickname, so that it is kept in the later sessions
+  --guest             Play as a guest even if an account has been registered
+  --practice          Start a practice game without connecting to the server
+  --replay <path>     Play back a replay saved from the results of a race
+  --log-file <path>   Write all the events to this file
+  --config <path>     Config file to be used instead of ~/.config/kittui-racer/config.json
+  --help              Print this message";
+
+/// Settings which can be written in the config file, every setting is optional
+#[derive(serde::Deserialize, Default)]
+struct ConfigFile {
+    server_url: Option<String>,
+    name: Option<String>,
+    log_file: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// `$XDG_CONFIG_HOME/kittui-racer/config.json`, or the same inside `~/.config`
+    fn default_path() -> Option<PathBuf> {
+        let config_directory = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(config_directory.join("kittui-racer").join("config.json"))
+    }
+
+    /// A missing file at the default path is not an error, the defaults are used instead
+    fn read(path: Option<PathBuf>) -> Result<Self, String> {
+        let is_explicit_path = path.is_some();
+        let Some(path) = path.or_else(Self::default_path) else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|error| format!("Invalid config file {}: {error}", path.display())),
+            Err(_) if !is_explicit_path => Ok(Self::default()),
+            Err(error) => Err(format!(
+                "Could not read config file {}: {error}",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// The account registered by the player, saved next to the config file
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SavedAccount {
+    pub nickname: String,
+    /// Secret with which the client logs in to the account
+    pub token: String,
+}
+
+impl SavedAccount {
+    /// A missing or invalid file is treated as no account
+    pub fn read(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        // The token is a secret, so only the owner can read the file
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+
+            // The mode is only applied to new files
+            if path.exists() {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+
+        let mut file = options.open(path)?;
+        file.write_all(contents.as_bytes())
+    }
+}
+
+/// Settings of the client
+/// Command line flags take precedence over the environment, which takes precedence over the config file
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    pub server_url: url::Url,
+    /// Name requested from the server, a random name is assigned if this is not present
+    pub name: Option<String>,
+    /// Skip the connection to the server and start practicing right away
+    pub practice: bool,
+    pub log_file: Option<PathBuf>,
+    /// Nickname to be registered, if there is no saved account
+    pub register: Option<String>,
+    /// Ignore the saved account and play as a guest
+    pub guest: bool,
+    /// File in which the registered account is saved
+    pub account_file: Option<PathBuf>,
+    /// Replay to be played back without connecting to the server
+    pub replay: Option<PathBuf>,
+    /// Directory in which the replays are saved
+    pub replay_directory: Option<PathBuf>,
+    /// Directory in which the personal bests of the practice games are saved
+    pub ghost_directory: Option<PathBuf>,
+}
+
+/// Only websocket urls can be used to connect to the server
+fn parse_server_url(server_url: &str) -> Result<url::Url, String> {
+    let url = url::Url::parse(server_url)
+        .map_err(|error| format!("Invalid server url {server_url}: {error}"))?;
+
+    match url.scheme() {
+        "ws" | "wss" => Ok(url),
+        scheme => Err(format!(
+            "Invalid server url {server_url}: expected ws:// or wss://, found {scheme}://"
+        )),
+    }
+}
+
+impl ClientConfig {
+    /// Build the config from the command line arguments, excluding the name of the binary
+    /// Returns the message to be printed if the arguments are not valid, or if help is asked for
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut server_url = None;
+        let mut name = None;
+        let mut practice = false;
+        let mut log_file = None;
+        let mut config_path = None;
+        let mut register = None;
+        let mut guest = false;
+        let mut replay = None;
+
+        while let Some(arg) = args.next() {
+            let mut value_of = |flag: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {flag}\n\n{USAGE}"))
+            };
+
+            match arg.as_str() {
+                "--server" => server_url = Some(value_of("--server")?),
+                "--name" => name = Some(value_of("--name")?),
+                "--register" => register = Some(value_of("--register")?),
+                "--guest" => guest = true,
+                "--log-file" => log_file = Some(PathBuf::from(value_of("--log-file")?)),
+                "--config" => config_path = Some(PathBuf::from(value_of("--config")?)),
+                "--practice" => practice = true,
+                "--replay" => replay = Some(PathBuf::from(value_of("--replay")?)),
+                "--help" | "-h" => return Err(USAGE.to_string()),
+                unknown => return Err(format!("Unknown argument {unknown}\n\n{USAGE}")),
+            }
+        }
+
+        // The account, the replays and the ghosts are saved in the same directory as the config file
+        let account_file = config_path
+            .clone()
+            .or_else(ConfigFile::default_path)
+            .map(|path| path.with_file_name("account.json"));
+        let replay_directory = account_file
+            .as_ref()
+            .map(|path| path.with_file_name("replays"));
+        let ghost_directory = account_file
+            .as_ref()
+            .map(|path| path.with_file_name("ghosts"));
+        let config_file = ConfigFile::read(config_path)?;
+
+        let server_url = server_url
+            .or_else(|| env::var(SERVER_URL_ENV_VAR).ok())
+            .or(config_file.server_url)
+            .unwrap_or_else(|| DEFAULT_SERVER_URL.to_string());
+
+        Ok(Self {
+            server_url: parse_server_url(&server_url)?,
+            name: name.or(config_file.name),
+            practice,
+            log_file: log_file.or(config_file.log_file),
+            register,
+            guest,
+            account_file,
+            replay,
+            replay_directory,
+            ghost_directory,
+        })
+    }
+
+    /// The account to log in to, or to register. Nothing is used if the player wants to be a guest
+    pub fn account_settings(&self) -> AccountSettings {
+        if self.guest {
+            return AccountSettings::default();
+        }
+
+        AccountSettings {
+            file: self.account_file.clone(),
+            saved: self.account_file.as_deref().and_then(SavedAccount::read),
+            register: self.register.clone(),
+        }
+    }
+
+    /// The url to connect to, along with the requested name
+    pub fn connection_url(&self) -> url::Url {
+        let mut url = self.server_url.clone();
+        if let Some(name) = self.name.as_ref() {
+            url.query_pairs_mut().append_pair("name", name);
+        }
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_account_is_read_back() {
+        let directory = env::temp_dir().join(format!("kittui-account-{}", std::process::id()));
+        let path = directory.join("account.json");
+        let account = SavedAccount {
+            nickname: "Tabby".to_string(),
+            token: "secret".to_string(),
+        };
+
+        account.write(&path).unwrap();
+        let saved_account = SavedAccount::read(&path).unwrap();
+        assert_eq!(saved_account.nickname, "Tabby");
+        assert_eq!(saved_account.token, "secret");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// A config file of its own for every test, so that the tests can run in parallel
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let directory =
+            env::temp_dir().join(format!("kittui-config-{}-{name}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.json");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn server_url_is_taken_from_the_flag_then_the_environment_then_the_config_file() {
+        let with_url = config_file("with-url", r#"{ "server_url": "wss://config.example" }"#);
+        let without_url = config_file("without-url", "{}");
+        let server_url = |arguments: &[&str]| {
+            ClientConfig::from_args(args(arguments))
+                .unwrap()
+                .server_url
+                .to_string()
+        };
+        let with_url_path = with_url.to_str().unwrap();
+        let without_url_path = without_url.to_str().unwrap();
+
+        // The environment is shared by the tests, so only this test sets the variable
+        env::set_var(SERVER_URL_ENV_VAR, "wss://environment.example");
+        assert_eq!(
+            server_url(&["--config", with_url_path, "--server", "ws://flag.example"]),
+            "ws://flag.example/"
+        );
+        assert_eq!(
+            server_url(&["--config", with_url_path]),
+            "wss://environment.example/"
+        );
+
+        env::remove_var(SERVER_URL_ENV_VAR);
+        assert_eq!(
+            server_url(&["--config", with_url_path]),
+            "wss://config.example/"
+        );
+        assert_eq!(
+            server_url(&["--config", without_url_path]),
+            format!("{DEFAULT_SERVER_URL}/")
+        );
+
+        fs::remove_dir_all(with_url.parent().unwrap()).unwrap();
+        fs::remove_dir_all(without_url.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn only_websocket_urls_are_accepted() {
+        assert!(parse_server_url("ws://127.0.0.1:8080").is_ok());
+        assert!(parse_server_url("wss://broken-glade-6034.fly.dev").is_ok());
+
+        let error = parse_server_url("https://broken-glade-6034.fly.dev").unwrap_err();
+        assert!(error.contains("expected ws:// or wss://, found https://"));
+        assert!(parse_server_url("not a url").is_err());
+    }
+
+    #[test]
+    fn flag_without_a_value_is_refused() {
+        let error = ClientConfig::from_args(args(&["--server"])).unwrap_err();
+        assert!(error.starts_with("Missing value for --server"));
+
+        let error = ClientConfig::from_args(args(&["--config"])).unwrap_err();
+        assert!(error.starts_with("Missing value for --config"));
+
+        let error = ClientConfig::from_args(args(&["--speed"])).unwrap_err();
+        assert!(error.starts_with("Unknown argument --speed"));
+    }
+
+    #[test]
+    fn saved_files_are_kept_next_to_the_config_file() {
+        let path = config_file("directories", "{}");
+        let directory = path.parent().unwrap();
+
+        let config = ClientConfig::from_args(args(&["--config", path.to_str().unwrap()])).unwrap();
+        assert_eq!(config.account_file, Some(directory.join("account.json")));
+        assert_eq!(config.replay_directory, Some(directory.join("replays")));
+        assert_eq!(config.ghost_directory, Some(directory.join("ghosts")));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,18 +1,30 @@
 use std::{
     collections::VecDeque,
     sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use tui::{
     backend::Backend,
-    layout::Rect,
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols,
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Wrap},
+    widgets::{
+        Axis, Block, Borders, Chart, Dataset, Gauge, GraphType, List, ListItem, Paragraph,
+        Sparkline, Wrap,
+    },
     Frame,
 };
 
-use crate::ui::types::{self, App, Layouts, Tab};
+use crate::{
+    models,
+    ui::{
+        layout_divider,
+        types::{self, App, Layouts, Tab},
+        utils,
+    },
+};
 
 fn draw_playground<B: Backend>(app: Arc<Mutex<App>>, playground_area: Rect, frame: &mut Frame<B>) {
     // App locking is required for all the tab variants
@@ -21,25 +33,73 @@ fn draw_playground<B: Backend>(app: Arc<Mutex<App>>, playground_area: Rect, fram
     match app.current_tab {
         // Draw the Typeracer UI with characters
         Tab::Game => {
-            let styles_text = app
-                .state
-                .game
-                .as_ref()
-                .unwrap()
-                .prompt_text
-                .iter()
-                .map(|prompt_key| {
-                    let mut span = Span::from(prompt_key.character.to_string()); //very bad
-                    span.style = prompt_key.state.get_style();
-                    span
-                })
-                .collect::<Vec<_>>();
+            let game_data = app.state.game.as_ref().unwrap();
+            let styles_text = match game_data.mode {
+                types::GameMode::Spectator => spectated_prompt_spans(game_data),
+                // The cursors of the opponents are shown over the typing of the player
+                types::GameMode::Replay => game_data
+                    .prompt_text
+                    .iter()
+                    .enumerate()
+                    .map(|(position, prompt_key)| {
+                        let cursor_of_opponent = game_data
+                            .racers
+                            .iter()
+                            .skip(1)
+                            .position(|racer| racer.cursor == position);
+
+                        let style = match cursor_of_opponent {
+                            Some(opponent_index) => Style::default()
+                                .fg(Color::Black)
+                                .bg(racer_color(opponent_index + 1)),
+                            None => prompt_key.state.get_style(),
+                        };
+                        Span::styled(prompt_key.character.to_string(), style)
+                    })
+                    .collect::<Vec<_>>(),
+                _ => {
+                    // The cursor of the ghost is drawn faintly, under the typing of the player
+                    let ghost_cursor = game_data.ghost_cursor();
+
+                    game_data
+                        .prompt_text
+                        .iter()
+                        .enumerate()
+                        .map(|(position, prompt_key)| {
+                            let mut span = Span::from(prompt_key.character.to_string()); //very bad
+                            span.style = prompt_key.state.get_style();
+                            if ghost_cursor == Some(position) {
+                                span.style = span.style.bg(Color::DarkGray);
+                            }
+                            span
+                        })
+                        .collect::<Vec<_>>()
+                }
+            };
 
-            let text = Text::from(Spans::from(styles_text));
+            let mut text = Text::from(Spans::from(styles_text));
+
+            if let Some(attribution) = game_data.prompt_attribution.as_ref() {
+                text.extend(Text::raw("\n"));
+                text.extend(Text::styled(
+                    attribution.to_owned(),
+                    Style::default()
+                        .add_modifier(Modifier::ITALIC)
+                        .add_modifier(Modifier::DIM),
+                ));
+            }
+
+            // The playback of a replay is described in the title
+            let block = match app.state.replay.as_ref() {
+                Some(playback) => Block::default()
+                    .borders(Borders::ALL)
+                    .title(playback.status_line()),
+                None => Block::default().borders(Borders::ALL),
+            };
 
             let drawable = Paragraph::new(text)
                 .wrap(Wrap { trim: true })
-                .block(Block::default().borders(Borders::ALL))
+                .block(block)
                 .alignment(tui::layout::Alignment::Center);
 
             frame.render_widget(drawable, playground_area);
@@ -54,17 +114,256 @@ fn draw_playground<B: Backend>(app: Arc<Mutex<App>>, playground_area: Rect, fram
                 .items
                 .iter()
                 .filter(|player| player.id != app.current_user.as_ref().unwrap().id) // user_id will have been set
-                .map(|player| ListItem::new(player.display_name.to_string()))
+                .map(|player| {
+                    // Busy players cannot be challenged, so they are dimmed
+                    let (badge, badge_color, name_style) = match player.status {
+                        types::UserStatus::Available => {
+                            (" Available ", Color::Green, Style::default())
+                        }
+                        types::UserStatus::Busy => (
+                            " Busy ",
+                            Color::Red,
+                            Style::default().add_modifier(Modifier::DIM),
+                        ),
+                    };
+
+                    // Guests are not rated
+                    let rating = player
+                        .rating
+                        .map(|rating| format!(" ({rating})"))
+                        .unwrap_or_default();
+
+                    let mut spans = vec![
+                        Span::styled(player.display_name.to_string(), name_style),
+                        Span::styled(rating, Style::default().add_modifier(Modifier::DIM)),
+                        Span::raw(" "),
+                        Span::styled(badge, Style::default().fg(Color::Black).bg(badge_color)),
+                    ];
+                    if player.bot {
+                        spans.push(Span::raw(" "));
+                        spans.push(Span::styled(
+                            " Bot ",
+                            Style::default().fg(Color::Black).bg(Color::Cyan),
+                        ));
+                    }
+
+                    ListItem::new(Spans::from(spans))
+                })
                 .collect::<Vec<_>>();
 
+            // Only the list in focus shows its selection
+            let (players_highlight, live_games_highlight) = match app.state.arena_focus {
+                types::ArenaFocus::Players => ("█ ", "  "),
+                types::ArenaFocus::LiveGames => ("  ", "█ "),
+            };
+
             let list = List::new(items)
                 .block(Block::default().borders(Borders::ALL).title("List"))
                 .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-                .highlight_symbol("█ ");
+                .highlight_symbol(players_highlight);
+
+            // Show the racers who have joined the lobby next to the players list
+            let players_area = if let Some(race_lobby) = app.state.race_lobby.as_ref() {
+                let chunks = layout_divider::split_half_horizontally(playground_area);
+
+                let lobby_items = race_lobby
+                    .participants
+                    .iter()
+                    .map(|participant| {
+                        if participant.id == race_lobby.owner_id {
+                            ListItem::new(Spans::from(vec![
+                                Span::raw(participant.display_name.to_string()),
+                                Span::raw(" "),
+                                Span::styled(
+                                    " Owner ",
+                                    Style::default().fg(Color::Black).bg(Color::Yellow),
+                                ),
+                            ]))
+                        } else {
+                            ListItem::new(participant.display_name.to_string())
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let lobby_title = match race_lobby.code.as_ref() {
+                    Some(code) => format!("Room {code}"),
+                    None => "Race".to_string(),
+                };
+                let lobby_list = List::new(lobby_items).block(
+                    Block::default().borders(Borders::ALL).title(format!(
+                        "{lobby_title} ({}/{})",
+                        race_lobby.participants.len(),
+                        models::MAXIMUM_RACERS
+                    )),
+                );
+
+                frame.render_widget(lobby_list, chunks[1]);
+                chunks[0]
+            } else {
+                playground_area
+            };
+
+            // Show the live games below the players, if any
+            if app.state.live_games.items.is_empty() {
+                frame.render_stateful_widget(list, players_area, &mut app.state.players.state);
+            } else {
+                let live_games_height =
+                    (app.state.live_games.items.len() as u16 + 2).min(MAXIMUM_LIVE_GAMES_HEIGHT);
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(3), Constraint::Length(live_games_height)])
+                    .split(players_area);
+
+                let current_time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                let live_game_items = app
+                    .state
+                    .live_games
+                    .items
+                    .iter()
+                    .map(|live_game| {
+                        let mut spans = vec![
+                            Span::raw(live_game.racer_names.join(" vs ")),
+                            Span::raw(" "),
+                        ];
+                        if live_game.ranked {
+                            spans.push(Span::styled(
+                                " Ranked ",
+                                Style::default().fg(Color::Black).bg(Color::Yellow),
+                            ));
+                            spans.push(Span::raw(" "));
+                        }
+
+                        let status = if current_time >= live_game.starts_at {
+                            "In progress".to_string()
+                        } else {
+                            format!("Starts in {}s", live_game.starts_at - current_time)
+                        };
+                        spans.push(Span::styled(
+                            status,
+                            Style::default().add_modifier(Modifier::DIM),
+                        ));
+
+                        ListItem::new(Spans::from(spans))
+                    })
+                    .collect::<Vec<_>>();
+
+                let live_games_list = List::new(live_game_items)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .title("Live games | Switch [Tab] | Spectate [Enter]"),
+                    )
+                    .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+                    .highlight_symbol(live_games_highlight);
 
-            frame.render_stateful_widget(list, playground_area, &mut app.state.players.state)
+                frame.render_stateful_widget(list, chunks[0], &mut app.state.players.state);
+                frame.render_stateful_widget(
+                    live_games_list,
+                    chunks[1],
+                    &mut app.state.live_games.state,
+                );
+            }
+        }
+
+        // Draw the summary of the game which has just ended
+        Tab::Results => {
+            let Some(results) = app.state.results.as_ref() else {
+                return;
+            };
+
+            let heading = match (results.disqualification.as_ref(), results.placement) {
+                (Some(note), _) => Spans::from(Span::styled(
+                    note.to_owned(),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                )),
+                (None, Some((position, racer_count))) => Spans::from(Span::styled(
+                    format!("Finished at position {position} of {racer_count}"),
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::BOLD),
+                )),
+                // Practice games are compared with the personal best on the prompt
+                (None, None) => {
+                    let heading = match results.previous_best {
+                        Some(previous_best) if results.time_taken < previous_best => format!(
+                            "New personal best, {:.1} seconds faster",
+                            (previous_best - results.time_taken) as f32 / 1000.0
+                        ),
+                        Some(previous_best) => format!(
+                            "Practice finished, {:.1} seconds behind your personal best",
+                            (results.time_taken - previous_best) as f32 / 1000.0
+                        ),
+                        None => "Practice finished".to_string(),
+                    };
+                    Spans::from(Span::styled(
+                        heading,
+                        Style::default()
+                            .fg(Color::Green)
+                            .add_modifier(Modifier::BOLD),
+                    ))
+                }
+            };
+
+            let slowest_words = results
+                .slowest_words
+                .iter()
+                .map(|(word, time_taken)| format!("{word} ({:.1}s)", *time_taken as f32 / 1000.0))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let mistyped_characters = results
+                .mistyped_characters
+                .iter()
+                .map(|(character, mistakes)| format!("'{character}' x{mistakes}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let rematch_action = match results.mode {
+                types::GameMode::Practice if results.raced_ghost => {
+                    "Race the ghost again [R/r] | Save replay [S/s] | Menu [Enter]"
+                }
+                types::GameMode::Practice => {
+                    "Practice again [R/r] | Save replay [S/s] | Menu [Enter]"
+                }
+                types::GameMode::Online | types::GameMode::Spectator | types::GameMode::Replay => {
+                    "Rematch [R/r] | Save replay [S/s] | Arena [Enter]"
+                }
+            };
+
+            let lines = vec![
+                heading,
+                Spans::from(""),
+                Spans::from(format!(
+                    "{:.0} WPM | {:.0}% accuracy | {:.1} seconds",
+                    results.wpm,
+                    results.accuracy,
+                    results.time_taken as f32 / 1000.0
+                )),
+                Spans::from(""),
+                Spans::from(format!("Slowest words: {slowest_words}")),
+                Spans::from(format!("Most mistyped: {mistyped_characters}")),
+                Spans::from(""),
+                Spans::from(Span::styled(
+                    rematch_action,
+                    Style::default().add_modifier(Modifier::DIM),
+                )),
+            ];
+
+            let drawable = Paragraph::new(Text::from(lines))
+                .wrap(Wrap { trim: true })
+                .block(Block::default().borders(Borders::ALL).title("Results"))
+                .alignment(tui::layout::Alignment::Center);
+
+            frame.render_widget(drawable, playground_area);
         }
 
+        Tab::Profile => draw_profile(&mut app, playground_area, frame),
+
+        Tab::Leaderboard => draw_leaderboard(&mut app, playground_area, frame),
+
         // Draw the menu, Options are whether to play the game or practice
         // If practice is selected -> Take user to Game page
         // If game is selected -> Take user to Arena
@@ -74,7 +373,12 @@ fn draw_playground<B: Backend>(app: Arc<Mutex<App>>, playground_area: Rect, fram
                 .menu
                 .items
                 .iter()
-                .map(|player_id| ListItem::new(player_id.to_owned()))
+                .map(|&menu_item| match menu_item {
+                    "Ranked" if app.state.in_ranked_queue => {
+                        ListItem::new("Ranked (looking for an opponent)")
+                    }
+                    _ => ListItem::new(menu_item),
+                })
                 .collect::<Vec<_>>();
 
             let list = List::new(list_items)
@@ -82,11 +386,254 @@ fn draw_playground<B: Backend>(app: Arc<Mutex<App>>, playground_area: Rect, fram
                 .highlight_style(Style::default().add_modifier(Modifier::BOLD))
                 .highlight_symbol("█ ");
 
-            frame.render_stateful_widget(list, playground_area, &mut app.state.menu.state)
+            // The code of a private room is entered below the menu
+            let menu_area = match app.state.room_code_input.as_ref() {
+                Some(room_code) => {
+                    let chunks = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Min(3), Constraint::Length(3)])
+                        .split(playground_area);
+
+                    let room_code_input = Paragraph::new(Spans::from(vec![
+                        Span::raw(room_code.to_owned()),
+                        Span::styled(" ", Style::default().bg(Color::Yellow)),
+                    ]))
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(Style::default().fg(Color::Yellow))
+                            .title(
+                                "Room code, empty to create a room | Enter [Enter] | Cancel [Esc]",
+                            ),
+                    );
+                    frame.render_widget(room_code_input, chunks[1]);
+                    chunks[0]
+                }
+                None => playground_area,
+            };
+
+            frame.render_stateful_widget(list, menu_area, &mut app.state.menu.state)
         }
     }
 }
 
+/// Height of the list of live games in the arena, longer lists are scrolled
+const MAXIMUM_LIVE_GAMES_HEIGHT: u16 = 8;
+
+/// Colors in which the cursors and the names of the racers are drawn while spectating
+const RACER_COLORS: [Color; models::MAXIMUM_RACERS] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Green,
+    Color::Yellow,
+    Color::LightBlue,
+    Color::LightRed,
+    Color::LightCyan,
+    Color::LightMagenta,
+];
+
+fn racer_color(racer_index: usize) -> Color {
+    RACER_COLORS[racer_index % RACER_COLORS.len()]
+}
+
+/// The prompt of a spectated game with the cursor of every racer, in the color of the racer
+/// The text typed by the leading racer is shown as typed
+fn spectated_prompt_spans(game_data: &types::UiGameData) -> Vec<Span<'static>> {
+    let leading_cursor = game_data
+        .racers
+        .iter()
+        .map(|racer| racer.cursor)
+        .max()
+        .unwrap_or_default();
+
+    game_data
+        .prompt_text
+        .iter()
+        .enumerate()
+        .map(|(position, prompt_key)| {
+            let cursor_of_racer = game_data
+                .racers
+                .iter()
+                .position(|racer| racer.cursor == position);
+
+            let style = match cursor_of_racer {
+                Some(racer_index) => Style::default()
+                    .fg(Color::Black)
+                    .bg(racer_color(racer_index)),
+                None if position < leading_cursor => {
+                    types::CharState::Touched(types::TouchState::Valid).get_style()
+                }
+                None => types::CharState::Untouched.get_style(),
+            };
+
+            Span::styled(prompt_key.character.to_string(), style)
+        })
+        .collect()
+}
+
+/// Number of characters of the prompt shown for every race in the history
+const HISTORY_PROMPT_LENGTH: usize = 30;
+
+/// Draw the statistics of the user, the WPM of the recent races and the list of the races
+fn draw_profile<B: Backend>(app: &mut App, area: Rect, frame: &mut Frame<B>) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(5),
+            Constraint::Percentage(40),
+            Constraint::Min(3),
+        ])
+        .split(area);
+
+    let stats_lines = match app.state.stats.as_ref() {
+        Some(stats) => vec![
+            Spans::from(format!(
+                "{} races | {} wins | Personal best {:.0} WPM | {}",
+                stats.races,
+                stats.wins,
+                stats.best_wpm,
+                stats
+                    .rating
+                    .map(|rating| format!("Rating {rating}"))
+                    .unwrap_or_else(|| "Unrated guest".to_string())
+            )),
+            Spans::from(format!(
+                "Last {} races: {:.0} WPM | {:.0}% accuracy",
+                stats.recent_races, stats.average_wpm, stats.average_accuracy
+            )),
+            Spans::from(Span::styled(
+                "Menu [Enter]",
+                Style::default().add_modifier(Modifier::DIM),
+            )),
+        ],
+        None => vec![Spans::from("Loading...")],
+    };
+    let title = app
+        .current_user
+        .as_ref()
+        .map(|user| user.display_name.to_owned())
+        .unwrap_or_else(|| "Profile".to_string());
+    let stats_paragraph = Paragraph::new(Text::from(stats_lines))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .alignment(tui::layout::Alignment::Center);
+    frame.render_widget(stats_paragraph, chunks[0]);
+
+    // The history is the most recent first, the chart goes from the oldest to the latest
+    let wpm_points = app
+        .state
+        .history
+        .items
+        .iter()
+        .rev()
+        .filter(|race| !race.disqualified)
+        .enumerate()
+        .map(|(index, race)| (index as f64, f64::from(race.wpm)))
+        .collect::<Vec<_>>();
+    let highest_wpm = wpm_points.iter().map(|(_, wpm)| *wpm).fold(0.0, f64::max);
+    // Round up to the next multiple of 20 to leave some space above the line
+    let wpm_bound = (highest_wpm / 20.0).floor() * 20.0 + 20.0;
+    let race_bound = wpm_points.len().saturating_sub(1).max(1) as f64;
+
+    let datasets = vec![Dataset::default()
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(Color::Yellow))
+        .data(&wpm_points)];
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title("WPM"))
+        .x_axis(Axis::default().bounds([0.0, race_bound]))
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, wpm_bound])
+                .labels(vec![Span::raw("0"), Span::raw(format!("{wpm_bound:.0}"))]),
+        );
+    frame.render_widget(chart, chunks[1]);
+
+    let history_items = app
+        .state
+        .history
+        .items
+        .iter()
+        .map(|race| {
+            let result = if race.disqualified {
+                Span::styled(" DQ ", Style::default().fg(Color::Black).bg(Color::Red))
+            } else {
+                Span::raw(format!("#{}/{}", race.position, race.racer_count))
+            };
+            let prompt_excerpt = race
+                .prompt_text
+                .chars()
+                .take(HISTORY_PROMPT_LENGTH)
+                .collect::<String>();
+
+            ListItem::new(Spans::from(vec![
+                result,
+                Span::raw(format!(
+                    " {:.0} WPM | {:.0}% | {} ",
+                    race.wpm,
+                    race.accuracy,
+                    utils::format_age(race.finished_at)
+                )),
+                Span::styled(prompt_excerpt, Style::default().add_modifier(Modifier::DIM)),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let history_list = List::new(history_items)
+        .block(Block::default().borders(Borders::ALL).title("Recent races"))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol("█ ");
+    frame.render_stateful_widget(history_list, chunks[2], &mut app.state.history.state);
+}
+
+/// Draw the players of the chosen leaderboard, the current user is highlighted
+fn draw_leaderboard<B: Backend>(app: &mut App, area: Rect, frame: &mut Frame<B>) {
+    let leaderboard = &app.state.leaderboard;
+    let current_user_id = app.current_user.as_ref().map(|user| user.id.as_str());
+
+    let (category_label, unit) = match leaderboard.category {
+        models::LeaderboardCategory::BestWpm => ("Best WPM", "WPM"),
+        models::LeaderboardCategory::Rating => ("Rating", ""),
+        models::LeaderboardCategory::MostRaces => ("Most races", "races"),
+    };
+    let period_label = match leaderboard.period {
+        models::LeaderboardPeriod::Daily => "Today",
+        models::LeaderboardPeriod::Weekly => "This week",
+        models::LeaderboardPeriod::AllTime => "All time",
+    };
+
+    let items = leaderboard
+        .entries
+        .items
+        .iter()
+        .map(|entry| {
+            let style = if Some(entry.user_id.as_str()) == current_user_id {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+
+            ListItem::new(Spans::from(vec![
+                Span::styled(format!("#{:<4}", entry.position), style),
+                Span::styled(entry.display_name.to_owned(), style),
+                Span::styled(
+                    format!(" {:.0} {unit}", entry.value),
+                    Style::default().add_modifier(Modifier::DIM),
+                ),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let title =
+        format!("{category_label} | {period_label} | Category [c] | Period [p] | Menu [Enter]");
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol("█ ");
+
+    frame.render_stateful_widget(list, area, &mut app.state.leaderboard.entries.state);
+}
+
 /// Get the current event and the modifier with which to display the event
 fn get_event_and_modifier(
     events_vector: &mut VecDeque<types::Event>,
@@ -140,9 +687,19 @@ fn draw_bottom_bar<B: Backend>(app: Arc<Mutex<App>>, area: Rect, frame: &mut Fra
         .map(|event| (event.log_type.get_color(), event.message.to_owned()))
         .unwrap_or((Color::Yellow, "No new events to be displayed".to_string()));
 
+    let title = Spans::from(vec![
+        Span::raw("Events "),
+        Span::styled(
+            format!(" {} ", app.connection.label()),
+            Style::default()
+                .fg(Color::Black)
+                .bg(app.connection.get_color()),
+        ),
+    ]);
+
     let paragraph_widget = Paragraph::new(Text::from(event_message))
         .style(Style::default().fg(event_color).add_modifier(modifier))
-        .block(Block::default().borders(Borders::ALL).title("Events"));
+        .block(Block::default().borders(Borders::ALL).title(title));
 
     frame.render_widget(paragraph_widget, area)
 }
@@ -153,39 +710,185 @@ fn draw_progress_bar<B: Backend>(app: Arc<Mutex<App>>, area: Vec<Rect>, frame: &
     // Draw progress bar only in game mode
     if app.current_tab == Tab::Game {
         let game_data = app.state.game.as_ref().unwrap();
+        let current_user_id = app.current_user.as_ref().map(|user| user.id.as_str());
 
-        let my_progress_gauge = Gauge::default()
-            .block(Block::default().borders(Borders::ALL).title("My Progress"))
-            .gauge_style(
-                Style::default()
-                    .fg(Color::White)
-                    .bg(Color::Black)
-                    .add_modifier(Modifier::ITALIC),
-            )
-            .percent(game_data.my_progress);
-
-        let opponent_progress_gauge = Gauge::default()
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("Opponent Progress"),
-            )
-            .gauge_style(
+        for (racer_index, (racer, racer_area)) in game_data.racers.iter().zip(area).enumerate() {
+            // The live statistics of the racer are shown next to the gauge
+            let chunks = layout_divider::split_half_horizontally(racer_area);
+
+            // Progress of the current user is known locally, and is more recent than the server's
+            // The player of a practice game, followed by the ghost, and of a replay is the first racer
+            let is_current_user = (matches!(
+                game_data.mode,
+                types::GameMode::Practice | types::GameMode::Replay
+            ) && racer_index == 0)
+                || Some(racer.user_id.as_str()) == current_user_id;
+            let (title, progress, stats) = if is_current_user {
+                (
+                    "My Progress".to_string(),
+                    game_data.my_progress,
+                    game_data.stats(),
+                )
+            } else if racer.disconnected {
+                (
+                    format!("{} (disconnected)", racer.display_name),
+                    racer.progress,
+                    racer.stats,
+                )
+            } else {
+                (racer.display_name.to_owned(), racer.progress, racer.stats)
+            };
+
+            // Spectators tell the cursors apart by the colors of the names
+            let is_watched = matches!(
+                game_data.mode,
+                types::GameMode::Spectator | types::GameMode::Replay
+            );
+            let title_style = if is_watched && !is_current_user {
+                Style::default().fg(racer_color(racer_index))
+            } else {
                 Style::default()
-                    .fg(Color::White)
-                    .bg(Color::Black)
-                    .add_modifier(Modifier::ITALIC),
-            )
-            .percent(game_data.opponent_progress);
-
-        frame.render_widget(my_progress_gauge, area[0]);
-        frame.render_widget(opponent_progress_gauge, area[1]);
+            };
+
+            let progress_gauge = Gauge::default()
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(Span::styled(title, title_style)),
+                )
+                .gauge_style(
+                    Style::default()
+                        .fg(Color::White)
+                        .bg(Color::Black)
+                        .add_modifier(Modifier::ITALIC),
+                )
+                .percent(progress.min(100));
+
+            frame.render_widget(progress_gauge, chunks[0]);
+
+            let stats_title = format!(
+                "{:.0} WPM | {:.0}% | {} errors",
+                stats.net_wpm, stats.accuracy, stats.uncorrected_errors
+            );
+            let stats_block = Block::default().borders(Borders::ALL).title(stats_title);
+
+            // Only the local typing is known keystroke by keystroke, so only it has a sparkline
+            if is_current_user {
+                let wpm_history = game_data.wpm_history();
+                let visible_points = usize::from(chunks[1].width.saturating_sub(2));
+                let recent_wpm = &wpm_history[wpm_history.len().saturating_sub(visible_points)..];
+
+                let wpm_sparkline = Sparkline::default()
+                    .block(stats_block)
+                    .data(recent_wpm)
+                    .style(Style::default().fg(Color::Yellow));
+
+                frame.render_widget(wpm_sparkline, chunks[1]);
+            } else {
+                let gross_wpm =
+                    Paragraph::new(Text::from(format!("Gross {:.0} WPM", stats.gross_wpm)))
+                        .style(Style::default().add_modifier(Modifier::DIM))
+                        .block(stats_block);
+
+                frame.render_widget(gross_wpm, chunks[1]);
+            }
+        }
+    }
+}
+
+/// Draw the latest chat messages which fit, and the message being written while the chat is focused
+fn draw_chat<B: Backend>(app: Arc<Mutex<App>>, area: Rect, frame: &mut Frame<B>) {
+    let app = app.lock().unwrap();
+    if !app.is_connected() || !app.supports(models::Feature::Chat) {
+        return;
+    }
+
+    let chat = &app.state.chat;
+    let (messages_area, input_area) = if chat.focused {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(3)])
+            .split(area);
+        (chunks[0], Some(chunks[1]))
+    } else {
+        (area, None)
+    };
+
+    // Messages wrap, so the lines of each are counted to keep the latest ones in view
+    let width = usize::from(messages_area.width.saturating_sub(2)).max(1);
+    let mut available_lines = usize::from(messages_area.height.saturating_sub(2));
+    let mut visible_lines = VecDeque::new();
+    for chat_line in chat.messages.iter().rev() {
+        let prefix = if chat_line.in_race { "[race] " } else { "" };
+        let length = prefix.len()
+            + chat_line.display_name.chars().count()
+            + 2
+            + chat_line.text.chars().count();
+        let line_count = (length - 1) / width + 1;
+        if line_count > available_lines {
+            break;
+        }
+        available_lines -= line_count;
+
+        let name_color = if chat_line.is_own {
+            Color::Yellow
+        } else {
+            Color::Cyan
+        };
+        visible_lines.push_front(Spans::from(vec![
+            Span::styled(prefix, Style::default().add_modifier(Modifier::DIM)),
+            Span::styled(
+                format!("{}: ", chat_line.display_name),
+                Style::default().fg(name_color),
+            ),
+            Span::raw(chat_line.text.to_owned()),
+        ]));
+    }
+
+    let (title, border_style) = if chat.focused {
+        (
+            "Chat | Send [Enter] | Leave [Esc]",
+            Style::default().fg(Color::Yellow),
+        )
+    } else {
+        ("Chat [Tab]", Style::default())
+    };
+    let messages = Paragraph::new(Text::from(Vec::from(visible_lines)))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style)
+                .title(title),
+        );
+    frame.render_widget(messages, messages_area);
+
+    if let Some(input_area) = input_area {
+        // The end of a long message is kept in view while it is written
+        let visible_characters = usize::from(input_area.width.saturating_sub(3));
+        let input_length = chat.input.chars().count();
+        let visible_input = chat
+            .input
+            .chars()
+            .skip(input_length.saturating_sub(visible_characters))
+            .collect::<String>();
+
+        let input = Paragraph::new(Spans::from(vec![
+            Span::raw(visible_input),
+            Span::styled(" ", Style::default().bg(Color::Yellow)),
+        ]))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style)
+                .title(format!("{}/{}", input_length, models::MAXIMUM_CHAT_LENGTH)),
+        );
+        frame.render_widget(input, input_area);
     }
 }
 
 /// Draw the UI from layout
 /// Based on the current active tab, Data drawn will be different
-
 pub fn draw_ui_from_layout<B: Backend>(
     app: Arc<Mutex<App>>,
     layouts: Layouts,
@@ -193,6 +896,7 @@ pub fn draw_ui_from_layout<B: Backend>(
 ) {
     draw_playground(app.clone(), layouts.playground, frame);
     draw_bottom_bar(app.clone(), layouts.bottom_bar, frame);
+    draw_chat(app.clone(), layouts.chat, frame);
 
     // This will be drawn only in case of game mode
     draw_progress_bar(app, layouts.progress_bars, frame);
@@ -31,4 +31,20 @@ pub enum TransitionAction {
     Init,
     Quit,
     AcceptChallenge,
+    RejectChallenge,
+    CancelChallenge,
+    CreateRace,
+    JoinRace,
+    LeaveRace,
+    StartRace,
+    Rematch,
+    NextCategory,
+    NextPeriod,
+    SwitchFocus,
+    SaveReplay,
+    TogglePause,
+    NormalSpeed,
+    DoubleSpeed,
+    SeekBackward,
+    SeekForward,
 }
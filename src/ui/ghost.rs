@@ -0,0 +1,112 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use rand::seq::SliceRandom;
+
+use crate::{models, ui::replay::Replay};
+
+/// Id of the racer which replays the personal best in a practice game
+pub const GHOST_ID: &str = "ghost";
+
+/// The fastest practice run of the player on a prompt, raced against as a ghost
+#[derive(Clone)]
+pub struct Ghost {
+    pub replay: Replay,
+}
+
+impl Ghost {
+    /// Milliseconds in which the prompt was typed
+    pub fn finished_in(&self) -> u64 {
+        self.replay.duration()
+    }
+
+    /// Progress of the ghost `elapsed` milliseconds into the game, as the keystrokes were recorded
+    pub fn racer_at(&self, elapsed: u64) -> models::RacerProgress {
+        let typing = self.replay.typing_at(elapsed);
+
+        models::RacerProgress {
+            user_id: GHOST_ID.to_string(),
+            display_name: "Personal best".to_string(),
+            progress: typing.my_progress,
+            cursor: typing.correct_prefix_length(),
+            stats: typing.stats(),
+            disconnected: false,
+        }
+    }
+}
+
+/// Personal bests saved in a directory, one replay file for every prompt
+pub struct GhostStore {
+    directory: PathBuf,
+}
+
+impl GhostStore {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// The file of the prompt is named after a FNV-1a hash of its text, which is stable across builds
+    fn path(&self, prompt_text: &str) -> PathBuf {
+        let hash = prompt_text
+            .bytes()
+            .fold(0xcbf29ce484222325_u64, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+            });
+        self.directory.join(format!("ghost-{hash:016x}.json"))
+    }
+
+    fn read(path: &Path, prompt_text: Option<&str>) -> Option<Ghost> {
+        let replay = Replay::read(path).ok()?;
+
+        // Two prompts may share a hash, the ghost of the other one is not raced against
+        match prompt_text {
+            Some(prompt_text) if replay.prompt_text != prompt_text => None,
+            _ => Some(Ghost { replay }),
+        }
+    }
+
+    pub fn load(&self, prompt_text: &str) -> Option<Ghost> {
+        Self::read(&self.path(prompt_text), Some(prompt_text))
+    }
+
+    /// The ghost of a random prompt which has been finished before
+    pub fn pick(&self) -> Option<Ghost> {
+        let entries = fs::read_dir(&self.directory).ok()?;
+        let paths = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .is_some_and(|file_name| {
+                        file_name.starts_with("ghost-") && file_name.ends_with(".json")
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        paths
+            .choose(&mut rand::thread_rng())
+            .and_then(|path| Self::read(path, None))
+    }
+
+    /// Save the run as the ghost of its prompt if it is faster than the personal best
+    /// Returns the milliseconds of the personal best before this run, if there was one
+    pub fn record(&self, replay: &Replay) -> io::Result<Option<u64>> {
+        let previous_best = self
+            .load(&replay.prompt_text)
+            .map(|ghost| ghost.finished_in());
+
+        let is_personal_best =
+            !matches!(previous_best, Some(previous_best) if replay.duration() >= previous_best);
+        if is_personal_best {
+            fs::create_dir_all(&self.directory)?;
+
+            let contents = serde_json::to_string(replay)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            fs::write(self.path(&replay.prompt_text), contents)?;
+        }
+        Ok(previous_best)
+    }
+}
@@ -4,11 +4,7 @@ use crossterm::event::KeyCode;
 
 use crate::{
     models,
-    ui::{
-        self,
-        fsm::TransitionAction,
-        types::{self, CharState, TouchState},
-    },
+    ui::{self, fsm::TransitionAction, types},
 };
 
 /// Handle input if current tab is game tab and game status is InProgress
@@ -17,37 +13,67 @@ use crate::{
 /// Check whether the entered key is same as expected
 /// Update the state of characters based on this
 pub fn handle_game_input(app: &mut types::App, input: KeyCode) -> bool {
+    let is_spectating = app
+        .state
+        .game
+        .as_ref()
+        .is_some_and(|game_data| game_data.mode == types::GameMode::Spectator);
+    if is_spectating {
+        return handle_spectator_input(app, input);
+    }
+    if app.state.replay.is_some() {
+        return handle_replay_input(app, input);
+    }
+
     let position = app.state.cursor_position as usize;
     let game_data = app.state.game.as_mut().unwrap();
-    let prompt_text = &mut game_data.prompt_text;
 
     if game_data.status == models::GameStatus::InProgress {
         match input {
             KeyCode::Char(character) => {
-                if prompt_text.get(position).unwrap().character.eq(&character) {
-                    prompt_text.get_mut(position).unwrap().state =
-                        CharState::Touched(TouchState::Valid);
-                } else {
-                    prompt_text.get_mut(position).unwrap().state =
-                        CharState::Touched(TouchState::Invalid);
-                }
-                if position + 1 != prompt_text.len() {
+                let is_correct = game_data.type_character(position, character);
+                if position + 1 != game_data.prompt_text.len() {
                     app.state.cursor_position += 1;
-                    prompt_text.get_mut(position + 1).unwrap().state = CharState::CursorPosition;
                 }
 
-                // Update the progress
-                let my_progress = ui::utils::calculate_progress(position, prompt_text.len());
-                game_data.update_current_progress(my_progress, &app.event_sender);
+                // Only the characters typed correctly from the start of the prompt count as progress
+                let prompt_length = game_data.prompt_text.len();
+                let elapsed = game_data.elapsed_millis();
+                game_data.record_keystroke(position, is_correct, elapsed);
+                game_data
+                    .recorder
+                    .record_keystroke(elapsed, position, character);
+                let my_progress =
+                    ui::utils::calculate_progress(game_data.correct_prefix_length(), prompt_length);
+                let keystroke = models::Keystroke {
+                    position,
+                    character,
+                    elapsed,
+                };
+                let progress_sent =
+                    game_data.update_current_progress(my_progress, keystroke, &app.event_sender);
+
+                // Practice games are not known to the server, so they are finished by the client
+                let is_practice_completed =
+                    game_data.mode == types::GameMode::Practice && game_data.is_prompt_completed();
+
+                if let Err(error) = progress_sent {
+                    app.add_log_event(types::Event::error(&error.to_string(), 1, true));
+                }
+                if is_practice_completed {
+                    app.finish_practice();
+                }
 
                 false
             }
             KeyCode::Backspace => {
                 if app.state.cursor_position > 0 {
                     // Make current character as next character
-                    prompt_text.get_mut(position).unwrap().state = CharState::Untouched;
+                    game_data.erase_character(position - 1);
                     app.state.cursor_position -= 1;
-                    prompt_text.get_mut(position - 1).unwrap().state = CharState::CursorPosition;
+
+                    let elapsed = game_data.elapsed_millis();
+                    game_data.recorder.record_backspace(elapsed, position - 1);
 
                     false
                 } else {
@@ -62,26 +88,120 @@ pub fn handle_game_input(app: &mut types::App, input: KeyCode) -> bool {
     }
 }
 
-/// This is a place where users can challenge other players
+/// Nothing can be typed in a spectated game, the user can only leave it
+/// Challenges can be answered while spectating
 /// Returns a bool which indicates whether to quit the app or not
-pub fn handle_arena_input(app: &mut types::App, input: KeyCode) -> bool {
+fn handle_spectator_input(app: &mut types::App, input: KeyCode) -> bool {
     let action = if app.state.challenge.is_some() {
+        challenge_action(input)
+    } else {
         match input {
-            KeyCode::Char('a') => TransitionAction::AcceptChallenge,
-            // Todo: blink the event bar
+            KeyCode::Left | KeyCode::Enter | KeyCode::Char('h') => TransitionAction::Unselect,
+            KeyCode::Esc => TransitionAction::Quit,
             _ => TransitionAction::Nop,
         }
+    };
+
+    match action {
+        TransitionAction::Unselect => {
+            app.stop_spectating();
+            false
+        }
+        TransitionAction::Quit => true,
+        TransitionAction::AcceptChallenge => {
+            app.accept_current_challenge();
+            false
+        }
+        TransitionAction::RejectChallenge => {
+            app.reject_current_challenge();
+            false
+        }
+        TransitionAction::CancelChallenge => {
+            app.cancel_current_challenge();
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Control the playback of a replay
+/// Returns a bool which indicates whether to quit the app or not
+fn handle_replay_input(app: &mut types::App, input: KeyCode) -> bool {
+    let action = match input {
+        KeyCode::Char(' ') => TransitionAction::TogglePause,
+        KeyCode::Char('1') => TransitionAction::NormalSpeed,
+        KeyCode::Char('2') => TransitionAction::DoubleSpeed,
+        KeyCode::Left | KeyCode::Char('h') => TransitionAction::SeekBackward,
+        KeyCode::Right | KeyCode::Char('l') => TransitionAction::SeekForward,
+        KeyCode::Esc => TransitionAction::Quit,
+        _ => TransitionAction::Nop,
+    };
+
+    let Some(playback) = app.state.replay.as_mut() else {
+        return false;
+    };
+
+    match action {
+        TransitionAction::TogglePause => playback.toggle_pause(),
+        TransitionAction::NormalSpeed => playback.speed = 1,
+        TransitionAction::DoubleSpeed => playback.speed = 2,
+        TransitionAction::SeekBackward => playback.seek_backward(),
+        TransitionAction::SeekForward => playback.seek_forward(),
+        TransitionAction::Quit => return true,
+        _ => return false,
+    }
+
+    // Show the change right away instead of on the next tick
+    app.update_replay();
+    false
+}
+
+/// When there is a challenge waiting for a response, only the keys to respond to it are handled
+fn challenge_action(input: KeyCode) -> TransitionAction {
+    match input {
+        KeyCode::Char('a') | KeyCode::Char('A') => TransitionAction::AcceptChallenge,
+        KeyCode::Char('r') | KeyCode::Char('R') => TransitionAction::RejectChallenge,
+        KeyCode::Char('c') | KeyCode::Char('C') => TransitionAction::CancelChallenge,
+        // Todo: blink the event bar
+        _ => TransitionAction::Nop,
+    }
+}
+
+/// This is a place where users can challenge other players, or spectate the live games
+/// Returns a bool which indicates whether to quit the app or not
+pub fn handle_arena_input(app: &mut types::App, input: KeyCode) -> bool {
+    let action = if app.state.challenge.is_some() {
+        challenge_action(input)
     } else {
         match input {
             KeyCode::Down | KeyCode::Char('j') => TransitionAction::MoveDown,
             KeyCode::Up | KeyCode::Char('k') => TransitionAction::MoveUp,
             KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') => TransitionAction::Select,
+            KeyCode::Char('n') | KeyCode::Char('N') => TransitionAction::CreateRace,
+            KeyCode::Char('y') | KeyCode::Char('Y') => TransitionAction::JoinRace,
+            KeyCode::Char('x') | KeyCode::Char('X') => TransitionAction::LeaveRace,
+            KeyCode::Char('s') | KeyCode::Char('S') => TransitionAction::StartRace,
+            KeyCode::Tab => TransitionAction::SwitchFocus,
             KeyCode::Esc => TransitionAction::Quit,
             _ => TransitionAction::Nop,
         }
     };
 
+    let is_live_games_focused = app.state.arena_focus == types::ArenaFocus::LiveGames;
+
     match action {
+        TransitionAction::MoveDown if is_live_games_focused => {
+            app.state.live_games.next();
+            false
+        }
+        TransitionAction::MoveUp if is_live_games_focused => {
+            app.state.live_games.previous();
+            false
+        }
+        TransitionAction::Select if is_live_games_focused => {
+            app.spectate_selected_game();
+            false
+        }
         TransitionAction::MoveDown => {
             app.state.players.next();
             false
@@ -90,20 +210,70 @@ pub fn handle_arena_input(app: &mut types::App, input: KeyCode) -> bool {
             app.state.players.previous();
             false
         }
+        TransitionAction::SwitchFocus => {
+            app.switch_arena_focus();
+            false
+        }
         TransitionAction::Select => {
-            // Challenge the player
+            // Challenge the player, or invite them if the user owns a race lobby
             // Steps to be taken
             // Send Challenge(player_id), message to be handled by the websocket
+            let owned_race_id = app
+                .state
+                .race_lobby
+                .as_ref()
+                .filter(|_| app.is_race_owner())
+                .map(|race_lobby| race_lobby.race_id.to_owned());
+
             if let Some(player) = app.state.players.get_selected_item() {
-                player.challenge(app.event_sender.clone())
+                if player.status == types::UserStatus::Busy {
+                    let busy_player_error = types::Event::error(
+                        &format!("{} is busy in a game", player.display_name),
+                        1,
+                        false,
+                    );
+                    app.add_log_event(busy_player_error);
+                } else {
+                    let sent = match owned_race_id {
+                        Some(race_id) => player.invite(&race_id, app.event_sender.clone()),
+                        None => player.challenge(app.event_sender.clone()),
+                    };
+                    if let Err(error) = sent {
+                        app.add_log_event(types::Event::error(&error.to_string(), 1, true));
+                    }
+                }
             }
             false
         }
+        TransitionAction::CreateRace => {
+            app.create_race();
+            false
+        }
+        TransitionAction::JoinRace => {
+            app.join_invited_race();
+            false
+        }
+        TransitionAction::LeaveRace => {
+            app.leave_race();
+            false
+        }
+        TransitionAction::StartRace => {
+            app.start_race();
+            false
+        }
         TransitionAction::Quit => true,
         TransitionAction::AcceptChallenge => {
             app.accept_current_challenge();
             false
         }
+        TransitionAction::RejectChallenge => {
+            app.reject_current_challenge();
+            false
+        }
+        TransitionAction::CancelChallenge => {
+            app.cancel_current_challenge();
+            false
+        }
         _ => false,
     }
 }
@@ -112,11 +282,7 @@ pub fn handle_arena_input(app: &mut types::App, input: KeyCode) -> bool {
 /// Returns a bool which indicates whether to quit the app or not
 pub fn handle_menu_input(app: &mut types::App, input: KeyCode) -> bool {
     let action = if app.state.challenge.is_some() {
-        match input {
-            KeyCode::Char('a') => TransitionAction::AcceptChallenge,
-            // Todo: blink the event bar
-            _ => TransitionAction::Nop,
-        }
+        challenge_action(input)
     } else {
         match input {
             KeyCode::Down | KeyCode::Char('j') => TransitionAction::MoveDown,
@@ -139,12 +305,26 @@ pub fn handle_menu_input(app: &mut types::App, input: KeyCode) -> bool {
         TransitionAction::Select => app
             .state
             .menu
-            .state
-            .selected()
-            .map(|index| {
-                match index {
-                    0 => app.current_tab = types::Tab::Arena,
-                    _ => app.current_tab = types::Tab::Game,
+            .get_selected_item()
+            .copied()
+            .map(|menu_item| {
+                match menu_item {
+                    // Practice is available even without the server
+                    "Practice" => app.start_practice(),
+                    "Ghost race" => app.race_ghost(None),
+                    _ if !app.is_connected() => {
+                        let offline_error = types::Event::error(
+                            "Not connected to the server, only practice is available",
+                            2,
+                            false,
+                        );
+                        app.add_log_event(offline_error);
+                    }
+                    "Game" => app.current_tab = types::Tab::Arena,
+                    "Ranked" => app.toggle_ranked_queue(),
+                    "Private room" => app.open_private_room(),
+                    "Profile" => app.open_profile(),
+                    _ => app.open_leaderboard(),
                 }
                 false
             })
@@ -154,22 +334,233 @@ pub fn handle_menu_input(app: &mut types::App, input: KeyCode) -> bool {
             app.accept_current_challenge();
             false
         }
+        TransitionAction::RejectChallenge => {
+            app.reject_current_challenge();
+            false
+        }
+        TransitionAction::CancelChallenge => {
+            app.cancel_current_challenge();
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Handle the input on the summary of a finished game
+/// Returns a bool which indicates whether to quit the app or not
+pub fn handle_results_input(app: &mut types::App, input: KeyCode) -> bool {
+    // The opponent may ask for a rematch while the results are being viewed
+    let action = if app.state.challenge.is_some() {
+        challenge_action(input)
+    } else {
+        match input {
+            KeyCode::Char('r') | KeyCode::Char('R') => TransitionAction::Rematch,
+            KeyCode::Char('s') | KeyCode::Char('S') => TransitionAction::SaveReplay,
+            KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') => TransitionAction::Select,
+            KeyCode::Esc => TransitionAction::Quit,
+            _ => TransitionAction::Nop,
+        }
+    };
+
+    match action {
+        TransitionAction::Rematch => {
+            app.rematch();
+            false
+        }
+        TransitionAction::SaveReplay => {
+            app.save_replay();
+            false
+        }
+        TransitionAction::Select => {
+            app.leave_results();
+            false
+        }
+        TransitionAction::Quit => true,
+        TransitionAction::AcceptChallenge => {
+            app.accept_current_challenge();
+            false
+        }
+        TransitionAction::RejectChallenge => {
+            app.reject_current_challenge();
+            false
+        }
+        TransitionAction::CancelChallenge => {
+            app.cancel_current_challenge();
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Scroll through the recent races of the user
+/// Returns a bool which indicates whether to quit the app or not
+pub fn handle_profile_input(app: &mut types::App, input: KeyCode) -> bool {
+    let action = if app.state.challenge.is_some() {
+        challenge_action(input)
+    } else {
+        match input {
+            KeyCode::Down | KeyCode::Char('j') => TransitionAction::MoveDown,
+            KeyCode::Up | KeyCode::Char('k') => TransitionAction::MoveUp,
+            KeyCode::Left | KeyCode::Enter | KeyCode::Char('h') => TransitionAction::Unselect,
+            KeyCode::Esc => TransitionAction::Quit,
+            _ => TransitionAction::Nop,
+        }
+    };
+
+    match action {
+        TransitionAction::MoveDown => {
+            app.state.history.next();
+            false
+        }
+        TransitionAction::MoveUp => {
+            app.state.history.previous();
+            false
+        }
+        TransitionAction::Unselect => {
+            app.current_tab = types::Tab::Menu;
+            false
+        }
+        TransitionAction::Quit => true,
+        TransitionAction::AcceptChallenge => {
+            app.accept_current_challenge();
+            false
+        }
+        TransitionAction::RejectChallenge => {
+            app.reject_current_challenge();
+            false
+        }
+        TransitionAction::CancelChallenge => {
+            app.cancel_current_challenge();
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Scroll through the leaderboard, and switch between the categories and periods
+/// Returns a bool which indicates whether to quit the app or not
+pub fn handle_leaderboard_input(app: &mut types::App, input: KeyCode) -> bool {
+    let action = if app.state.challenge.is_some() {
+        challenge_action(input)
+    } else {
+        match input {
+            KeyCode::Down | KeyCode::Char('j') => TransitionAction::MoveDown,
+            KeyCode::Up | KeyCode::Char('k') => TransitionAction::MoveUp,
+            KeyCode::Char('c') | KeyCode::Char('C') => TransitionAction::NextCategory,
+            KeyCode::Char('p') | KeyCode::Char('P') => TransitionAction::NextPeriod,
+            KeyCode::Left | KeyCode::Enter | KeyCode::Char('h') => TransitionAction::Unselect,
+            KeyCode::Esc => TransitionAction::Quit,
+            _ => TransitionAction::Nop,
+        }
+    };
+
+    match action {
+        TransitionAction::MoveDown => {
+            app.state.leaderboard.entries.next();
+            false
+        }
+        TransitionAction::MoveUp => {
+            app.state.leaderboard.entries.previous();
+            false
+        }
+        TransitionAction::NextCategory => {
+            app.state.leaderboard.next_category();
+            app.fetch_leaderboard();
+            false
+        }
+        TransitionAction::NextPeriod => {
+            app.state.leaderboard.next_period();
+            app.fetch_leaderboard();
+            false
+        }
+        TransitionAction::Unselect => {
+            app.current_tab = types::Tab::Menu;
+            false
+        }
+        TransitionAction::Quit => true,
+        TransitionAction::AcceptChallenge => {
+            app.accept_current_challenge();
+            false
+        }
+        TransitionAction::RejectChallenge => {
+            app.reject_current_challenge();
+            false
+        }
+        TransitionAction::CancelChallenge => {
+            app.cancel_current_challenge();
+            false
+        }
         _ => false,
     }
 }
 
 /// Handle the input for a key event
 /// Returns a bool which indicates whether to quit the app or not
+/// Keys go to the message being written, so that nothing is typed in a game by mistake
+/// Tab or Esc leave the chat
+fn handle_chat_input(app: &mut types::App, input: KeyCode) -> bool {
+    match input {
+        KeyCode::Char(character)
+            if app.state.chat.input.chars().count() < models::MAXIMUM_CHAT_LENGTH =>
+        {
+            app.state.chat.input.push(character)
+        }
+        KeyCode::Backspace => {
+            app.state.chat.input.pop();
+        }
+        KeyCode::Enter => app.send_chat(),
+        KeyCode::Tab | KeyCode::Esc => app.state.chat.focused = false,
+        _ => {}
+    }
+    false
+}
+
+/// Longest room code which can be typed, the codes given out by the server are shorter
+const MAXIMUM_ROOM_CODE_LENGTH: usize = 12;
+
+/// Keys go to the code of the private room being entered, Esc cancels it
+fn handle_room_code_input(app: &mut types::App, input: KeyCode) -> bool {
+    let Some(room_code) = app.state.room_code_input.as_mut() else {
+        return false;
+    };
+
+    match input {
+        KeyCode::Char(character)
+            if character.is_ascii_alphanumeric() && room_code.len() < MAXIMUM_ROOM_CODE_LENGTH =>
+        {
+            room_code.push(character.to_ascii_uppercase())
+        }
+        KeyCode::Backspace => {
+            room_code.pop();
+        }
+        KeyCode::Enter => app.enter_private_room(),
+        KeyCode::Esc => app.state.room_code_input = None,
+        _ => {}
+    }
+    false
+}
+
 pub fn handle_input(app: Arc<Mutex<types::App>>, input: KeyCode) -> bool {
     // Handling of input is dependent on the current tab the user is in.
+    let mut unlocked_app = app.lock().unwrap();
+    if unlocked_app.state.chat.focused {
+        return handle_chat_input(&mut unlocked_app, input);
+    }
+    if unlocked_app.state.room_code_input.is_some() {
+        return handle_room_code_input(&mut unlocked_app, input);
+    }
 
     match input {
         // On pressing the `Esc` key, the app should quit no matter what tab he is in
         // This logic can be handled for all tabs at a single place
         KeyCode::Esc => true,
+        // The arena moves the focus through its lists before reaching the chat
+        KeyCode::Tab if unlocked_app.current_tab != types::Tab::Arena => {
+            unlocked_app.focus_chat();
+            false
+        }
         _ => {
             // How other keys behave will be dependent on the current tab
-            let mut unlocked_app = app.lock().unwrap();
             unlocked_app
                 .current_tab
                 .handle_tab_specific_input(&mut unlocked_app, input)
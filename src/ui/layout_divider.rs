@@ -8,14 +8,15 @@ pub fn split_half_horizontally(area: Rect) -> Vec<Rect> {
         .split(area)
 }
 
-pub fn divide_frame(main_frame_size: Rect) -> types::Layouts {
+/// Divide the frame, leaving space for `progress_bar_count` progress bars stacked vertically
+pub fn divide_frame(main_frame_size: Rect, progress_bar_count: usize) -> types::Layouts {
     let main_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [
                 Constraint::Percentage(20), // Used here as a top margin
                 Constraint::Min(1),
-                Constraint::Length(6),
+                Constraint::Length(3 * progress_bar_count as u16),
                 Constraint::Length(3),
             ]
             .as_ref(),
@@ -33,12 +34,13 @@ pub fn divide_frame(main_frame_size: Rect) -> types::Layouts {
 
     let progress_bars = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .constraints(vec![Constraint::Length(3); progress_bar_count])
         .split(main_chunks[2]);
 
     types::Layouts {
         playground: middle_chunks[1],
         progress_bars,
         bottom_bar: main_chunks[3],
+        chat: middle_chunks[2],
     }
 }
@@ -0,0 +1,337 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time,
+};
+
+use crate::{
+    models::{self, GameStatus},
+    ui::{types::UiGameData, utils},
+};
+
+/// Milliseconds by which the playback is moved when seeking
+const SEEK_STEP: u64 = 5000;
+
+/// A key pressed by the player, stored as `[elapsed, position, character]` to keep the file small
+/// The character is `null` for a backspace, which erased the character at `position`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+pub struct ReplayKeystroke(pub u64, pub usize, pub Option<char>);
+
+/// Progress of an opponent as received from the server, `elapsed` milliseconds into the race
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RacerSnapshot {
+    pub elapsed: u64,
+    pub progress: u16,
+    pub cursor: usize,
+    pub stats: models::TypingStats,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ReplayOpponent {
+    pub display_name: String,
+    pub snapshots: Vec<RacerSnapshot>,
+}
+
+/// Everything needed to play a race back: the typing of the player, keystroke by keystroke,
+/// and the progress of the opponents as it was known to the player
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Replay {
+    pub prompt_text: String,
+    pub prompt_attribution: Option<String>,
+    pub player_name: String,
+    /// Unix timestamp at which the race was finished
+    pub played_at: u64,
+    pub keystrokes: Vec<ReplayKeystroke>,
+    pub opponents: Vec<ReplayOpponent>,
+}
+
+impl Replay {
+    /// Milliseconds from the start of the race till the last recorded event
+    pub fn duration(&self) -> u64 {
+        let last_keystroke = self
+            .keystrokes
+            .last()
+            .map(|keystroke| keystroke.0)
+            .unwrap_or_default();
+        let last_snapshot = self
+            .opponents
+            .iter()
+            .filter_map(|opponent| opponent.snapshots.last())
+            .map(|snapshot| snapshot.elapsed)
+            .max()
+            .unwrap_or_default();
+
+        last_keystroke.max(last_snapshot)
+    }
+
+    /// The typing of the player as it was `position` milliseconds into the race
+    pub fn typing_at(&self, position: u64) -> UiGameData {
+        let mut game_data = UiGameData::replay(self);
+
+        for &ReplayKeystroke(elapsed, prompt_position, character) in self
+            .keystrokes
+            .iter()
+            .take_while(|keystroke| keystroke.0 <= position)
+        {
+            match character {
+                Some(character) => {
+                    let is_correct = game_data.type_character(prompt_position, character);
+                    game_data.record_keystroke(prompt_position, is_correct, elapsed);
+                }
+                None => game_data.erase_character(prompt_position),
+            }
+        }
+
+        game_data.finished_in = Some(position);
+        game_data.my_progress = utils::calculate_progress(
+            game_data.correct_prefix_length(),
+            game_data.prompt_text.len(),
+        );
+        game_data
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read replay {}: {error}", path.display()))?;
+        let replay: Replay = serde_json::from_str(&contents)
+            .map_err(|error| format!("Invalid replay {}: {error}", path.display()))?;
+
+        if replay.prompt_text.is_empty() {
+            return Err(format!(
+                "Invalid replay {}: the prompt is empty",
+                path.display()
+            ));
+        }
+        Ok(replay)
+    }
+
+    /// Write the replay to a new file in the directory, returns the path of the file
+    pub fn save(&self, directory: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(directory)?;
+
+        let path = directory.join(format!("replay-{}.json", self.played_at));
+        let contents = serde_json::to_string(self)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+}
+
+/// Collects the events of a game while it is played, to build a `Replay` once it is over
+#[derive(Default)]
+pub struct ReplayRecorder {
+    keystrokes: Vec<ReplayKeystroke>,
+    opponents: Vec<(String, ReplayOpponent)>,
+}
+
+impl ReplayRecorder {
+    pub fn record_keystroke(&mut self, elapsed: u64, position: usize, character: char) {
+        self.keystrokes
+            .push(ReplayKeystroke(elapsed, position, Some(character)));
+    }
+
+    pub fn record_backspace(&mut self, elapsed: u64, erased_position: usize) {
+        self.keystrokes
+            .push(ReplayKeystroke(elapsed, erased_position, None));
+    }
+
+    /// The keystrokes which typed the text that is currently in the prompt, in order
+    pub fn current_keystrokes(&self) -> Vec<models::Keystroke> {
+        let mut typed: Vec<models::Keystroke> = vec![];
+        for &ReplayKeystroke(elapsed, position, character) in self.keystrokes.iter() {
+            typed.truncate(position);
+            if let Some(character) = character {
+                typed.push(models::Keystroke {
+                    position,
+                    character,
+                    elapsed,
+                });
+            }
+        }
+        typed
+    }
+
+    /// Record the progress of every racer except the player
+    pub fn record_racers(
+        &mut self,
+        elapsed: u64,
+        racers: &[models::RacerProgress],
+        player_id: Option<&str>,
+    ) {
+        for racer in racers
+            .iter()
+            .filter(|racer| Some(racer.user_id.as_str()) != player_id)
+        {
+            let snapshot = RacerSnapshot {
+                elapsed,
+                progress: racer.progress,
+                cursor: racer.cursor,
+                stats: racer.stats,
+            };
+
+            match self
+                .opponents
+                .iter_mut()
+                .find(|(user_id, _)| *user_id == racer.user_id)
+            {
+                Some((_, opponent)) => opponent.snapshots.push(snapshot),
+                None => self.opponents.push((
+                    racer.user_id.to_owned(),
+                    ReplayOpponent {
+                        display_name: racer.display_name.to_owned(),
+                        snapshots: vec![snapshot],
+                    },
+                )),
+            }
+        }
+    }
+
+    pub fn to_replay(&self, game_data: &UiGameData, player_name: &str, played_at: u64) -> Replay {
+        Replay {
+            prompt_text: game_data
+                .prompt_text
+                .iter()
+                .map(|prompt_key| prompt_key.character)
+                .collect(),
+            prompt_attribution: game_data.prompt_attribution.clone(),
+            player_name: player_name.to_owned(),
+            played_at,
+            keystrokes: self.keystrokes.clone(),
+            opponents: self
+                .opponents
+                .iter()
+                .map(|(_, opponent)| opponent.clone())
+                .collect(),
+        }
+    }
+}
+
+/// State of a replay being played back
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    /// Milliseconds into the race which are being shown
+    pub position: u64,
+    /// 1 for the real speed, 2 for double
+    pub speed: u64,
+    pub paused: bool,
+    /// Time at which the position was last moved forward
+    last_advanced_at: time::Instant,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            position: 0,
+            speed: 1,
+            paused: false,
+            last_advanced_at: time::Instant::now(),
+        }
+    }
+
+    /// Move the position forward by the time passed since the last call
+    /// The playback is paused at the end of the race
+    pub fn advance(&mut self) {
+        let elapsed = self.last_advanced_at.elapsed().as_millis() as u64;
+        self.last_advanced_at = time::Instant::now();
+
+        if !self.paused {
+            self.position = (self.position + elapsed * self.speed).min(self.replay.duration());
+            if self.position == self.replay.duration() {
+                self.paused = true;
+            }
+        }
+    }
+
+    /// Pause or resume, the replay starts over if it was paused at the end
+    pub fn toggle_pause(&mut self) {
+        if self.paused && self.position == self.replay.duration() {
+            self.position = 0;
+        }
+        self.paused = !self.paused;
+    }
+
+    pub fn seek_forward(&mut self) {
+        self.position = (self.position + SEEK_STEP).min(self.replay.duration());
+    }
+
+    pub fn seek_backward(&mut self) {
+        self.position = self.position.saturating_sub(SEEK_STEP);
+    }
+
+    /// A game showing the race as it was at the current position
+    /// The game is built again from the start, so that seeking backwards works the same way
+    pub fn game_at_position(&self) -> UiGameData {
+        let mut game_data = self.replay.typing_at(self.position);
+
+        game_data.status = if self.position == 0 {
+            GameStatus::Init
+        } else {
+            GameStatus::InProgress
+        };
+
+        // The player is the first racer, followed by the opponents in the order of the replay
+        let player_cursor = game_data.correct_prefix_length();
+        let player_progress = game_data.my_progress;
+        if let Some(player) = game_data.racers.first_mut() {
+            player.progress = player_progress;
+            player.cursor = player_cursor;
+        }
+        for (racer, opponent) in game_data
+            .racers
+            .iter_mut()
+            .skip(1)
+            .zip(self.replay.opponents.iter())
+        {
+            if let Some(snapshot) = opponent
+                .snapshots
+                .iter()
+                .take_while(|snapshot| snapshot.elapsed <= self.position)
+                .last()
+            {
+                racer.progress = snapshot.progress;
+                racer.cursor = snapshot.cursor;
+                racer.stats = snapshot.stats;
+            }
+        }
+
+        game_data
+    }
+
+    /// Shown above the prompt, along with the keys which control the playback
+    pub fn status_line(&self) -> String {
+        let state = if self.paused { "Paused" } else { "Playing" };
+        format!(
+            "Replay of {} | {state} {}x | {:.1}s of {:.1}s | Pause [Space] | Speed [1/2] | Seek [←/→]",
+            self.replay.player_name,
+            self.speed,
+            self.position as f32 / 1000.0,
+            self.replay.duration() as f32 / 1000.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_keystrokes_skip_erased_characters() {
+        let mut recorder = ReplayRecorder::default();
+        recorder.record_keystroke(100, 0, 'c');
+        recorder.record_keystroke(200, 1, 'o');
+        recorder.record_backspace(300, 1);
+        recorder.record_keystroke(400, 1, 'a');
+        recorder.record_keystroke(500, 2, 't');
+
+        let keystrokes = recorder
+            .current_keystrokes()
+            .into_iter()
+            .map(|keystroke| (keystroke.position, keystroke.character, keystroke.elapsed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keystrokes,
+            vec![(0, 'c', 100), (1, 'a', 400), (2, 't', 500)]
+        );
+    }
+}
@@ -1,4 +1,10 @@
-use std::{collections::VecDeque, time};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::Write,
+    path::PathBuf,
+    time::{self, SystemTime, UNIX_EPOCH},
+};
 
 use crossterm::event::KeyCode;
 use tui::{
@@ -9,14 +15,81 @@ use tui::{
 pub use models::UserStatus;
 
 use crate::{
+    errors::{Error, TransportError},
     models::{self, GameStatus},
-    ui::stateful_list::StatefulList,
+    server_utils::prompt_corpus::{PromptCorpus, PromptFilter},
+    ui::{
+        config::SavedAccount,
+        ghost::{Ghost, GhostStore, GHOST_ID},
+        replay::{Replay, ReplayPlayback, ReplayRecorder},
+        stateful_list::StatefulList,
+        utils,
+    },
 };
 
+/// Seconds to wait before a practice game starts
+const PRACTICE_COUNTDOWN: u8 = 3;
+
+/// Seconds of typing over which each point of the WPM sparkline is measured
+const WPM_WINDOW_SECONDS: u64 = 5;
+
+/// State of the connection to the server, shown in the bottom bar
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum ConnectionState {
+    /// The server is not contacted, as in the practice mode
+    #[default]
+    Offline,
+    Connecting,
+    Connected,
+    /// The connection was lost, the next attempt to connect is made at `retry_at`
+    Reconnecting {
+        attempt: u32,
+        retry_at: time::Instant,
+    },
+}
+
+impl ConnectionState {
+    pub fn label(&self) -> String {
+        match self {
+            ConnectionState::Offline => "Offline".to_string(),
+            ConnectionState::Connecting => "Connecting".to_string(),
+            ConnectionState::Connected => "Connected".to_string(),
+            ConnectionState::Reconnecting { attempt, retry_at } => format!(
+                "Reconnecting in {}s, attempt {attempt}",
+                retry_at
+                    .saturating_duration_since(time::Instant::now())
+                    .as_secs()
+                    + 1
+            ),
+        }
+    }
+
+    pub fn get_color(&self) -> Color {
+        match self {
+            ConnectionState::Offline => Color::Gray,
+            ConnectionState::Connecting => Color::Yellow,
+            ConnectionState::Connected => Color::Green,
+            ConnectionState::Reconnecting { .. } => Color::Red,
+        }
+    }
+}
+
+/// Whether the game is played against other users or alone without the server
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameMode {
+    Online,
+    Practice,
+    /// The user is watching the game of other users, nothing can be typed
+    Spectator,
+    /// A recorded game is played back, without the server
+    Replay,
+}
+
 pub struct Layouts {
     pub playground: Rect,
     pub progress_bars: Vec<Rect>,
     pub bottom_bar: Rect,
+    pub chat: Rect,
 }
 
 #[derive(Clone, Copy)]
@@ -55,32 +128,80 @@ impl CharState {
     }
 }
 
+/// Pass the message to the websocket handler, which sends it to the server
+/// Fails only if the handler has stopped
+pub fn send_ui_message(
+    sender: &tokio::sync::mpsc::Sender<UiMessage>,
+    message: UiMessage,
+) -> Result<(), Error> {
+    sender.blocking_send(message).map_err(|_| {
+        TransportError::SendFailed("the connection handler has stopped".to_string()).into()
+    })
+}
+
 // For the client, each user is a player
 pub type Player = models::User;
 
 impl Player {
     // Send a challenge message to the player
-    pub fn challenge(&self, sender: tokio::sync::mpsc::Sender<UiMessage>) {
+    pub fn challenge(&self, sender: tokio::sync::mpsc::Sender<UiMessage>) -> Result<(), Error> {
         let message = UiMessage::Challenge {
             user_name: self.display_name.clone(),
             user_id: self.id.clone(),
         };
-        sender.blocking_send(message).unwrap();
+        send_ui_message(&sender, message)
+    }
+
+    // Invite the player to the race lobby owned by the current user
+    pub fn invite(
+        &self,
+        race_id: &str,
+        sender: tokio::sync::mpsc::Sender<UiMessage>,
+    ) -> Result<(), Error> {
+        let message = UiMessage::InviteToRace {
+            race_id: race_id.to_owned(),
+            user_name: self.display_name.clone(),
+            user_id: self.id.clone(),
+        };
+        send_ui_message(&sender, message)
     }
 }
 
 pub struct UiGameData {
     game_id: String,
+    pub mode: GameMode,
     pub status: GameStatus,
     pub my_progress: u16,
-    pub opponent_progress: u16,
+    /// Progress of all the users in the game, as known to the server
+    pub racers: Vec<models::RacerProgress>,
     pub prompt_text: Vec<PromptKey>,
+    /// Author and source of the prompt, displayed below the prompt
+    pub prompt_attribution: Option<String>,
     pub starts_at: u64,
+    /// Time at which the `GameStart` message was received
+    pub started_at: Option<time::Instant>,
+    /// Milliseconds taken to type the prompt, set when a practice game is finished
+    pub finished_in: Option<u64>,
+    /// Number of characters typed, including the wrong ones
+    pub keystrokes: u32,
+    pub correct_keystrokes: u32,
+    /// Milliseconds since the start of the game at which each character was typed
+    pub keystroke_times: Vec<u64>,
+    /// Events of the game, from which a replay can be saved once it is over
+    pub recorder: ReplayRecorder,
+    /// Personal best raced against in a practice game, it is the second racer
+    pub ghost: Option<Ghost>,
 }
 
 impl UiGameData {
     /// Create a new game data with the status as Init
-    pub fn new(game_id: String, prompt_text: String, starts_at: u64) -> Self {
+    pub fn new(
+        game_id: String,
+        prompt_text: String,
+        prompt_attribution: Option<String>,
+        starts_at: u64,
+        participants: Vec<Player>,
+    ) -> Self {
         let mut transformed_quote_str = prompt_text.chars().map(PromptKey::new).collect::<Vec<_>>();
 
         // Make the first Prompt key underlined to make it appear as cursor
@@ -88,26 +209,396 @@ impl UiGameData {
 
         Self {
             game_id,
+            mode: GameMode::Online,
             my_progress: 0,
-            opponent_progress: 0,
+            racers: participants
+                .into_iter()
+                .map(|participant| models::RacerProgress {
+                    user_id: participant.id,
+                    display_name: participant.display_name,
+                    progress: 0,
+                    cursor: 0,
+                    stats: models::TypingStats::default(),
+                    disconnected: false,
+                })
+                .collect(),
             prompt_text: transformed_quote_str,
+            prompt_attribution,
             starts_at,
             status: GameStatus::Init,
+            started_at: None,
+            finished_in: None,
+            keystrokes: 0,
+            correct_keystrokes: 0,
+            keystroke_times: vec![],
+            recorder: ReplayRecorder::default(),
+            ghost: None,
+        }
+    }
+
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    /// Create a game which is played without the server, alone or against the ghost
+    pub fn practice(
+        prompt_text: String,
+        prompt_attribution: Option<String>,
+        starts_at: u64,
+        player: Player,
+        ghost: Option<Ghost>,
+    ) -> Self {
+        let mut game_data = Self {
+            mode: GameMode::Practice,
+            ..Self::new(
+                "practice".to_string(),
+                prompt_text,
+                prompt_attribution,
+                starts_at,
+                vec![player],
+            )
+        };
+        if let Some(ghost) = ghost {
+            game_data.racers.push(ghost.racer_at(0));
+            game_data.ghost = Some(ghost);
+        }
+        game_data
+    }
+
+    /// Move the ghost to where it was at this point of the recorded run
+    pub fn update_ghost(&mut self) {
+        let elapsed = self.elapsed_millis();
+        let Some(ghost) = self.ghost.as_ref() else {
+            return;
+        };
+
+        let ghost_progress = ghost.racer_at(elapsed);
+        if let Some(racer) = self
+            .racers
+            .iter_mut()
+            .find(|racer| racer.user_id == GHOST_ID)
+        {
+            *racer = ghost_progress;
+        }
+    }
+
+    /// Position of the cursor of the ghost in the prompt, if the game has one
+    pub fn ghost_cursor(&self) -> Option<usize> {
+        self.ghost.as_ref()?;
+        self.racers
+            .iter()
+            .find(|racer| racer.user_id == GHOST_ID)
+            .map(|racer| racer.cursor)
+    }
+
+    /// Watch a game of other users, which may have started already
+    pub fn spectate(
+        game_id: String,
+        prompt_text: String,
+        prompt_attribution: Option<String>,
+        starts_at: u64,
+        racers: Vec<models::RacerProgress>,
+    ) -> Self {
+        let current_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let mut game_data = Self {
+            mode: GameMode::Spectator,
+            racers,
+            ..Self::new(game_id, prompt_text, prompt_attribution, starts_at, vec![])
+        };
+        if current_time >= starts_at {
+            game_data.status = GameStatus::InProgress;
+            game_data.started_at = time::Instant::now()
+                .checked_sub(time::Duration::from_secs(current_time - starts_at));
         }
+        game_data
     }
 
+    /// The game shown while a replay is played back, before any keystroke is applied
+    /// The player is the first racer, followed by the opponents
+    pub fn replay(replay: &Replay) -> Self {
+        let player = models::RacerProgress {
+            user_id: "replay-player".to_string(),
+            display_name: replay.player_name.to_owned(),
+            progress: 0,
+            cursor: 0,
+            stats: models::TypingStats::default(),
+            disconnected: false,
+        };
+        let opponents = replay
+            .opponents
+            .iter()
+            .enumerate()
+            .map(|(index, opponent)| models::RacerProgress {
+                user_id: format!("replay-opponent-{index}"),
+                display_name: opponent.display_name.to_owned(),
+                ..player.clone()
+            });
+        let racers = std::iter::once(player.clone()).chain(opponents).collect();
+
+        Self {
+            mode: GameMode::Replay,
+            racers,
+            ..Self::new(
+                "replay".to_string(),
+                replay.prompt_text.to_owned(),
+                replay.prompt_attribution.to_owned(),
+                0,
+                vec![],
+            )
+        }
+    }
+
+    /// Mark the character typed at `position` and move the cursor to the next character
+    /// Returns true if the expected character was typed
+    pub fn type_character(&mut self, position: usize, character: char) -> bool {
+        let Some(prompt_key) = self.prompt_text.get_mut(position) else {
+            return false;
+        };
+
+        let is_correct = prompt_key.character == character;
+        prompt_key.state = if is_correct {
+            CharState::Touched(TouchState::Valid)
+        } else {
+            CharState::Touched(TouchState::Invalid)
+        };
+
+        if let Some(next_prompt_key) = self.prompt_text.get_mut(position + 1) {
+            next_prompt_key.state = CharState::CursorPosition;
+        }
+        is_correct
+    }
+
+    /// Mark the first `cursor` characters as typed correctly, for a player who rejoins a running
+    /// race from a new client. Returns the position at which the player resumes typing
+    pub fn resume_at(&mut self, cursor: usize) -> usize {
+        let cursor = cursor.min(self.prompt_text.len().saturating_sub(1));
+        for prompt_key in self.prompt_text.iter_mut().take(cursor) {
+            prompt_key.state = CharState::Touched(TouchState::Valid);
+        }
+        if let Some(prompt_key) = self.prompt_text.get_mut(cursor) {
+            prompt_key.state = CharState::CursorPosition;
+        }
+
+        self.my_progress = utils::calculate_progress(cursor, self.prompt_text.len());
+        cursor
+    }
+
+    /// Erase the character at `position`, which becomes the cursor again
+    pub fn erase_character(&mut self, position: usize) {
+        if let Some(next_prompt_key) = self.prompt_text.get_mut(position + 1) {
+            next_prompt_key.state = CharState::Untouched;
+        }
+        if let Some(prompt_key) = self.prompt_text.get_mut(position) {
+            prompt_key.state = CharState::CursorPosition;
+        }
+    }
+
+    /// Record a key press at `position`, `is_correct` is whether the expected character was typed
+    pub fn record_keystroke(&mut self, position: usize, is_correct: bool, elapsed: u64) {
+        if let Some(prompt_key) = self.prompt_text.get_mut(position) {
+            if is_correct {
+                prompt_key.typed_at = Some(elapsed);
+            } else {
+                prompt_key.mistakes += 1;
+            }
+        }
+
+        self.keystroke_times.push(elapsed);
+        self.keystrokes += 1;
+        if is_correct {
+            self.correct_keystrokes += 1;
+        }
+    }
+
+    /// Percentage of the key presses which were correct
+    pub fn accuracy(&self) -> f32 {
+        if self.keystrokes == 0 {
+            100.0
+        } else {
+            self.correct_keystrokes as f32 * 100.0 / self.keystrokes as f32
+        }
+    }
+
+    /// Milliseconds elapsed since the game was started, till it was finished
+    pub fn elapsed_millis(&self) -> u64 {
+        self.finished_in.unwrap_or_else(|| {
+            self.started_at
+                .map(|started_at| started_at.elapsed().as_millis() as u64)
+                .unwrap_or_default()
+        })
+    }
+
+    /// Number of characters in the prompt which are typed wrong and not yet corrected
+    pub fn uncorrected_errors(&self) -> u32 {
+        self.prompt_text
+            .iter()
+            .filter(|prompt_key| {
+                matches!(prompt_key.state, CharState::Touched(TouchState::Invalid))
+            })
+            .count() as u32
+    }
+
+    /// Words per minute of all the characters typed, a word is taken as five characters
+    pub fn gross_wpm(&self) -> f32 {
+        let minutes = self.elapsed_millis() as f32 / 60_000.0;
+        if minutes == 0.0 {
+            0.0
+        } else {
+            self.keystrokes as f32 / 5.0 / minutes
+        }
+    }
+
+    /// Gross WPM after a penalty of one word for every uncorrected error
+    pub fn net_wpm(&self) -> f32 {
+        let minutes = self.elapsed_millis() as f32 / 60_000.0;
+        if minutes == 0.0 {
+            0.0
+        } else {
+            (self.gross_wpm() - self.uncorrected_errors() as f32 / minutes).max(0.0)
+        }
+    }
+
+    pub fn stats(&self) -> models::TypingStats {
+        models::TypingStats {
+            gross_wpm: self.gross_wpm(),
+            net_wpm: self.net_wpm(),
+            accuracy: self.accuracy(),
+            uncorrected_errors: self.uncorrected_errors(),
+        }
+    }
+
+    /// WPM at every second of the game, measured over the last `WPM_WINDOW_SECONDS`
+    /// Used to draw the sparkline of the typing speed
+    pub fn wpm_history(&self) -> Vec<u64> {
+        let elapsed_seconds = self.elapsed_millis() / 1000;
+
+        (1..=elapsed_seconds)
+            .map(|second| {
+                let window_start = second.saturating_sub(WPM_WINDOW_SECONDS) * 1000;
+                let window_end = second * 1000;
+                let window_keystrokes = self
+                    .keystroke_times
+                    .iter()
+                    .filter(|&&elapsed| elapsed > window_start && elapsed <= window_end)
+                    .count();
+
+                let window_minutes = (window_end - window_start) as f32 / 60_000.0;
+                (window_keystrokes as f32 / 5.0 / window_minutes) as u64
+            })
+            .collect()
+    }
+
+    /// Milliseconds taken to type the prompt, or the time played if it was not completed
+    pub fn time_taken(&self) -> u64 {
+        if self.is_prompt_completed() {
+            self.prompt_text
+                .iter()
+                .filter_map(|prompt_key| prompt_key.typed_at)
+                .max()
+                .unwrap_or_default()
+        } else {
+            self.elapsed_millis()
+        }
+    }
+
+    /// Words which took the most time per character, along with the milliseconds taken
+    /// The time of a word starts when the character before it was typed
+    pub fn slowest_words(&self, count: usize) -> Vec<(String, u64)> {
+        let mut word_timings = vec![];
+        let mut word_start = 0;
+
+        while word_start < self.prompt_text.len() {
+            let word_end = self.prompt_text[word_start..]
+                .iter()
+                .position(|prompt_key| prompt_key.character == ' ')
+                .map(|offset| word_start + offset)
+                .unwrap_or(self.prompt_text.len());
+
+            let started_at = match word_start {
+                0 => Some(0),
+                _ => self.prompt_text[word_start - 1].typed_at,
+            };
+            let finished_at = self.prompt_text[word_end - 1].typed_at;
+
+            if let (Some(started_at), Some(finished_at)) = (started_at, finished_at) {
+                let word = self.prompt_text[word_start..word_end]
+                    .iter()
+                    .map(|prompt_key| prompt_key.character)
+                    .collect::<String>();
+                word_timings.push((word, finished_at.saturating_sub(started_at)));
+            }
+
+            // Skip the space after the word
+            word_start = word_end + 1;
+        }
+
+        word_timings.sort_by(|(first_word, first_time), (second_word, second_time)| {
+            let first_speed = *first_time as f32 / first_word.chars().count() as f32;
+            let second_speed = *second_time as f32 / second_word.chars().count() as f32;
+            second_speed.total_cmp(&first_speed)
+        });
+        word_timings.truncate(count);
+        word_timings
+    }
+
+    /// Characters of the prompt which were typed wrong the most, along with the number of mistakes
+    pub fn mistyped_characters(&self, count: usize) -> Vec<(char, u32)> {
+        let mut mistakes = Vec::<(char, u32)>::new();
+
+        for prompt_key in self.prompt_text.iter().filter(|key| key.mistakes > 0) {
+            match mistakes
+                .iter_mut()
+                .find(|(character, _)| *character == prompt_key.character)
+            {
+                Some((_, character_mistakes)) => *character_mistakes += prompt_key.mistakes,
+                None => mistakes.push((prompt_key.character, prompt_key.mistakes)),
+            }
+        }
+
+        mistakes.sort_by(|(_, first), (_, second)| second.cmp(first));
+        mistakes.truncate(count);
+        mistakes
+    }
+
+    /// Returns true if every character of the prompt is typed correctly
+    pub fn is_prompt_completed(&self) -> bool {
+        self.correct_prefix_length() == self.prompt_text.len()
+    }
+
+    /// Number of characters from the start of the prompt which are typed correctly
+    pub fn correct_prefix_length(&self) -> usize {
+        self.prompt_text
+            .iter()
+            .take_while(|prompt_key| {
+                matches!(prompt_key.state, CharState::Touched(TouchState::Valid))
+            })
+            .count()
+    }
+
+    /// Update the progress and send the keystroke to the server, which validates it
+    /// Practice games are not known to the server, so nothing is sent
     pub fn update_current_progress(
         &mut self,
         progress: u16,
+        keystroke: models::Keystroke,
         sender: &tokio::sync::mpsc::Sender<UiMessage>,
-    ) {
+    ) -> Result<(), Error> {
         self.my_progress = progress;
+        if self.mode == GameMode::Practice {
+            return Ok(());
+        }
+
         let message = UiMessage::UpdateProgress {
             game_id: self.game_id.to_owned(),
-            progress,
+            keystrokes: vec![keystroke],
+            stats: self.stats(),
         };
 
-        sender.blocking_send(message).unwrap();
+        send_ui_message(sender, message)
     }
 }
 
@@ -127,6 +618,40 @@ pub struct State {
 
     /// Details of the game
     pub game: Option<UiGameData>,
+
+    /// The race lobby which the user has joined
+    pub race_lobby: Option<models::RaceLobby>,
+
+    /// The latest invitation to join a race lobby
+    pub race_invitation: Option<RaceInvitationData>,
+
+    /// Summary of the game which has just ended
+    pub results: Option<UiResultsData>,
+
+    /// Recent races of the user, the most recent first
+    pub history: StatefulList<models::RaceSummary>,
+
+    /// Statistics of the user, shown in the profile
+    pub stats: Option<models::PlayerStats>,
+
+    /// Whether the user is waiting for a ranked opponent
+    pub in_ranked_queue: bool,
+
+    /// Games being played by other users, which can be spectated
+    pub live_games: StatefulList<models::LiveGame>,
+
+    /// The list of the arena in which the selection is moved
+    pub arena_focus: ArenaFocus,
+
+    pub leaderboard: UiLeaderboardData,
+
+    /// The replay being played back, its game is shown in the `Game` tab
+    pub replay: Option<ReplayPlayback>,
+
+    pub chat: UiChatData,
+
+    /// Code of the private room being typed, the keys go to it while it is being entered
+    pub room_code_input: Option<String>,
 }
 
 impl Default for State {
@@ -134,13 +659,109 @@ impl Default for State {
         Self {
             cursor_position: 0,
             players: StatefulList::with_items(vec![]),
-            menu: StatefulList::with_items(vec!["Game", "Practice"]),
+            menu: StatefulList::with_items(vec![
+                "Game",
+                "Ranked",
+                "Private room",
+                "Practice",
+                "Ghost race",
+                "Profile",
+                "Leaderboard",
+            ]),
             challenge: None,
             game: None,
+            race_lobby: None,
+            race_invitation: None,
+            results: None,
+            history: StatefulList::with_items(vec![]),
+            stats: None,
+            in_ranked_queue: false,
+            live_games: StatefulList::with_items(vec![]),
+            arena_focus: ArenaFocus::default(),
+            leaderboard: UiLeaderboardData::default(),
+            replay: None,
+            chat: UiChatData::default(),
+            room_code_input: None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum ArenaFocus {
+    /// Players who can be challenged or invited
+    #[default]
+    Players,
+    /// Games which can be spectated
+    LiveGames,
+}
+
+/// Most chat messages kept, the older ones are dropped
+const MAXIMUM_CHAT_LINES: usize = 100;
+
+/// A chat message received from the server
+pub struct ChatLine {
+    pub display_name: String,
+    pub text: String,
+    /// Whether the message was sent to the racers of the game, rather than to the lobby
+    pub in_race: bool,
+    /// Whether the message was sent by the current user
+    pub is_own: bool,
+}
+
+/// Chat of the lobby and of the race, the keys go to the message being written while it is focused
+#[derive(Default)]
+pub struct UiChatData {
+    /// The received messages, the oldest first
+    pub messages: VecDeque<ChatLine>,
+    /// The message being written
+    pub input: String,
+    pub focused: bool,
+}
+
+impl UiChatData {
+    pub fn push(&mut self, chat_line: ChatLine) {
+        if self.messages.len() == MAXIMUM_CHAT_LINES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(chat_line);
+    }
+}
+
+/// The leaderboard being viewed, the entries are fetched again whenever it is changed
+pub struct UiLeaderboardData {
+    pub category: models::LeaderboardCategory,
+    pub period: models::LeaderboardPeriod,
+    pub entries: StatefulList<models::LeaderboardEntry>,
+}
+
+impl Default for UiLeaderboardData {
+    fn default() -> Self {
+        Self {
+            category: models::LeaderboardCategory::BestWpm,
+            period: models::LeaderboardPeriod::Weekly,
+            entries: StatefulList::with_items(vec![]),
         }
     }
 }
 
+impl UiLeaderboardData {
+    pub fn next_category(&mut self) {
+        self.category = match self.category {
+            models::LeaderboardCategory::BestWpm => models::LeaderboardCategory::Rating,
+            models::LeaderboardCategory::Rating => models::LeaderboardCategory::MostRaces,
+            models::LeaderboardCategory::MostRaces => models::LeaderboardCategory::BestWpm,
+        };
+    }
+
+    pub fn next_period(&mut self) {
+        self.period = match self.period {
+            models::LeaderboardPeriod::Daily => models::LeaderboardPeriod::Weekly,
+            models::LeaderboardPeriod::Weekly => models::LeaderboardPeriod::AllTime,
+            models::LeaderboardPeriod::AllTime => models::LeaderboardPeriod::Daily,
+        };
+    }
+}
+
 #[derive(Default, Clone, Copy, PartialEq)]
 pub enum Tab {
     // This is where the player can play
@@ -151,6 +772,12 @@ pub enum Tab {
     // This is the default Tab when user initializes the app
     #[default]
     Menu,
+    // Summary of the game which has just ended
+    Results,
+    // Recent races and statistics of the user
+    Profile,
+    // The best players by WPM, rating or number of races
+    Leaderboard,
 }
 
 impl Tab {
@@ -159,6 +786,9 @@ impl Tab {
             Tab::Game => super::input_handler::handle_game_input(app, input),
             Tab::Arena => super::input_handler::handle_arena_input(app, input),
             Tab::Menu => super::input_handler::handle_menu_input(app, input),
+            Tab::Results => super::input_handler::handle_results_input(app, input),
+            Tab::Profile => super::input_handler::handle_profile_input(app, input),
+            Tab::Leaderboard => super::input_handler::handle_leaderboard_input(app, input),
         }
     }
 }
@@ -180,6 +810,15 @@ impl LogType {
             LogType::CountDown => Color::Yellow,
         }
     }
+
+    fn label(&self) -> &'static str {
+        match self {
+            LogType::Success => "SUCCESS",
+            LogType::Error => "ERROR",
+            LogType::Info => "INFO",
+            LogType::CountDown => "COUNTDOWN",
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -267,6 +906,17 @@ impl Event {
     }
 }
 
+/// The account of the player on the server, players without one are guests
+#[derive(Default)]
+pub struct AccountSettings {
+    /// File in which the account is saved after registering
+    pub file: Option<PathBuf>,
+    /// Account which is logged in to on every connection
+    pub saved: Option<SavedAccount>,
+    /// Nickname to be registered on the next connection
+    pub register: Option<String>,
+}
+
 /// App holds the state of the application
 pub struct App {
     // The currently active tab. Layout will be same for all the Tabs. Data displayed will be different
@@ -275,14 +925,32 @@ pub struct App {
     pub state: State,
     // User id of the connection
     pub current_user: Option<Player>,
+    pub connection: ConnectionState,
+    // Token issued by the server, to resume the session after the connection is lost
+    pub resume_token: Option<String>,
+    // Features supported by both the client and the server, negotiated on connecting
+    pub server_features: Vec<models::Feature>,
+    pub account: AccountSettings,
     // A queue of buffered events
     pub events: VecDeque<Event>,
     pub event_sender: tokio::sync::mpsc::Sender<UiMessage>,
+    // Prompts bundled with the binary, used for practice games
+    practice_prompts: PromptCorpus,
+    // File to which all the events are written, if `--log-file` is passed
+    log_file: Option<fs::File>,
+    // Directory in which the replays are saved from the results
+    replay_directory: Option<PathBuf>,
+    // Personal bests of the practice games, raced against as ghosts
+    ghosts: Option<GhostStore>,
 }
 
 pub struct PromptKey {
     pub character: char,
     pub state: CharState,
+    /// Number of times a wrong key was pressed at this position
+    pub mistakes: u32,
+    /// Milliseconds since the start of the game at which this character was typed correctly
+    pub typed_at: Option<u64>,
 }
 
 impl PromptKey {
@@ -290,6 +958,8 @@ impl PromptKey {
         Self {
             character,
             state: CharState::default(),
+            mistakes: 0,
+            typed_at: None,
         }
     }
 }
@@ -300,12 +970,396 @@ impl App {
             current_tab: Tab::default(),
             events: VecDeque::new(),
             current_user: None,
+            connection: ConnectionState::default(),
+            resume_token: None,
+            server_features: vec![],
+            account: AccountSettings::default(),
             state: State::default(),
             event_sender,
+            practice_prompts: PromptCorpus::bundled(),
+            log_file: None,
+            replay_directory: None,
+            ghosts: None,
+        }
+    }
+
+    pub fn set_log_file(&mut self, log_file: fs::File) {
+        self.log_file = Some(log_file);
+    }
+
+    pub fn set_account(&mut self, account: AccountSettings) {
+        self.account = account;
+    }
+
+    pub fn set_replay_directory(&mut self, replay_directory: Option<PathBuf>) {
+        self.replay_directory = replay_directory;
+    }
+
+    pub fn set_ghost_directory(&mut self, ghost_directory: Option<PathBuf>) {
+        self.ghosts = ghost_directory.map(GhostStore::new);
+    }
+
+    /// The first message sent on every connection, to register or log in to the account
+    /// It announces the version of the protocol and the features which the client supports
+    pub fn hello_message(&self) -> models::WSClientMessage {
+        let (nickname, token) = match self.account.register.as_ref() {
+            Some(nickname) => (Some(nickname.clone()), None),
+            None => (
+                None,
+                self.account
+                    .saved
+                    .as_ref()
+                    .map(|saved_account| saved_account.token.clone()),
+            ),
+        };
+
+        models::WSClientMessage::Hello {
+            nickname,
+            token,
+            protocol_version: models::PROTOCOL_VERSION,
+            features: models::SUPPORTED_FEATURES.to_vec(),
+        }
+    }
+
+    /// Returns true if the server supports the feature, servers which predate the handshake
+    /// are older than `MINIMUM_PROTOCOL_VERSION` and support none of the features
+    pub fn supports(&self, feature: models::Feature) -> bool {
+        self.server_features.contains(&feature)
+    }
+
+    /// Log an error and return false if the server does not support the feature
+    fn ensure_supported(&mut self, feature: models::Feature, name: &str) -> bool {
+        let is_supported = self.supports(feature);
+        if !is_supported {
+            let unsupported_error = Event::error(
+                &format!(
+                    "{name} is not supported by the server, it may be running an older version"
+                ),
+                2,
+                false,
+            );
+            self.add_log_event(unsupported_error);
+        }
+        is_supported
+    }
+
+    /// Remember the account to which the server has logged in the player
+    pub fn save_account(&mut self, nickname: &str, token: String) {
+        let is_saved = self
+            .account
+            .saved
+            .as_ref()
+            .is_some_and(|saved_account| saved_account.token == token);
+        if is_saved {
+            return;
+        }
+
+        let saved_account = SavedAccount {
+            nickname: nickname.to_owned(),
+            token,
+        };
+        let event = match self.account.file.as_deref() {
+            Some(account_file) => match saved_account.write(account_file) {
+                Ok(_) => Event::success(
+                    &format!("Registered {nickname}, it is yours in the next sessions too"),
+                    2,
+                    false,
+                ),
+                Err(error) => Event::error(
+                    &format!(
+                        "Could not save the account to {}: {error}",
+                        account_file.display()
+                    ),
+                    2,
+                    false,
+                ),
+            },
+            None => Event::success(&format!("Registered {nickname}"), 2, false),
+        };
+
+        self.account.register = None;
+        self.account.saved = Some(saved_account);
+        self.add_log_event(event);
+    }
+
+    /// Returns true if the server is reachable and has assigned a name to the user
+    pub fn is_connected(&self) -> bool {
+        self.connection == ConnectionState::Connected && self.current_user.is_some()
+    }
+
+    /// Add `duration` events to the queue, each lasting for one second, to simulate a countdown
+    pub fn add_countdown_events(&mut self, event: &str, action: &str, duration: u8) {
+        self.add_log_event(Event::countdown(event, action, duration, true));
+
+        (1..duration)
+            .rev()
+            .map(|duration| Event::countdown(event, action, duration, false))
+            .for_each(|event| self.add_log_event(event));
+    }
+
+    /// Start a game with a bundled prompt, which is played without the server
+    pub fn start_practice(&mut self) {
+        let prompt = self.practice_prompts.pick(&PromptFilter::default());
+        let prompt_attribution = utils::format_attribution(prompt.author, prompt.source);
+
+        self.start_practice_game(prompt.text, prompt_attribution, None);
+    }
+
+    /// Practice on a prompt finished before, against the ghost of the personal best on it
+    /// The prompt is picked at random if it is not given
+    pub fn race_ghost(&mut self, prompt_text: Option<&str>) {
+        let ghost = self.ghosts.as_ref().and_then(|ghosts| match prompt_text {
+            Some(prompt_text) => ghosts.load(prompt_text),
+            None => ghosts.pick(),
+        });
+
+        let Some(ghost) = ghost else {
+            self.add_log_event(Event::error(
+                "No personal best yet, finish a practice game to race against it",
+                2,
+                false,
+            ));
+            return;
+        };
+
+        self.add_log_event(Event::info(
+            &format!(
+                "Racing your personal best of {:.1} seconds",
+                ghost.finished_in() as f32 / 1000.0
+            ),
+            2,
+            false,
+        ));
+        self.start_practice_game(
+            ghost.replay.prompt_text.to_owned(),
+            ghost.replay.prompt_attribution.to_owned(),
+            Some(ghost),
+        );
+    }
+
+    fn start_practice_game(
+        &mut self,
+        prompt_text: String,
+        prompt_attribution: Option<String>,
+        ghost: Option<Ghost>,
+    ) {
+        let current_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let player = self.current_user.clone().unwrap_or_else(|| Player {
+            id: "practice".to_string(),
+            status: UserStatus::Busy,
+            display_name: "You".to_string(),
+            rating: None,
+            bot: false,
+        });
+
+        self.state.game = Some(UiGameData::practice(
+            prompt_text,
+            prompt_attribution,
+            current_time + u64::from(PRACTICE_COUNTDOWN),
+            player,
+            ghost,
+        ));
+        self.state.cursor_position = 0;
+        self.current_tab = Tab::Game;
+
+        self.add_countdown_events("practice", "start", PRACTICE_COUNTDOWN);
+    }
+
+    /// Play the replay back in the `Game` tab, the server is not contacted
+    pub fn start_replay(&mut self, replay: Replay) {
+        let playback = ReplayPlayback::new(replay);
+        self.state.game = Some(playback.game_at_position());
+        self.state.replay = Some(playback);
+        self.current_tab = Tab::Game;
+
+        self.add_log_event(Event::info(
+            "Playing the replay, press Esc to quit",
+            3,
+            true,
+        ));
+    }
+
+    /// Move the replay forward by the time passed, and show the game at the new position
+    pub fn update_replay(&mut self) {
+        if let Some(playback) = self.state.replay.as_mut() {
+            playback.advance();
+            self.state.game = Some(playback.game_at_position());
+        }
+    }
+
+    /// Save the replay of the game whose results are shown
+    pub fn save_replay(&mut self) {
+        let replay = self.state.results.as_ref().map(|results| &results.replay);
+
+        let event = match (replay, self.replay_directory.as_deref()) {
+            (None, _) => Event::error("There is no game to save the replay of", 1, false),
+            (Some(_), None) => {
+                Event::error("There is no directory to save the replay in", 2, false)
+            }
+            (Some(replay), Some(replay_directory)) => match replay.save(replay_directory) {
+                Ok(path) => {
+                    Event::success(&format!("Saved the replay to {}", path.display()), 3, false)
+                }
+                Err(error) => Event::error(
+                    &format!(
+                        "Could not save the replay to {}: {error}",
+                        replay_directory.display()
+                    ),
+                    2,
+                    false,
+                ),
+            },
+        };
+        self.add_log_event(event);
+    }
+
+    /// Called on every tick of the UI, starts the practice game once its countdown is over
+    /// and moves its ghost. Online games are started by the `GameStart` message instead
+    pub fn on_tick(&mut self) {
+        self.update_replay();
+
+        let current_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let practice_started = match self.state.game.as_mut() {
+            Some(game_data)
+                if game_data.mode == GameMode::Practice
+                    && game_data.status == GameStatus::Init
+                    && current_time >= game_data.starts_at =>
+            {
+                game_data.status = GameStatus::InProgress;
+                game_data.started_at = Some(time::Instant::now());
+                true
+            }
+            _ => false,
+        };
+
+        if practice_started {
+            self.add_log_event(Event::success("Practice Started", 10, true));
+        }
+
+        if let Some(game_data) = self.state.game.as_mut() {
+            game_data.update_ghost();
+        }
+    }
+
+    /// End the practice game once the prompt has been typed, and show the result
+    /// The run is saved as the ghost of the prompt if it is the personal best
+    pub fn finish_practice(&mut self) {
+        let Some(game_data) = self.state.game.as_mut() else {
+            return;
+        };
+        game_data.finished_in = Some(game_data.elapsed_millis());
+        game_data.status = GameStatus::Finished;
+
+        let mut results =
+            UiResultsData::new(game_data, None, game_data.net_wpm(), game_data.accuracy());
+        results.raced_ghost = game_data.ghost.is_some();
+
+        if let Some(ghosts) = self.ghosts.as_ref() {
+            match ghosts.record(&results.replay) {
+                Ok(previous_best) => results.previous_best = previous_best,
+                Err(error) => self.add_log_event(Event::error(
+                    &format!("Could not save the personal best: {error}"),
+                    2,
+                    false,
+                )),
+            }
+        }
+
+        self.show_results(results);
+        self.add_log_event(Event::success("Practice finished!", 2, true));
+    }
+
+    /// Leave the finished game and show its summary
+    pub fn show_results(&mut self, results: UiResultsData) {
+        self.state.results = Some(results);
+        self.state.game = None;
+        self.state.cursor_position = 0;
+        self.current_tab = Tab::Results;
+    }
+
+    /// Play again with the same opponent, or practice again after a practice game
+    /// The ghost of the prompt is raced again, it is the new personal best if it was beaten
+    pub fn rematch(&mut self) {
+        let Some(results) = self.state.results.as_ref() else {
+            return;
+        };
+
+        match (results.mode, results.opponents.as_slice()) {
+            (GameMode::Practice, _) if results.raced_ghost => {
+                let prompt_text = results.replay.prompt_text.to_owned();
+                self.race_ghost(Some(&prompt_text));
+            }
+            (GameMode::Practice, _) => self.start_practice(),
+            (GameMode::Online, [opponent]) if self.is_connected() => {
+                if let Err(error) = opponent.challenge(self.event_sender.clone()) {
+                    self.add_log_event(Event::error(&error.to_string(), 1, true));
+                }
+            }
+            (GameMode::Online, [_]) => {
+                let offline_error = Event::error("Not connected to the server", 1, false);
+                self.add_log_event(offline_error);
+            }
+            (GameMode::Online, _) => {
+                let invalid_action_error = Event::error(
+                    "Rematch is only for one vs one games, create a race to play with everyone again",
+                    2,
+                    false,
+                );
+                self.add_log_event(invalid_action_error);
+            }
+            // Spectated and replayed games end without showing the results
+            (GameMode::Spectator | GameMode::Replay, _) => {}
+        }
+    }
+
+    /// Leave the results, online players go back to the arena and the others to the menu
+    pub fn leave_results(&mut self) {
+        let is_online = self
+            .state
+            .results
+            .take()
+            .is_some_and(|results| results.mode == GameMode::Online);
+
+        self.current_tab = if is_online && self.is_connected() {
+            Tab::Arena
+        } else {
+            Tab::Menu
+        };
+    }
+
+    /// Send the message to the server, an error event is shown if it cannot be sent
+    pub fn send_to_server(&mut self, message: UiMessage) {
+        if let Err(error) = send_ui_message(&self.event_sender, message) {
+            self.add_log_event(Event::error(&error.to_string(), 1, true));
         }
     }
 
     pub fn add_log_event(&mut self, event: Event) {
+        // Every second of a countdown is an event, only the first one is worth writing
+        if let Some(log_file) = self.log_file.as_mut() {
+            if !matches!(event.log_type, LogType::CountDown) || event.is_priority {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                writeln!(
+                    log_file,
+                    "{timestamp} [{}] {}",
+                    event.log_type.label(),
+                    event.message
+                )
+                .ok();
+            }
+        }
+
         // If it is a priority event, remove all other events from the queue
         if event.is_priority {
             self.events.clear();
@@ -313,23 +1367,370 @@ impl App {
         self.events.push_back(event);
     }
 
+    /// The challenge currently waiting for a response, if it is in the given direction
+    fn current_challenge(&self, direction: ChallengeDirection) -> Option<&ChallengeData> {
+        self.state
+            .challenge
+            .as_ref()
+            .filter(|challenge_data| challenge_data.direction == direction)
+    }
+
     pub fn accept_current_challenge(&mut self) {
-        if let Some(challenge_data) = self.state.challenge.as_ref() {
+        if let Some(challenge_data) = self.current_challenge(ChallengeDirection::Incoming) {
             let accept_challenge_ui_message = UiMessage::AcceptChallenge {
                 user_id: challenge_data.opponent_id.to_owned(),
             };
-            self.event_sender
-                .blocking_send(accept_challenge_ui_message)
-                .unwrap();
+            self.send_to_server(accept_challenge_ui_message);
         } else {
             let invalid_action_error = Event::error("No active challenges to accept", 1, false);
             self.add_log_event(invalid_action_error);
         }
     }
+
+    /// Show the profile, the history and statistics are fetched again from the server
+    pub fn open_profile(&mut self) {
+        self.send_to_server(UiMessage::GetProfile);
+        self.current_tab = Tab::Profile;
+    }
+
+    /// Fetch the leaderboard which is currently chosen
+    pub fn fetch_leaderboard(&mut self) {
+        let get_leaderboard_ui_message = UiMessage::GetLeaderboard {
+            category: self.state.leaderboard.category,
+            period: self.state.leaderboard.period,
+        };
+        self.send_to_server(get_leaderboard_ui_message);
+    }
+
+    pub fn open_leaderboard(&mut self) {
+        self.fetch_leaderboard();
+        self.current_tab = Tab::Leaderboard;
+    }
+
+    /// Move the selection between the players and the live games of the arena, and then the chat
+    pub fn switch_arena_focus(&mut self) {
+        match self.state.arena_focus {
+            ArenaFocus::Players if !self.state.live_games.items.is_empty() => {
+                self.state.arena_focus = ArenaFocus::LiveGames
+            }
+            _ => {
+                self.state.arena_focus = ArenaFocus::Players;
+                self.focus_chat();
+            }
+        }
+    }
+
+    /// Send the keys to the chat, which is only available while connected to the server
+    pub fn focus_chat(&mut self) {
+        if !self.is_connected() {
+            let offline_error = Event::error("Chat is not available while offline", 1, false);
+            self.add_log_event(offline_error);
+        } else if self.ensure_supported(models::Feature::Chat, "Chat") {
+            self.state.chat.focused = true;
+        }
+    }
+
+    /// Send the message being written to the chat, the chat stays focused to write the next one
+    pub fn send_chat(&mut self) {
+        let text = self.state.chat.input.trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+        if !self.is_connected() {
+            let offline_error = Event::error("Chat message not sent, you are offline", 1, false);
+            self.add_log_event(offline_error);
+            return;
+        }
+
+        self.state.chat.input.clear();
+        self.send_to_server(UiMessage::Chat { text });
+    }
+
+    pub fn spectate_selected_game(&mut self) {
+        if !self.ensure_supported(models::Feature::Spectating, "Spectating") {
+            return;
+        }
+
+        if let Some(live_game) = self.state.live_games.get_selected_item() {
+            let spectate_ui_message = UiMessage::SpectateGame {
+                game_id: live_game.game_id.to_owned(),
+            };
+            self.send_to_server(spectate_ui_message);
+        } else {
+            let invalid_action_error = Event::error("No game is selected to spectate", 1, false);
+            self.add_log_event(invalid_action_error);
+        }
+    }
+
+    /// Leave the spectated game and go back to the arena
+    pub fn stop_spectating(&mut self) {
+        let is_spectating = self
+            .state
+            .game
+            .as_ref()
+            .is_some_and(|game_data| game_data.mode == GameMode::Spectator);
+
+        if is_spectating {
+            self.send_to_server(UiMessage::StopSpectating);
+            self.state.game = None;
+            self.current_tab = Tab::Arena;
+        }
+    }
+
+    /// Join the ranked queue, or leave it if the user is already waiting
+    pub fn toggle_ranked_queue(&mut self) {
+        if !self.ensure_supported(models::Feature::Ranked, "Ranked") {
+            return;
+        }
+
+        let ui_message = if self.state.in_ranked_queue {
+            UiMessage::LeaveRankedQueue
+        } else {
+            UiMessage::JoinRankedQueue
+        };
+        self.send_to_server(ui_message);
+    }
+
+    /// Returns true if the current user owns the race lobby they have joined
+    pub fn is_race_owner(&self) -> bool {
+        match (self.state.race_lobby.as_ref(), self.current_user.as_ref()) {
+            (Some(race_lobby), Some(current_user)) => race_lobby.owner_id == current_user.id,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the keys are being typed into a text field rather than handled by the tab
+    pub fn is_entering_text(&self) -> bool {
+        self.state.chat.focused || self.state.room_code_input.is_some()
+    }
+
+    /// Ask for the code of a private room, or go back to the room if the user is already in one
+    pub fn open_private_room(&mut self) {
+        let is_in_room = self
+            .state
+            .race_lobby
+            .as_ref()
+            .is_some_and(|race_lobby| race_lobby.code.is_some());
+
+        if is_in_room {
+            self.current_tab = Tab::Arena;
+        } else if self.state.race_lobby.is_some() {
+            let invalid_action_error = Event::error("Leave the current race lobby first", 1, false);
+            self.add_log_event(invalid_action_error);
+        } else {
+            self.state.room_code_input = Some(String::new());
+        }
+    }
+
+    /// Join the room with the code which has been typed, or create a new room if it is empty
+    pub fn enter_private_room(&mut self) {
+        let Some(code) = self.state.room_code_input.take() else {
+            return;
+        };
+
+        let ui_message = if code.is_empty() {
+            UiMessage::CreateRoom
+        } else {
+            UiMessage::JoinRoom { code }
+        };
+        self.send_to_server(ui_message);
+        self.current_tab = Tab::Arena;
+    }
+
+    pub fn create_race(&mut self) {
+        if self.state.race_lobby.is_some() {
+            let invalid_action_error = Event::error("Leave the current race lobby first", 1, false);
+            self.add_log_event(invalid_action_error);
+        } else {
+            self.send_to_server(UiMessage::CreateRace);
+        }
+    }
+
+    pub fn start_race(&mut self) {
+        match self.state.race_lobby.as_ref() {
+            Some(race_lobby) if self.is_race_owner() => {
+                let start_race_ui_message = UiMessage::StartRace {
+                    race_id: race_lobby.race_id.to_owned(),
+                };
+                self.send_to_server(start_race_ui_message);
+            }
+            _ => {
+                let invalid_action_error = Event::error(
+                    "Only the owner of a race lobby can start the race",
+                    1,
+                    false,
+                );
+                self.add_log_event(invalid_action_error);
+            }
+        }
+    }
+
+    pub fn leave_race(&mut self) {
+        if let Some(race_lobby) = self.state.race_lobby.take() {
+            let leave_race_ui_message = UiMessage::LeaveRace {
+                race_id: race_lobby.race_id,
+            };
+            self.send_to_server(leave_race_ui_message);
+        } else {
+            let invalid_action_error = Event::error("You have not joined any race", 1, false);
+            self.add_log_event(invalid_action_error);
+        }
+    }
+
+    pub fn join_invited_race(&mut self) {
+        if let Some(race_invitation) = self.state.race_invitation.take() {
+            let joining_event = Event::info(
+                &format!("Joining the race of {}", race_invitation.owner_name),
+                1,
+                false,
+            );
+            self.add_log_event(joining_event);
+
+            let join_race_ui_message = UiMessage::JoinRace {
+                race_id: race_invitation.race_id,
+            };
+            self.send_to_server(join_race_ui_message);
+        } else {
+            let invalid_action_error = Event::error("No race invitations to accept", 1, false);
+            self.add_log_event(invalid_action_error);
+        }
+    }
+
+    pub fn reject_current_challenge(&mut self) {
+        if let Some(challenge_data) = self.current_challenge(ChallengeDirection::Incoming) {
+            let reject_challenge_ui_message = UiMessage::RejectChallenge {
+                user_id: challenge_data.opponent_id.to_owned(),
+            };
+            self.send_to_server(reject_challenge_ui_message);
+            self.state.challenge = None;
+        } else {
+            let invalid_action_error = Event::error("No active challenges to reject", 1, false);
+            self.add_log_event(invalid_action_error);
+        }
+    }
+
+    pub fn cancel_current_challenge(&mut self) {
+        if let Some(challenge_data) = self.current_challenge(ChallengeDirection::Outgoing) {
+            let cancel_challenge_ui_message = UiMessage::CancelChallenge {
+                user_id: challenge_data.opponent_id.to_owned(),
+            };
+            self.send_to_server(cancel_challenge_ui_message);
+            self.state.challenge = None;
+        } else {
+            let invalid_action_error = Event::error("No active challenges to cancel", 1, false);
+            self.add_log_event(invalid_action_error);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChallengeDirection {
+    /// The opponent has challenged the current user
+    Incoming,
+    /// The current user has challenged the opponent
+    Outgoing,
+}
+
+/// Number of words and characters listed in the results
+const RESULTS_BREAKDOWN_COUNT: usize = 3;
+
+/// Summary of a finished game, shown in the `Results` tab
+pub struct UiResultsData {
+    pub mode: GameMode,
+    /// Finishing position of the user and the number of racers, not present for practice games
+    pub placement: Option<(usize, usize)>,
+    /// Reason for which the user was disqualified
+    pub disqualification: Option<String>,
+    pub wpm: f32,
+    pub accuracy: f32,
+    /// Milliseconds taken to type the prompt
+    pub time_taken: u64,
+    pub slowest_words: Vec<(String, u64)>,
+    pub mistyped_characters: Vec<(char, u32)>,
+    /// The other users in the game, who can be challenged for a rematch
+    pub opponents: Vec<Player>,
+    pub replay: Replay,
+    /// Milliseconds of the personal best on the prompt before this practice game, if any
+    pub previous_best: Option<u64>,
+    /// The practice game was raced against the ghost of the personal best
+    pub raced_ghost: bool,
+}
+
+impl UiResultsData {
+    /// `placement` is the result sent by the server, the other details are derived from the typing
+    pub fn new(
+        game_data: &UiGameData,
+        placement: Option<(&models::Placement, usize)>,
+        wpm: f32,
+        accuracy: f32,
+    ) -> Self {
+        let opponents = game_data
+            .racers
+            .iter()
+            .filter(|racer| {
+                placement.is_some_and(|(placement, _)| placement.user_id != racer.user_id)
+            })
+            .map(|racer| Player {
+                id: racer.user_id.to_owned(),
+                status: UserStatus::Available,
+                display_name: racer.display_name.to_owned(),
+                rating: None,
+                bot: false,
+            })
+            .collect();
+
+        // The player is the first racer in practice games
+        let player_name = placement
+            .map(|(placement, _)| placement.display_name.as_str())
+            .or_else(|| {
+                game_data
+                    .racers
+                    .first()
+                    .map(|racer| racer.display_name.as_str())
+            })
+            .unwrap_or("You");
+        let played_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        Self {
+            mode: game_data.mode,
+            placement: placement.map(|(placement, racer_count)| (placement.position, racer_count)),
+            disqualification: placement
+                .filter(|(placement, _)| placement.disqualified)
+                .map(|(placement, _)| {
+                    placement
+                        .note
+                        .to_owned()
+                        .unwrap_or_else(|| "Disqualified".to_string())
+                }),
+            wpm,
+            accuracy,
+            time_taken: game_data.time_taken(),
+            slowest_words: game_data.slowest_words(RESULTS_BREAKDOWN_COUNT),
+            mistyped_characters: game_data.mistyped_characters(RESULTS_BREAKDOWN_COUNT),
+            opponents,
+            replay: game_data
+                .recorder
+                .to_replay(game_data, player_name, played_at),
+            previous_best: None,
+            raced_ghost: false,
+        }
+    }
+}
+
+pub struct RaceInvitationData {
+    pub race_id: String,
+    pub owner_name: String,
 }
 
 pub struct ChallengeData {
     pub opponent_id: String,
+    pub opponent_name: String,
+    pub direction: ChallengeDirection,
+    /// Unix timestamp after which the challenge expires
+    pub expires_at: u64,
 }
 
 #[derive(Debug)]
@@ -345,8 +1746,53 @@ pub enum UiMessage {
         /// Username of the opponent
         user_id: String,
     },
+    RejectChallenge {
+        /// User id of the opponent who raised the challenge
+        user_id: String,
+    },
+    CreateRace,
+    InviteToRace {
+        race_id: String,
+        /// Username of the invited player
+        user_name: String,
+        /// User id of the invited player
+        user_id: String,
+    },
+    JoinRace {
+        race_id: String,
+    },
+    LeaveRace {
+        race_id: String,
+    },
+    StartRace {
+        race_id: String,
+    },
+    CancelChallenge {
+        /// User id of the player who was challenged
+        user_id: String,
+    },
     UpdateProgress {
         game_id: String,
-        progress: u16,
+        keystrokes: Vec<models::Keystroke>,
+        stats: models::TypingStats,
+    },
+    /// Fetch the history and statistics of the current user
+    GetProfile,
+    JoinRankedQueue,
+    LeaveRankedQueue,
+    GetLeaderboard {
+        category: models::LeaderboardCategory,
+        period: models::LeaderboardPeriod,
+    },
+    SpectateGame {
+        game_id: String,
+    },
+    StopSpectating,
+    Chat {
+        text: String,
+    },
+    CreateRoom,
+    JoinRoom {
+        code: String,
     },
 }
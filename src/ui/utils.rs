@@ -2,3 +2,29 @@ pub fn calculate_progress(current_position: usize, total_length: usize) -> u16 {
     let progress = current_position as f64 / total_length as f64;
     (progress * 100.0) as u16
 }
+
+/// Format the author and source of a prompt as `— author, source`
+pub fn format_attribution(author: Option<String>, source: Option<String>) -> Option<String> {
+    match (author, source) {
+        (Some(author), Some(source)) => Some(format!("— {author}, {source}")),
+        (Some(author), None) => Some(format!("— {author}")),
+        (None, Some(source)) => Some(format!("— {source}")),
+        (None, None) => None,
+    }
+}
+
+/// Time since the unix timestamp in milliseconds, as `5m ago`
+pub fn format_age(timestamp_millis: u64) -> String {
+    let current_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let seconds = current_time.saturating_sub(timestamp_millis) / 1000;
+
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
@@ -2,28 +2,30 @@
 use futures_util::{SinkExt, StreamExt};
 use std::{
     sync::{Arc, Mutex},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 
-use crate::{models as server_models, ui::types};
-const WS_URL: &str = "ws://127.0.0.1:8080";
+use crate::{
+    errors::{Error, ProtocolError, TransportError},
+    models as server_models,
+    ui::{stateful_list::StatefulList, types, utils},
+};
 
-fn count_down_to_zero(app: Arc<Mutex<types::App>>, event: &str, action: &str, duration: u8) {
-    let first_event = types::Event::countdown(event, action, duration, true);
-    {
-        let mut unlocked_app = app.lock().unwrap();
-        unlocked_app.add_log_event(first_event);
-    }
+/// Seconds to wait before reconnecting, doubled after every failed attempt
+const INITIAL_RECONNECT_DELAY: u64 = 1;
 
-    // To simulate countdown, add `duration` events to the queue with each event lasting for one second
-    (1..duration)
-        .rev()
-        .map(|duration| types::Event::countdown(event, action, duration, false))
-        .for_each(|event| {
-            let mut unlocked_app = app.lock().unwrap();
-            unlocked_app.add_log_event(event);
-        });
+/// Longest wait between two attempts to reconnect
+const MAXIMUM_RECONNECT_DELAY: u64 = 30;
+
+/// Seconds remaining for the unix timestamp, to be used as the duration of an event
+fn seconds_until(timestamp: u64) -> u8 {
+    let current_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    u8::try_from(timestamp.saturating_sub(current_time)).unwrap_or(u8::MAX)
 }
 
 fn handle_incoming_websocket_message(
@@ -31,243 +33,1100 @@ fn handle_incoming_websocket_message(
     websock_message: server_models::WSServerMessage,
 ) {
     match websock_message {
-        server_models::WSServerMessage::RequestForChallenge { from_user } => {
+        server_models::WSServerMessage::RequestForChallenge {
+            from_user,
+            expires_at,
+        } => {
             let mut unlocked_app = app.lock().unwrap();
             // Show a prompt for the user to accept / reject the challenge
-            // This should last only for few seconds, based on the expiry time
-            // TODO: add expiry time
+            // This lasts only till the challenge expires
             unlocked_app.add_log_event(types::Event::info(
                 &format!(
                     "Challenge received from {}. Accept [A/a] | Reject [R/r]",
                     from_user.display_name
                 ),
-                5,
+                seconds_until(expires_at),
                 false,
             ));
 
             let challenge_data = types::ChallengeData {
                 opponent_id: from_user.id.to_string(),
+                opponent_name: from_user.display_name,
+                direction: types::ChallengeDirection::Incoming,
+                expires_at,
+            };
+            unlocked_app.state.challenge = Some(challenge_data);
+        }
+        server_models::WSServerMessage::ChallengeCreated {
+            to_user,
+            expires_at,
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+            unlocked_app.add_log_event(types::Event::info(
+                &format!(
+                    "Waiting for {} to respond. Cancel [C/c]",
+                    to_user.display_name
+                ),
+                seconds_until(expires_at),
+                false,
+            ));
+
+            let challenge_data = types::ChallengeData {
+                opponent_id: to_user.id,
+                opponent_name: to_user.display_name,
+                direction: types::ChallengeDirection::Outgoing,
+                expires_at,
             };
             unlocked_app.state.challenge = Some(challenge_data);
         }
-        server_models::WSServerMessage::UserStatus { connected_users } => {
+        server_models::WSServerMessage::ChallengeRejected { by_user } => {
+            let mut unlocked_app = app.lock().unwrap();
+            unlocked_app.state.challenge = None;
+            unlocked_app.add_log_event(types::Event::error(
+                &format!("{} rejected your challenge", by_user.display_name),
+                2,
+                true,
+            ));
+        }
+        server_models::WSServerMessage::ChallengeCancelled { by_user } => {
+            let mut unlocked_app = app.lock().unwrap();
+            unlocked_app.state.challenge = None;
+            unlocked_app.add_log_event(types::Event::info(
+                &format!("{} cancelled the challenge", by_user.display_name),
+                2,
+                true,
+            ));
+        }
+        server_models::WSServerMessage::ChallengeExpired {
+            from_user_id,
+            to_user_id,
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+
+            let expired_challenge_name = unlocked_app
+                .state
+                .challenge
+                .as_ref()
+                .filter(|challenge_data| {
+                    challenge_data.opponent_id == from_user_id
+                        || challenge_data.opponent_id == to_user_id
+                })
+                .map(|challenge_data| challenge_data.opponent_name.to_owned());
+
+            if let Some(opponent_name) = expired_challenge_name {
+                unlocked_app.state.challenge = None;
+                unlocked_app.add_log_event(types::Event::info(
+                    &format!("Challenge with {opponent_name} has expired"),
+                    2,
+                    true,
+                ));
+            }
+        }
+        server_models::WSServerMessage::UserStatus {
+            connected_users,
+            live_games,
+        } => {
             let mut unlocked_app = app.lock().unwrap();
             // filter out current user
+            let current_user_id = unlocked_app
+                .current_user
+                .as_ref()
+                .map(|current_user| current_user.id.clone());
             let users_without_current_user = connected_users
                 .into_iter()
-                .filter(|user| user.id != unlocked_app.current_user.as_ref().unwrap().id)
+                .filter(|user| Some(&user.id) != current_user_id.as_ref())
                 .collect();
             unlocked_app
                 .state
                 .players
-                .clear_and_insert_items(users_without_current_user)
+                .clear_and_insert_items(users_without_current_user);
+
+            unlocked_app
+                .state
+                .live_games
+                .clear_and_insert_items(live_games);
+            if unlocked_app.state.live_games.items.is_empty() {
+                unlocked_app.state.arena_focus = types::ArenaFocus::Players;
+            }
         }
-        server_models::WSServerMessage::SuccessfulConnection { user } => {
+        server_models::WSServerMessage::SuccessfulConnection {
+            user,
+            resume_token,
+            active_game_id,
+            account_token,
+            protocol_version,
+            features,
+        } => {
             let mut unlocked_app = app.lock().unwrap();
-            let name_assign_log_event = types::Event::success(
-                &format!("Master Cat assigned name {} to you", user.display_name),
-                1,
-                false,
-            );
+            unlocked_app.server_features =
+                server_models::negotiate_features(protocol_version, &features);
+            let is_resumed = unlocked_app
+                .current_user
+                .as_ref()
+                .is_some_and(|current_user| {
+                    current_user.id == user.id && current_user.display_name == user.display_name
+                });
+
+            let name_assign_log_event = if is_resumed {
+                types::Event::success(&format!("Reconnected as {}", user.display_name), 1, false)
+            } else {
+                types::Event::success(
+                    &format!("Master Cat assigned name {} to you", user.display_name),
+                    1,
+                    false,
+                )
+            };
             unlocked_app.add_log_event(name_assign_log_event);
-            // User details of the current user
-            unlocked_app.current_user = Some(types::Player {
-                id: user.id,
-                status: types::UserStatus::Available,
-                display_name: user.display_name,
+            unlocked_app.resume_token = Some(resume_token);
+            if let Some(account_token) = account_token {
+                unlocked_app.save_account(&user.display_name, account_token);
+            }
+
+            // Challenges, race lobbies and the ranked queue are left by the server when the
+            // connection is lost
+            unlocked_app.state.challenge = None;
+            unlocked_app.state.race_lobby = None;
+            unlocked_app.state.race_invitation = None;
+            unlocked_app.state.in_ranked_queue = false;
+
+            // Spectators are removed from the game when the connection is lost
+            let is_spectating = unlocked_app
+                .state
+                .game
+                .as_ref()
+                .is_some_and(|game_data| game_data.mode == types::GameMode::Spectator);
+            if is_spectating {
+                unlocked_app.state.game = None;
+                unlocked_app.current_tab = types::Tab::Arena;
+                unlocked_app.add_log_event(types::Event::error(
+                    "Stopped spectating as the connection was lost",
+                    2,
+                    false,
+                ));
+            }
+
+            // The game may have ended while the user was disconnected
+            let is_game_lost = unlocked_app.state.game.as_ref().is_some_and(|game_data| {
+                game_data.mode == types::GameMode::Online
+                    && active_game_id.as_deref() != Some(game_data.game_id())
             });
+            if is_game_lost {
+                unlocked_app.state.game = None;
+                unlocked_app.state.cursor_position = 0;
+                unlocked_app.current_tab = types::Tab::Arena;
+                unlocked_app.add_log_event(types::Event::error(
+                    "The game ended while you were disconnected",
+                    2,
+                    false,
+                ));
+            }
+
+            // User details of the current user
+            unlocked_app.current_user = Some(user);
         }
-        server_models::WSServerMessage::Error { message } => {
+        server_models::WSServerMessage::Error { code, message } => {
             let mut unlocked_app = app.lock().unwrap();
-            let error_event_log = types::Event::error(&message, 1, false);
-            unlocked_app.add_log_event(error_event_log);
+            // Keystrokes still on their way when the game ends are refused, which is expected
+            let is_game_over = match unlocked_app.state.game.as_ref() {
+                Some(game_data) => game_data.status == server_models::GameStatus::Finished,
+                None => true,
+            };
+            let is_stale_game_error =
+                code == server_models::ErrorCode::GameNotFound && is_game_over;
+
+            if !is_stale_game_error {
+                let error_event_log = types::Event::error(&message, 1, false);
+                unlocked_app.add_log_event(error_event_log);
+            }
         }
         server_models::WSServerMessage::GameInit {
             game_id,
             prompt_text,
+            prompt_author,
+            prompt_source,
             starts_at,
+            participants,
+            ranked,
+            cursor,
         } => {
-            let seconds_for_game_start = {
-                let mut unlocked_app = app.lock().unwrap();
-                let ui_game_data = types::UiGameData::new(game_id, prompt_text, starts_at);
-                unlocked_app.state.game = Some(ui_game_data);
-                unlocked_app.current_tab = types::Tab::Game;
-
-                let current_time = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-
-                starts_at.saturating_sub(current_time)
-            };
+            let mut unlocked_app = app.lock().unwrap();
+            if ranked {
+                unlocked_app.add_log_event(types::Event::success(
+                    "Found a ranked opponent",
+                    1,
+                    false,
+                ));
+            }
+            let prompt_attribution = utils::format_attribution(prompt_author, prompt_source);
+            let mut ui_game_data = types::UiGameData::new(
+                game_id,
+                prompt_text,
+                prompt_attribution,
+                starts_at,
+                participants,
+            );
 
-            let cloned_app = app.clone();
+            // The game has already started when the player logs in again from a new client
+            let current_time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let is_started = starts_at < current_time;
+            let mut cursor_position = 0;
+            if is_started {
+                ui_game_data.status = server_models::GameStatus::InProgress;
+                ui_game_data.started_at =
+                    Instant::now().checked_sub(Duration::from_secs(current_time - starts_at));
+                cursor_position = ui_game_data.resume_at(cursor);
+            }
+            unlocked_app.state.game = Some(ui_game_data);
+            unlocked_app.state.challenge = None;
+            // The players of a private room are still in it after the race
+            unlocked_app.state.race_lobby = unlocked_app
+                .state
+                .race_lobby
+                .take()
+                .filter(|race_lobby| race_lobby.code.is_some());
+            unlocked_app.state.race_invitation = None;
+            unlocked_app.state.in_ranked_queue = false;
+            unlocked_app.state.cursor_position = cursor_position as u16;
+            unlocked_app.current_tab = types::Tab::Game;
 
-            count_down_to_zero(
-                cloned_app,
-                "game",
-                "start",
-                u8::try_from(seconds_for_game_start).unwrap(),
-            );
+            if is_started {
+                unlocked_app.add_log_event(types::Event::success(
+                    "Rejoined the race where you left off",
+                    2,
+                    true,
+                ));
+            } else {
+                unlocked_app.add_countdown_events("game", "start", seconds_until(starts_at));
+            }
         }
         server_models::WSServerMessage::GameStart => {
             let mut unlocked_app = app.lock().unwrap();
 
             if let Some(game_data) = unlocked_app.state.game.as_mut() {
                 game_data.status = server_models::GameStatus::InProgress;
+                game_data.started_at = Some(std::time::Instant::now());
             }
             unlocked_app.add_log_event(types::Event::success("Game Started", 10, true));
         }
-        server_models::WSServerMessage::GameUpdate {
-            my_progress,
-            opponent_progress,
+        server_models::WSServerMessage::GameUpdate { racers } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let current_user_id = unlocked_app
+                .current_user
+                .as_ref()
+                .map(|current_user| current_user.id.clone());
+
+            if let Some(game_data) = unlocked_app.state.game.as_mut() {
+                let elapsed = game_data.elapsed_millis();
+                game_data
+                    .recorder
+                    .record_racers(elapsed, &racers, current_user_id.as_deref());
+                game_data.racers = racers;
+            }
+        }
+        server_models::WSServerMessage::OpponentDisconnected {
+            user_id,
+            display_name,
+            forfeits_at,
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+            if let Some(game_data) = unlocked_app.state.game.as_mut() {
+                game_data
+                    .racers
+                    .iter_mut()
+                    .filter(|racer| racer.user_id == user_id)
+                    .for_each(|racer| racer.disconnected = true);
+            }
+            unlocked_app.add_log_event(types::Event::error(
+                &format!("{display_name} lost the connection, waiting for them to return"),
+                seconds_until(forfeits_at),
+                false,
+            ));
+        }
+        server_models::WSServerMessage::OpponentReconnected {
+            user_id,
+            display_name,
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+            if let Some(game_data) = unlocked_app.state.game.as_mut() {
+                game_data
+                    .racers
+                    .iter_mut()
+                    .filter(|racer| racer.user_id == user_id)
+                    .for_each(|racer| racer.disconnected = false);
+            }
+            unlocked_app.add_log_event(types::Event::success(
+                &format!("{display_name} is back in the race"),
+                2,
+                true,
+            ));
+        }
+        server_models::WSServerMessage::RaceLobbyUpdate { lobby } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let is_owner = unlocked_app
+                .current_user
+                .as_ref()
+                .is_some_and(|current_user| current_user.id == lobby.owner_id);
+
+            let lobby_event = match lobby.code.as_ref() {
+                Some(code) if is_owner => types::Event::info(
+                    &format!(
+                        "Room {code} has {} players, share the code to let others join. Start [S/s] | Leave [X/x]",
+                        lobby.participants.len()
+                    ),
+                    10,
+                    false,
+                ),
+                Some(code) => types::Event::info(
+                    &format!(
+                        "Room {code} has {} players, waiting for the owner to start. Leave [X/x]",
+                        lobby.participants.len()
+                    ),
+                    5,
+                    false,
+                ),
+                None if is_owner => types::Event::info(
+                    &format!(
+                        "Race lobby has {} racers. Invite [Enter] | Start [S/s] | Leave [X/x]",
+                        lobby.participants.len()
+                    ),
+                    5,
+                    false,
+                ),
+                None => types::Event::info(
+                    &format!(
+                        "Race lobby has {} racers, waiting for the owner to start. Leave [X/x]",
+                        lobby.participants.len()
+                    ),
+                    5,
+                    false,
+                ),
+            };
+            unlocked_app.add_log_event(lobby_event);
+            unlocked_app.state.race_lobby = Some(lobby);
+        }
+        server_models::WSServerMessage::RaceInvitation { race_id, from_user } => {
+            let mut unlocked_app = app.lock().unwrap();
+            unlocked_app.add_log_event(types::Event::info(
+                &format!(
+                    "{} invited you to a race. Join [Y/y]",
+                    from_user.display_name
+                ),
+                10,
+                false,
+            ));
+            unlocked_app.state.race_invitation = Some(types::RaceInvitationData {
+                race_id,
+                owner_name: from_user.display_name,
+            });
+        }
+        server_models::WSServerMessage::RaceLobbyClosed { race_id } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let is_current_lobby = unlocked_app
+                .state
+                .race_lobby
+                .as_ref()
+                .is_some_and(|race_lobby| race_lobby.race_id == race_id);
+
+            if is_current_lobby {
+                let left_event = match unlocked_app.state.race_lobby.take() {
+                    Some(race_lobby) if race_lobby.code.is_some() => "Left the room",
+                    _ => "Left the race lobby",
+                };
+                unlocked_app.add_log_event(types::Event::info(left_event, 2, true));
+            }
+
+            let is_invited_lobby = unlocked_app
+                .state
+                .race_invitation
+                .as_ref()
+                .is_some_and(|race_invitation| race_invitation.race_id == race_id);
+
+            if is_invited_lobby {
+                unlocked_app.state.race_invitation = None;
+            }
+        }
+        server_models::WSServerMessage::GameOver {
+            placements,
+            wpm,
+            accuracy,
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let current_user_id = unlocked_app
+                .current_user
+                .as_ref()
+                .map(|current_user| current_user.id.clone());
+
+            let my_placement = placements
+                .iter()
+                .find(|placement| Some(&placement.user_id) == current_user_id.as_ref());
+
+            let game_over_event = match my_placement {
+                Some(placement) if placement.disqualified => types::Event::error(
+                    &format!(
+                        "Game over! {}",
+                        placement.note.as_deref().unwrap_or("You were disqualified")
+                    ),
+                    10,
+                    true,
+                ),
+                _ => {
+                    let position = my_placement
+                        .map(|placement| placement.position)
+                        .unwrap_or(placements.len());
+
+                    types::Event::success(
+                        &format!(
+                            "Game over! You finished at position {position} of {}",
+                            placements.len()
+                        ),
+                        10,
+                        true,
+                    )
+                }
+            };
+
+            if let Some(game_data) = unlocked_app.state.game.as_mut() {
+                game_data.status = server_models::GameStatus::Finished;
+
+                let placement = my_placement.map(|placement| (placement, placements.len()));
+                let results = types::UiResultsData::new(game_data, placement, wpm, accuracy);
+                unlocked_app.show_results(results);
+            }
+            unlocked_app.add_log_event(game_over_event);
+        }
+        server_models::WSServerMessage::History { races } => {
+            app.lock()
+                .unwrap()
+                .state
+                .history
+                .clear_and_insert_items(races);
+        }
+        server_models::WSServerMessage::Stats { stats } => {
+            app.lock().unwrap().state.stats = Some(stats);
+        }
+        server_models::WSServerMessage::Leaderboard {
+            category,
+            period,
+            entries,
         } => {
             let mut unlocked_app = app.lock().unwrap();
-            unlocked_app.state.game.as_mut().unwrap().my_progress = my_progress;
-            unlocked_app.state.game.as_mut().unwrap().opponent_progress = opponent_progress;
+            let leaderboard = &mut unlocked_app.state.leaderboard;
+
+            // The user may have switched to another leaderboard while this one was on its way
+            if leaderboard.category == category && leaderboard.period == period {
+                leaderboard.entries = StatefulList::with_items(entries);
+            }
+        }
+        server_models::WSServerMessage::SpectatorInit {
+            game_id,
+            prompt_text,
+            prompt_author,
+            prompt_source,
+            starts_at,
+            racers,
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let racer_names = racers
+                .iter()
+                .map(|racer| racer.display_name.as_str())
+                .collect::<Vec<_>>()
+                .join(" vs ");
+            unlocked_app.add_log_event(types::Event::info(
+                &format!("Spectating {racer_names}, press Enter to leave"),
+                3,
+                true,
+            ));
+
+            let prompt_attribution = utils::format_attribution(prompt_author, prompt_source);
+            let ui_game_data = types::UiGameData::spectate(
+                game_id,
+                prompt_text,
+                prompt_attribution,
+                starts_at,
+                racers,
+            );
+            if ui_game_data.status == server_models::GameStatus::Init {
+                unlocked_app.add_countdown_events("game", "start", seconds_until(starts_at));
+            }
+            unlocked_app.state.game = Some(ui_game_data);
+            unlocked_app.state.cursor_position = 0;
+            unlocked_app.current_tab = types::Tab::Game;
+        }
+        server_models::WSServerMessage::SpectatedGameOver {
+            game_id,
+            placements,
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let is_spectated_game = unlocked_app.state.game.as_ref().is_some_and(|game_data| {
+                game_data.mode == types::GameMode::Spectator && game_data.game_id() == game_id
+            });
+
+            if is_spectated_game {
+                unlocked_app.state.game = None;
+                unlocked_app.current_tab = types::Tab::Arena;
+
+                let game_over_event = match placements
+                    .iter()
+                    .find(|placement| placement.position == 1 && !placement.disqualified)
+                {
+                    Some(winner) => types::Event::success(
+                        &format!("Game over! {} won the race", winner.display_name),
+                        5,
+                        true,
+                    ),
+                    None => types::Event::info("Game over! Nobody won the race", 5, true),
+                };
+                unlocked_app.add_log_event(game_over_event);
+            }
+        }
+        server_models::WSServerMessage::RankedQueueJoined { rating } => {
+            let mut unlocked_app = app.lock().unwrap();
+            unlocked_app.state.in_ranked_queue = true;
+            unlocked_app.add_log_event(types::Event::info(
+                &format!(
+                    "Looking for an opponent around your rating of {rating}, select Ranked again to stop"
+                ),
+                10,
+                true,
+            ));
+        }
+        server_models::WSServerMessage::RankedQueueLeft => {
+            let mut unlocked_app = app.lock().unwrap();
+            unlocked_app.state.in_ranked_queue = false;
+            unlocked_app.add_log_event(types::Event::info(
+                "Stopped looking for a ranked opponent",
+                2,
+                false,
+            ));
+        }
+        server_models::WSServerMessage::Chat {
+            from_user_id,
+            display_name,
+            text,
+            game_id,
+            ..
+        } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let is_own = unlocked_app
+                .current_user
+                .as_ref()
+                .is_some_and(|current_user| current_user.id == from_user_id);
+            unlocked_app.state.chat.push(types::ChatLine {
+                display_name,
+                text,
+                in_race: game_id.is_some(),
+                is_own,
+            });
+        }
+        server_models::WSServerMessage::ProgressRejected { game_id, position } => {
+            let mut unlocked_app = app.lock().unwrap();
+            let Some(game_data) = unlocked_app.state.game.as_ref().filter(|game_data| {
+                game_data.game_id() == game_id
+                    && game_data.status == server_models::GameStatus::InProgress
+            }) else {
+                return;
+            };
+
+            // The keystrokes keep their original time, so that the typing speed is measured correctly
+            let keystrokes = game_data
+                .recorder
+                .current_keystrokes()
+                .into_iter()
+                .filter(|keystroke| keystroke.position >= position)
+                .collect::<Vec<_>>();
+            if keystrokes.is_empty() {
+                return;
+            }
+
+            let message = types::UiMessage::UpdateProgress {
+                game_id,
+                keystrokes,
+                stats: game_data.stats(),
+            };
+            if unlocked_app.event_sender.try_send(message).is_err() {
+                let resend_error =
+                    types::Event::error("Could not send the keystrokes again", 1, false);
+                unlocked_app.add_log_event(resend_error);
+            }
+        }
+        // Messages of a newer version of the server, which this client cannot do anything with
+        server_models::WSServerMessage::Unknown => {}
+        server_models::WSServerMessage::RatingUpdate { rating, change } => {
+            let mut unlocked_app = app.lock().unwrap();
+            if let Some(current_user) = unlocked_app.current_user.as_mut() {
+                current_user.rating = Some(rating);
+            }
+            unlocked_app.add_log_event(types::Event::success(
+                &format!("Your rating is now {rating} ({change:+})"),
+                5,
+                false,
+            ));
+        }
+    }
+}
+
+/// Send a message to the server
+async fn try_send_websocket_message<S>(
+    ws_writer: &mut S,
+    websocket_message: &server_models::WSClientMessage,
+) -> Result<(), Error>
+where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Debug,
+{
+    let websocket_message_string = serde_json::to_string(websocket_message)
+        .map_err(|error| ProtocolError::Serialization(error.to_string()))?;
+
+    ws_writer
+        .send(Message::Text(websocket_message_string))
+        .await
+        .map_err(|error| TransportError::SendFailed(format!("{error:?}")).into())
+}
+
+/// Send a message to the server, logging an error event if it could not be sent
+async fn send_websocket_message<S>(
+    app: &Arc<Mutex<types::App>>,
+    ws_writer: &mut S,
+    websocket_message: server_models::WSClientMessage,
+    success_event: Option<types::Event>,
+) where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Debug,
+{
+    let event = match try_send_websocket_message(ws_writer, &websocket_message).await {
+        Ok(_) => success_event,
+        Err(error) => Some(types::Event::error(&error.to_string(), 1, true)),
+    };
+
+    if let Some(event) = event {
+        app.lock().unwrap().add_log_event(event);
+    }
+}
+
+/// Send the message of the app to the server
+/// Progress which could not be sent is kept in `pending_progress`, to be sent after reconnecting
+async fn send_ui_message<S>(
+    app: &Arc<Mutex<types::App>>,
+    ws_writer: &mut S,
+    ui_message: types::UiMessage,
+    pending_progress: &mut Option<PendingProgress>,
+) where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Debug,
+{
+    match ui_message {
+        types::UiMessage::AcceptChallenge {
+            user_id: opponent_user_id,
+        } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::AcceptChallenge { opponent_user_id },
+                Some(types::Event::success("Accepted challenge", 1, true)),
+            )
+            .await;
+        }
+        types::UiMessage::RejectChallenge {
+            user_id: opponent_user_id,
+        } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::RejectChallenge { opponent_user_id },
+                Some(types::Event::info("Rejected challenge", 1, true)),
+            )
+            .await;
+        }
+        types::UiMessage::CancelChallenge {
+            user_id: to_user_id,
+        } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::CancelChallenge { to_user_id },
+                Some(types::Event::info("Cancelled challenge", 1, true)),
+            )
+            .await;
+        }
+        types::UiMessage::ProgressUpdate(_progress) => {}
+        types::UiMessage::Challenge { user_name, user_id } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::Challenge {
+                    to_user_id: user_id,
+                },
+                Some(types::Event::success(
+                    &format!("Successfully sent the challenge to {user_name}"),
+                    2,
+                    false,
+                )),
+            )
+            .await;
+        }
+        types::UiMessage::CreateRace => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::CreateRace,
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::InviteToRace {
+            race_id,
+            user_name,
+            user_id,
+        } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::InviteToRace { race_id, user_id },
+                Some(types::Event::success(
+                    &format!("Invited {user_name} to the race"),
+                    2,
+                    false,
+                )),
+            )
+            .await;
+        }
+        types::UiMessage::JoinRace { race_id } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::JoinRace { race_id },
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::LeaveRace { race_id } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::LeaveRace { race_id },
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::StartRace { race_id } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::StartRace { race_id },
+                None,
+            )
+            .await;
+        }
+        // The pending keystrokes have to reach the server first, they are sent after `SuccessfulConnection`
+        types::UiMessage::UpdateProgress {
+            game_id,
+            keystrokes,
+            stats,
+        } if pending_progress.is_some() => {
+            PendingProgress::add(pending_progress, game_id, keystrokes, stats);
+        }
+        types::UiMessage::UpdateProgress {
+            game_id,
+            keystrokes,
+            stats,
+        } => {
+            let websocket_message = server_models::WSClientMessage::UpdateProgress {
+                game_id,
+                keystrokes,
+                stats,
+            };
+
+            if let Err(error) = try_send_websocket_message(ws_writer, &websocket_message).await {
+                let send_error = types::Event::error(&error.to_string(), 1, true);
+                app.lock().unwrap().add_log_event(send_error);
+
+                if let server_models::WSClientMessage::UpdateProgress {
+                    game_id,
+                    keystrokes,
+                    stats,
+                } = websocket_message
+                {
+                    PendingProgress::add(pending_progress, game_id, keystrokes, stats);
+                }
+            }
+        }
+        types::UiMessage::JoinRankedQueue => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::JoinRankedQueue,
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::LeaveRankedQueue => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::LeaveRankedQueue,
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::GetLeaderboard { category, period } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::GetLeaderboard { category, period },
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::SpectateGame { game_id } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::SpectateGame { game_id },
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::StopSpectating => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::StopSpectating,
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::Chat { text } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::Chat { text },
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::CreateRoom => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::CreateRoom,
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::JoinRoom { code } => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::JoinRoom { code },
+                None,
+            )
+            .await;
+        }
+        types::UiMessage::GetProfile => {
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::GetStats,
+                None,
+            )
+            .await;
+            send_websocket_message(
+                app,
+                ws_writer,
+                server_models::WSClientMessage::GetHistory,
+                None,
+            )
+            .await;
+        }
+    }
+}
+
+/// Keystrokes typed while the connection was lost, these are sent once it is back
+/// so that the server does not see a gap in the typing
+struct PendingProgress {
+    game_id: String,
+    keystrokes: Vec<server_models::Keystroke>,
+    stats: server_models::TypingStats,
+}
+
+impl PendingProgress {
+    /// Keep the keystrokes to be sent later, after the keystrokes which are already pending
+    /// Keystrokes of an older game are dropped
+    fn add(
+        pending_progress: &mut Option<PendingProgress>,
+        game_id: String,
+        keystrokes: Vec<server_models::Keystroke>,
+        stats: server_models::TypingStats,
+    ) {
+        match pending_progress.as_mut() {
+            Some(progress) if progress.game_id == game_id => {
+                progress.keystrokes.extend(keystrokes);
+                progress.stats = stats;
+            }
+            _ => {
+                *pending_progress = Some(PendingProgress {
+                    game_id,
+                    keystrokes,
+                    stats,
+                })
+            }
+        }
+    }
+
+    fn into_ui_message(self) -> types::UiMessage {
+        types::UiMessage::UpdateProgress {
+            game_id: self.game_id,
+            keystrokes: self.keystrokes,
+            stats: self.stats,
+        }
+    }
+}
+
+/// Wait till it is time to reconnect, while holding back the messages of the app
+/// Progress updates are collected in `pending_progress`, other messages cannot be sent
+/// Returns false if the app has been closed
+async fn wait_for_reconnect(
+    app: &Arc<Mutex<types::App>>,
+    ui_message_receiver: &mut tokio::sync::mpsc::Receiver<types::UiMessage>,
+    pending_progress: &mut Option<PendingProgress>,
+    delay: Duration,
+) -> bool {
+    let reconnect_timer = tokio::time::sleep(delay);
+    tokio::pin!(reconnect_timer);
+
+    loop {
+        tokio::select! {
+            _ = &mut reconnect_timer => return true,
+            ui_message = ui_message_receiver.recv() => match ui_message {
+                Some(types::UiMessage::UpdateProgress {
+                    game_id,
+                    keystrokes,
+                    stats,
+                }) => PendingProgress::add(pending_progress, game_id, keystrokes, stats),
+                Some(_) => {
+                    let offline_error =
+                        types::Event::error("Not connected to the server, reconnecting", 1, true);
+                    app.lock().unwrap().add_log_event(offline_error);
+                }
+                None => return false,
+            },
         }
     }
 }
 
 /// Handle the websocket events
 /// No blocking functions should be executed in this function
+///
+/// When the connection is lost, the handler reconnects with an exponential backoff
+/// and presents the resume token, so that the server can restore the same session
 pub async fn event_handler(
     app: Arc<Mutex<types::App>>,
     mut ui_message_receiver: tokio::sync::mpsc::Receiver<types::UiMessage>,
+    url: url::Url,
 ) {
-    // Handle the ui input in a separate tokio task
-    // This is because we do not want the event handler to go down because websocket connection failed
-    // The join handlers can then be polled using the join!() macro
-    let url = url::Url::parse(WS_URL).expect("Unable to parse the url");
-
-    let connect_socket_result = connect_async(url).await;
-    let cloned_app = app.clone();
-    match connect_socket_result {
-        Ok((socket, _response)) => {
-            {
-                let connection_success_log =
-                    types::Event::success("Websocket connection established", 1, false);
-                app.clone()
-                    .lock()
-                    .unwrap()
-                    .add_log_event(connection_success_log);
+    let mut failed_attempts = 0;
+    let mut pending_progress: Option<PendingProgress> = None;
+
+    loop {
+        let connection_url = {
+            let mut unlocked_app = app.lock().unwrap();
+            if failed_attempts == 0 {
+                unlocked_app.connection = types::ConnectionState::Connecting;
             }
 
-            let (mut ws_writer, ws_reader) = socket.split();
-
-            let ws_reader_handler = tokio::spawn(async move {
-                ws_reader
-                    .for_each(|message| async {
-                        let message = message.unwrap();
-                        if let Message::Text(message) = message {
-                            let message =
-                                serde_json::from_str::<server_models::WSServerMessage>(&message)
-                                    .expect("Cannot parse incoming websocket message");
-
-                            handle_incoming_websocket_message(cloned_app.clone(), message)
-                        }
-                    })
-                    .await
-            });
+            let mut connection_url = url.clone();
+            if let Some(resume_token) = unlocked_app.resume_token.as_ref() {
+                connection_url
+                    .query_pairs_mut()
+                    .append_pair("resume", resume_token);
+            }
+            connection_url
+        };
 
-            // If blocking channel ( std::sync::mpsc ) is used, it will block the current thread/task
-            // If a single threaded runtime is used, no progress can be made by other tasks
-            // So, a tokio channel must is used
-
-            // Async code should never spend a long time without reaching an .await
-            // https://ryhl.io/blog/async-what-is-blocking/
-
-            while let Some(ui_message) = ui_message_receiver.recv().await {
-                match ui_message {
-                    types::UiMessage::AcceptChallenge {
-                        user_id: opponent_user_id,
-                    } => {
-                        let websocket_message =
-                            server_models::WSClientMessage::AcceptChallenge { opponent_user_id };
-
-                        let websocket_message_string =
-                            serde_json::to_string(&websocket_message).unwrap();
-
-                        let res = ws_writer
-                            .send(Message::Text(websocket_message_string))
-                            .await
-                            .map_err(|error| {
-                                types::Event::error(
-                                    &format!("Could not send challenge because of error {error:?}"),
-                                    1,
-                                    true,
-                                )
-                            })
-                            .map(|_| types::Event::success("Accepted challenge", 1, true));
-
-                        let event = match res {
-                            Ok(event) => event,
-                            Err(event) => event,
-                        };
-
-                        app.lock().unwrap().add_log_event(event);
-                    }
-                    types::UiMessage::ProgressUpdate(_progress) => {}
-                    types::UiMessage::Challenge { user_name, user_id } => {
-                        let websocket_message = server_models::WSClientMessage::Challenge {
-                            to_user_id: user_id,
-                        };
-
-                        let websocket_message_string =
-                            serde_json::to_string(&websocket_message).unwrap(); // When can this fail?
-
-                        let res = ws_writer
-                            .send(Message::Text(websocket_message_string))
-                            .await
-                            .map_err(|error| {
-                                types::Event::error(
-                                    &format!("Could not send challenge because of error {error:?}"),
-                                    1,
-                                    false,
-                                )
-                            })
-                            .map(|_| {
-                                types::Event::success(
-                                    &format!("Successfully sent the challenge to {user_name}",),
-                                    2,
-                                    false,
-                                )
-                            });
-
-                        let event = match res {
-                            Ok(event) => event,
-                            Err(event) => event,
-                        };
-
-                        app.lock().unwrap().add_log_event(event);
-                    }
-                    types::UiMessage::UpdateProgress { game_id, progress } => {
-                        let websocket_message =
-                            server_models::WSClientMessage::UpdateProgress { game_id, progress };
+        match connect_async(connection_url).await {
+            Ok((socket, _response)) => {
+                failed_attempts = 0;
+                {
+                    let mut unlocked_app = app.lock().unwrap();
+                    unlocked_app.connection = types::ConnectionState::Connected;
+                    let connection_success_log =
+                        types::Event::success("Websocket connection established", 1, false);
+                    unlocked_app.add_log_event(connection_success_log);
+                }
+
+                let (mut ws_writer, mut ws_reader) = socket.split();
+
+                let hello_message = app.lock().unwrap().hello_message();
+                send_websocket_message(&app, &mut ws_writer, hello_message, None).await;
 
-                        let websocket_message_string =
-                            serde_json::to_string(&websocket_message).unwrap();
+                // If blocking channel ( std::sync::mpsc ) is used, it will block the current thread/task
+                // If a single threaded runtime is used, no progress can be made by other tasks
+                // So, a tokio channel must is used
 
-                        ws_writer
-                            .send(Message::Text(websocket_message_string))
-                            .await
-                            .unwrap();
+                // Async code should never spend a long time without reaching an .await
+                // https://ryhl.io/blog/async-what-is-blocking/
+                loop {
+                    tokio::select! {
+                        websocket_message = ws_reader.next() => match websocket_message {
+                            Some(Ok(Message::Text(message))) => {
+                                match server_models::parse_message::<server_models::WSServerMessage>(&message) {
+                                    Ok(message) => {
+                                        let is_connected = matches!(message, server_models::WSServerMessage::SuccessfulConnection { .. });
+                                        handle_incoming_websocket_message(app.clone(), message);
+
+                                        // The session has been resumed, so the server accepts the progress in the game
+                                        if is_connected {
+                                            if let Some(progress) = pending_progress.take() {
+                                                send_ui_message(&app, &mut ws_writer, progress.into_ui_message(), &mut pending_progress).await;
+                                            }
+                                        }
+                                    }
+                                    Err(error) => {
+                                        let error = Error::from(ProtocolError::MalformedMessage(error.to_string()));
+                                        let parse_error = types::Event::error(&error.to_string(), 1, false);
+                                        app.lock().unwrap().add_log_event(parse_error);
+                                    }
+                                }
+                            }
+                            Some(Ok(_)) => {}
+                            Some(Err(_)) | None => break,
+                        },
+                        ui_message = ui_message_receiver.recv() => match ui_message {
+                            Some(ui_message) => send_ui_message(&app, &mut ws_writer, ui_message, &mut pending_progress).await,
+                            // The app has been closed
+                            None => return,
+                        },
                     }
-                };
+                }
+
+                let connection_lost_error =
+                    types::Event::error("Lost the connection to the server", 2, true);
+                app.lock().unwrap().add_log_event(connection_lost_error);
             }
 
-            //Todo: handle this unwrap
-            ws_reader_handler.await.unwrap();
+            Err(socket_connect_error) => {
+                let error = Error::from(TransportError::ConnectFailed(format!(
+                    "{url}: {socket_connect_error}"
+                )));
+                let error_log_event = types::Event::error(&error.to_string(), 1, true);
+                app.lock().unwrap().add_log_event(error_log_event);
+            }
         }
 
-        Err(socket_connect_error) => {
-            let mut app = app.lock().unwrap();
-            let error_log_event = types::Event::error(
-                &format!("Could not create websocket connection {socket_connect_error}"),
-                1,
-                true,
-            );
-            app.add_log_event(error_log_event);
+        let delay = Duration::from_secs(
+            (INITIAL_RECONNECT_DELAY << failed_attempts.min(5)).min(MAXIMUM_RECONNECT_DELAY),
+        );
+        failed_attempts += 1;
+        app.lock().unwrap().connection = types::ConnectionState::Reconnecting {
+            attempt: failed_attempts,
+            retry_at: Instant::now() + delay,
+        };
+
+        if !wait_for_reconnect(&app, &mut ui_message_receiver, &mut pending_progress, delay).await {
+            return;
         }
     }
 }